@@ -0,0 +1,32 @@
+// A host that shows what a program printed next to its result, like a
+// grader, instead of letting it go to its own stdout
+use interpreter::prelude::*;
+
+fn main() {
+    let options = EvalOptions {
+        capture_output: true,
+        ..EvalOptions::default()
+    };
+    let mut interpreter = Interpreter::with_options(LangVersion::Cp4, &options);
+    let program = r#"{"Lambda": [{"Parameters": [{"Identifier": "n"}]}, {"Block": [
+        {"Application": [{"Identifier": "print"}, {"Identifier": "n"}]},
+        {"Application": [{"Identifier": "print"}, "done"]},
+        {"Application": [{"Identifier": "mul"}, {"Identifier": "n"}, 2]}
+    ]}]}"#;
+    let program = format!(r#"{{"Application": [{}, 21]}}"#, program);
+    let result = interpreter.eval_str(&program).expect("program evaluates");
+    assert_eq!(result.to_string(), "42");
+    assert_eq!(interpreter.evaluation().take_output(), "21\ndone\n");
+
+    // Taking the output empties the buffer, and what a failed program
+    // printed before the error is kept too
+    let failing = r#"{"Application": [{"Identifier": "add"},
+        {"Application": [{"Identifier": "print"}, "before"]},
+        {"Application": [{"Identifier": "div"}, 1, 0]}]}"#;
+    let error = interpreter.eval_str(failing).expect_err("division by zero");
+    assert!(matches!(error.kind(), EvalError::DivisionByZero));
+    assert_eq!(interpreter.evaluation().take_output(), "before\n");
+    assert_eq!(interpreter.evaluation().take_output(), "");
+
+    println!("captured output: ok");
+}
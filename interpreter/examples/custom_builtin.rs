@@ -0,0 +1,51 @@
+// A host that gives programs procedures of its own: a host function with
+// `register_fn`, and a registry without the file builtins that adds one
+// with a fixed arity
+use interpreter::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn main() {
+    let mut interpreter = Interpreter::new(LangVersion::Cp4);
+
+    // Host functions can keep state of their own
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&calls);
+    interpreter.register_fn("clamp", move |arguments| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let value = i64::from_argument(arguments, 0)?;
+        let limit = i64::from_argument(arguments, 1)?;
+        Ok(value.min(limit).into())
+    });
+    let result = interpreter
+        .eval_str(r#"{"Application": [{"Identifier": "clamp"}, 250, 100]}"#)
+        .expect("clamp evaluates");
+    assert_eq!(result.to_string(), "100");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // The error a host function returns fails the evaluation
+    let error = interpreter
+        .eval_str(r#"{"Application": [{"Identifier": "clamp"}, "many", 100]}"#)
+        .expect_err("clamp needs numbers");
+    assert!(matches!(error.kind(), EvalError::TypeError(_)));
+
+    // Only the categories the host picks, plus a builtin of its own
+    let mut registry = BuiltinRegistry::empty();
+    registry.add_category(Category::Arith);
+    registry.add_category(Category::Compare);
+    registry.register("square", Arity::Exactly(1), |_, arguments, _| {
+        let number = i64::from_argument(&arguments, 0).unwrap_or_else(|err| panic!("{}", err));
+        (number * number).into()
+    });
+    interpreter.evaluation().set_builtins(registry);
+    let result = interpreter
+        .eval_str(r#"{"Application": [{"Identifier": "add"}, {"Application": [{"Identifier": "square"}, 7]}, 1]}"#)
+        .expect("square evaluates");
+    assert_eq!(result.to_string(), "50");
+    let error = interpreter
+        .eval_str(r#"{"Application": [{"Identifier": "readFile"}, "secret.txt"]}"#)
+        .expect_err("readFile isn't registered");
+    assert!(matches!(error.kind(), EvalError::UnknownProcedure(_)));
+
+    println!("custom builtins: ok");
+}
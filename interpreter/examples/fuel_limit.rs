@@ -0,0 +1,32 @@
+// A host running untrusted programs with a step budget, so one that never
+// ends fails instead of hanging the host
+use interpreter::prelude::*;
+
+fn main() {
+    let options = EvalOptions {
+        max_steps: Some(1_000),
+        ..EvalOptions::default()
+    };
+    let mut interpreter = Interpreter::with_options(LangVersion::Cp4, &options);
+    let forever = r#"{"Letrec": [
+        {"Bindings": [{"Binding": [{"Identifier": "loop"}, {"Lambda": [
+            {"Parameters": [{"Identifier": "n"}]},
+            {"Block": [{"Application": [{"Identifier": "loop"},
+                {"Application": [{"Identifier": "add"}, {"Identifier": "n"}, 1]}]}]}
+        ]}]}]},
+        {"Block": [{"Application": [{"Identifier": "loop"}, 0]}]}
+    ]}"#;
+    let error = interpreter
+        .eval_str(forever)
+        .expect_err("the loop runs out of fuel");
+    assert_eq!(error.kind(), &EvalError::FuelExhausted(1_000));
+    assert_eq!(error.kind().to_string(), "Fuel exhausted after 1000 steps");
+
+    // The next program gets the whole budget again
+    let result = interpreter
+        .eval_str(r#"{"Application": [{"Identifier": "add"}, 1, 2]}"#)
+        .expect("a short program evaluates");
+    assert_eq!(result.to_string(), "3");
+
+    println!("fuel limit: ok");
+}
@@ -0,0 +1,38 @@
+// A host that saves the globals a session defined and picks the session up
+// again in a fresh interpreter, like after a restart
+use interpreter::prelude::*;
+
+fn main() {
+    let mut interpreter = Interpreter::new(LangVersion::Cp4);
+    interpreter.set_global("base", 40.into());
+    let double = interpreter
+        .eval_str(r#"{"Lambda": [{"Parameters": [{"Identifier": "x"}]}, {"Block": [{"Application": [{"Identifier": "mul"}, {"Identifier": "x"}, 2]}]}]}"#)
+        .expect("a lambda evaluates");
+    interpreter.set_global("double", double);
+    let snapshot = interpreter
+        .snapshot()
+        .expect("numbers and closures can be saved");
+    let saved = snapshot.to_string();
+
+    let mut restored = Interpreter::new(LangVersion::Cp4);
+    restored
+        .restore(&serde_json::from_str(&saved).expect("the snapshot is JSON"))
+        .expect("the snapshot restores");
+    assert_eq!(
+        restored.get_global("base").map(|value| value.to_string()),
+        Some("40".to_string())
+    );
+    let result = restored
+        .eval_str(r#"{"Application": [{"Identifier": "double"}, {"Identifier": "base"}]}"#)
+        .expect("the restored closure can be called");
+    assert_eq!(result.to_string(), "80");
+
+    // A document that isn't a snapshot leaves the globals alone
+    let error = restored
+        .restore(&serde_json::json!({"vars": 1}))
+        .expect_err("not a snapshot");
+    assert!(matches!(error, EvalError::Other(_)));
+    assert!(restored.get_global("double").is_some());
+
+    println!("snapshot and restore: ok");
+}
@@ -0,0 +1,57 @@
+#!/bin/bash
+
+# Runs every program in tests/golden and compares what the interpreter
+# prints with the matching .expected file. An .expected file of the form
+# `error: PATTERN` instead requires the program to fail with an error
+# matching the extended regular expression PATTERN. Then checks every property in
+# tests/properties with generated inputs and runs the conformance suites
+# with every engine.
+#
+# Set INTERPRETER to test another build, e.g. one with the mutation hooks.
+interpreter="${INTERPRETER:-cargo run --quiet --}"
+
+# ------------------------------------------------------------------
+
+failed=0
+for program in tests/golden/*.json; do
+    expected="${program%.json}.expected"
+    if [[ "$(head -n 1 "$expected")" == error:* ]]; then
+	pattern=$(sed '1s/^error: *//' "$expected")
+	if errors=$($interpreter < "$program" 2>&1 >/dev/null); then
+	    echo Expected "$program" to fail with: "$pattern"
+	    failed=1
+	elif [[ ! "$errors" =~ $pattern ]]; then
+	    echo Unexpected error for "$program": "$errors"
+	    echo Expected an error matching: "$pattern"
+	    failed=1
+	fi
+	continue
+    fi
+    output=$($interpreter < "$program" 2>/dev/null)
+    if [[ "$output" != "$(cat "$expected")" ]]; then
+	echo Unexpected output for "$program": "$output"
+	echo Expected this output: "$(cat "$expected")"
+	failed=1
+    fi
+done
+
+for property in tests/properties/*.json; do
+    if ! output=$($interpreter check "$property" 2>/dev/null); then
+	echo Property "$property" failed: "$output"
+	failed=1
+    fi
+done
+
+for suite in tests/conformance.json tests/imported/conformance.json; do
+    for engine in evaluator stepper; do
+	if ! output=$($interpreter conformance "$suite" --engine $engine); then
+	    echo "$output"
+	    failed=1
+	fi
+    done
+done
+
+if [[ $failed != 0 ]]; then
+    exit 1
+fi
+echo "All golden tests passed!"
@@ -0,0 +1,38 @@
+#!/bin/bash
+
+# Builds the interpreter with the mutation hooks and runs the golden tests
+# once per mutation. Every mutation changes the semantics, so the tests
+# must fail for each of them; a mutation that survives points at behavior
+# no test pins down.
+
+mutations=(
+    less-is-greater-equal
+    equal-is-unequal
+    zero-is-nonzero
+    cond-last-clause-first
+    arguments-right-to-left
+)
+
+# ------------------------------------------------------------------
+
+cargo build --quiet --features mutations || exit 1
+export INTERPRETER=target/debug/interpreter
+
+if ! ./goldentest.sh > /dev/null; then
+    echo "Golden tests fail without any mutation"
+    exit 1
+fi
+
+survived=0
+for mutation in "${mutations[@]}"; do
+    if INTERPRETER_MUTATION="$mutation" ./goldentest.sh > /dev/null; then
+	echo "Mutation survived: $mutation"
+	survived=1
+    else
+	echo "Mutation killed: $mutation"
+    fi
+done
+
+# Leave a normal build behind
+cargo build --quiet
+exit $survived
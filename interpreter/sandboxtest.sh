@@ -0,0 +1,92 @@
+#!/bin/bash
+
+# Sends every program in tests/sandbox to a server started with the
+# default limits and checks that it is refused with the error in the
+# matching .expected file: the language has no builtins for files, the
+# network, environment variables or processes, and the depth, memory and
+# fuel limits stop runaway programs. The server must still answer
+# afterwards, also while idle connections hold every worker, and refuse
+# bodies over its size limit without reading them. Sessions are only
+# reachable with the tokens the server issued.
+#
+# Set INTERPRETER to test another build, and PORT to use another port.
+interpreter="${INTERPRETER:-cargo run --quiet --}"
+port="${PORT:-7980}"
+
+# ------------------------------------------------------------------
+
+$interpreter serve --port "$port" --io-timeout 1 2>/dev/null &
+server=$!
+trap 'kill $server 2>/dev/null' EXIT
+for _ in $(seq 100); do
+    curl -s -o /dev/null "http://127.0.0.1:$port/" && break
+    sleep 0.1
+done
+
+evaluate() {
+    curl -s -w '\n%{http_code}' -X POST "http://127.0.0.1:$port/eval" \
+	 --data-binary "{\"program\": $1}"
+}
+
+failed=0
+for program in tests/sandbox/*.json; do
+    expected=$(cat "${program%.json}.expected")
+    response=$(evaluate "$(cat "$program")")
+    status=$(tail -n 1 <<< "$response")
+    if [[ "$status" != 422 || "$response" != *"$expected"* ]]; then
+	echo Unexpected response for "$program": "$response"
+	echo Expected an error with: "$expected"
+	failed=1
+    fi
+done
+
+if [[ "$(evaluate '{"Application": [{"Identifier": "add"}, 1, 2]}')" != *'"3"'* ]]; then
+    echo The server stopped answering
+    failed=1
+fi
+
+# A definition stays in the session of the token the server issued, and
+# other tokens name no session
+response=$(evaluate '{"Application": [{"Lambda": [{"Parameters": [{"Identifier": "y"}]}, {"Block": []}]}, 7]}')
+token=$(sed -n 's/.*"token":"\([0-9a-f]*\)".*/\1/p' <<< "$response")
+if [[ -z "$token" ]]; then
+    echo No token issued: "$response"
+    failed=1
+fi
+response=$(curl -s -X POST "http://127.0.0.1:$port/eval" \
+		--data-binary "{\"token\": \"$token\", \"program\": {\"Identifier\": \"y\"}}")
+if [[ "$response" != *'"result":"7"'* ]]; then
+    echo The session of "$token" lost its definition: "$response"
+    failed=1
+fi
+status=$(curl -s -o /dev/null -w '%{http_code}' -X POST "http://127.0.0.1:$port/eval" \
+	      --data-binary '{"token": "abc", "program": 1}')
+if [[ "$status" != 401 ]]; then
+    echo A made-up token got status "$status" instead of 401
+    failed=1
+fi
+
+# The default 4 workers, each waiting for a request that never comes
+idle=()
+for _ in 1 2 3 4; do
+    bash -c "exec 3<>/dev/tcp/127.0.0.1/$port; sleep 5" &
+    idle+=($!)
+done
+sleep 0.2
+if [[ "$(evaluate '{"Application": [{"Identifier": "add"}, 1, 2]}')" != *'"3"'* ]]; then
+    echo The server stopped answering while connections were idle
+    failed=1
+fi
+kill "${idle[@]}" 2>/dev/null
+
+status=$(curl -s -o /dev/null -w '%{http_code}' -X POST "http://127.0.0.1:$port/eval" \
+	      -H 'Content-Length: 99999999999' --data-binary x)
+if [[ "$status" != 413 ]]; then
+    echo An oversized body got status "$status" instead of 413
+    failed=1
+fi
+
+if [[ $failed != 0 ]]; then
+    exit 1
+fi
+echo "All sandbox tests passed!"
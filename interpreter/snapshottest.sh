@@ -0,0 +1,38 @@
+#!/bin/bash
+
+# Runs every program in tests/snapshots (JSON, or s-expressions in .scm
+# files) with the flags in its .args file and compares everything the
+# interpreter prints, errors included, with the matching .snap file. A
+# changed output is written next to it as .snap.new for review; set
+# UPDATE_SNAPSHOTS=1 to accept the changes and to record the snapshots of
+# new programs.
+#
+# Set INTERPRETER to test another build.
+interpreter="${INTERPRETER:-cargo run --quiet --}"
+
+# ------------------------------------------------------------------
+
+failed=0
+for program in tests/snapshots/*.json tests/snapshots/*.scm; do
+    name="${program%.*}"
+    args=$(cat "$name.args" 2>/dev/null)
+    # The location of a panic changes with every edit, so only its message
+    # is kept
+    output=$(RUST_BACKTRACE=0 $interpreter $args < "$program" 2>&1 |
+		 sed -e '/^thread .* panicked at /d' -e '/^note: run with /d')
+    if [[ -n "$UPDATE_SNAPSHOTS" ]]; then
+	echo "$output" > "$name.snap"
+	rm -f "$name.snap.new"
+    elif [[ ! -f "$name.snap" || "$output" != "$(cat "$name.snap")" ]]; then
+	echo "$output" > "$name.snap.new"
+	echo Snapshot of "$program" changed, review with: diff "$name.snap" "$name.snap.new"
+	failed=1
+    else
+	rm -f "$name.snap.new"
+    fi
+done
+
+if [[ $failed != 0 ]]; then
+    exit 1
+fi
+echo "All snapshot tests passed!"
@@ -0,0 +1,831 @@
+use crate::ast::*;
+use crate::env::*;
+use crate::eval::*;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// A lambda lifted to the top level by closure conversion. Its free
+// variables become fields of an explicit environment struct.
+struct LiftedLambda {
+    captures: Vec<String>,
+    parameters: Vec<String>,
+    body: Vec<Expr>,
+}
+
+// A program after closure conversion and defunctionalization: lambdas are
+// numbered top-level functions, creating a closure builds `LambdaN(...)`
+// from the captured values, and every call of a closure goes through one
+// `apply` function that dispatches on the lambda number. In converted code
+// `env.x` reads a captured variable.
+pub(crate) struct ClosureConversion {
+    lambdas: Vec<LiftedLambda>,
+    main: Expr,
+}
+
+impl ClosureConversion {
+    pub(crate) fn new(program: &Expr) -> ClosureConversion {
+        let mut conversion = ClosureConversion {
+            lambdas: Vec::new(),
+            main: Expr::Number(0),
+        };
+        conversion.main = conversion.convert(program, &[], &[]);
+        conversion
+    }
+
+    // Convert an expression inside a lambda with the given parameters and
+    // captured variables; anything else it refers to is global or a builtin
+    fn convert(&mut self, expr: &Expr, parameters: &[String], captures: &[String]) -> Expr {
+        let in_scope = |name: &str| in_scope_of(parameters, name) || in_scope_of(captures, name);
+        match expr {
+            Expr::Identifier(name)
+                if !in_scope_of(parameters, name) && in_scope_of(captures, name) =>
+            {
+                Expr::Identifier(format!("env.{}", name).into())
+            }
+            Expr::Application(items) => {
+                let mut converted: Vec<Expr> = items
+                    .iter()
+                    .map(|item| self.convert(item, parameters, captures))
+                    .collect();
+                match &items[0] {
+                    Expr::Identifier(name) if !in_scope(name) => Expr::Application(converted),
+                    _ => {
+                        converted.insert(0, Expr::Identifier("apply".into()));
+                        Expr::Application(converted)
+                    }
+                }
+            }
+            Expr::Lambda(inner_parameters, body, _) => {
+                let inner_captures: Vec<String> = expr
+                    .free_variables()
+                    .into_iter()
+                    .filter(|name| in_scope(name))
+                    .collect();
+                let index = self.lambdas.len();
+                self.lambdas.push(LiftedLambda {
+                    captures: inner_captures.clone(),
+                    parameters: inner_parameters.clone(),
+                    body: Vec::new(),
+                });
+                let body = body
+                    .iter()
+                    .map(|item| self.convert(item, inner_parameters, &inner_captures))
+                    .collect();
+                self.lambdas[index].body = body;
+                // Build the closure from the captured values
+                let mut closure = vec![Expr::Identifier(format!("Lambda{}", index + 1).into())];
+                for name in &inner_captures {
+                    closure.push(self.convert(
+                        &Expr::Identifier(name.as_str().into()),
+                        parameters,
+                        captures,
+                    ));
+                }
+                Expr::Application(closure)
+            }
+            Expr::Cond(clauses) => Expr::Cond(
+                clauses
+                    .iter()
+                    .map(|(test, result)| {
+                        (
+                            self.convert(test, parameters, captures),
+                            self.convert(result, parameters, captures),
+                        )
+                    })
+                    .collect(),
+            ),
+            // The bound names are local variables, like parameters
+            Expr::Letrec(bindings, _) => {
+                let mut parameters = parameters.to_vec();
+                parameters.extend(bindings.iter().map(|(name, _)| name.clone()));
+                expr.with_children(
+                    expr.children()
+                        .into_iter()
+                        .map(|child| self.convert(child, &parameters, captures))
+                        .collect(),
+                )
+            }
+            Expr::Array(_) | Expr::Dict(_) => expr.with_children(
+                expr.children()
+                    .into_iter()
+                    .map(|child| self.convert(child, parameters, captures))
+                    .collect(),
+            ),
+            _ => expr.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ClosureConversion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, lambda) in self.lambdas.iter().enumerate() {
+            let number = i + 1;
+            if lambda.captures.is_empty() {
+                writeln!(f, "struct Env{} {{}}", number)?;
+            } else {
+                let captures = lambda.captures.join(", ");
+                writeln!(f, "struct Env{} {{ {} }}", number, captures)?;
+            }
+            let mut parameters = vec![format!("env: Env{}", number)];
+            parameters.extend(lambda.parameters.iter().cloned());
+            writeln!(f, "fn lambda{}({}) {{", number, parameters.join(", "))?;
+            for item in &lambda.body {
+                writeln!(f, "    {}", item)?;
+            }
+            writeln!(f, "}}")?;
+            writeln!(f)?;
+        }
+        if !self.lambdas.is_empty() {
+            writeln!(f, "fn apply(closure, arguments...) {{")?;
+            writeln!(f, "    match closure {{")?;
+            for number in 1..=self.lambdas.len() {
+                writeln!(
+                    f,
+                    "        Lambda{}(env) => lambda{}(env, arguments...),",
+                    number, number
+                )?;
+            }
+            writeln!(f, "    }}")?;
+            writeln!(f, "}}")?;
+            writeln!(f)?;
+        }
+        write!(f, "main: {}", self.main)
+    }
+}
+
+// Range of numbers an expression may evaluate to. The ends saturate, so
+// i64::MIN and i64::MAX also stand for "unbounded".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Interval {
+    lo: i64,
+    hi: i64,
+}
+
+impl Interval {
+    const TOP: Interval = Interval {
+        lo: i64::MIN,
+        hi: i64::MAX,
+    };
+
+    fn exactly(number: i64) -> Interval {
+        Interval {
+            lo: number,
+            hi: number,
+        }
+    }
+
+    fn join(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    fn contains(self, number: i64) -> bool {
+        self.lo <= number && number <= self.hi
+    }
+
+    fn add(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.saturating_add(other.lo),
+            hi: self.hi.saturating_add(other.hi),
+        }
+    }
+
+    fn sub(self, other: Interval) -> Interval {
+        Interval {
+            lo: self.lo.saturating_sub(other.hi),
+            hi: self.hi.saturating_sub(other.lo),
+        }
+    }
+
+    fn mul(self, other: Interval) -> Interval {
+        let products = [
+            self.lo.saturating_mul(other.lo),
+            self.lo.saturating_mul(other.hi),
+            self.hi.saturating_mul(other.lo),
+            self.hi.saturating_mul(other.hi),
+        ];
+        Interval {
+            lo: *products.iter().min().unwrap(),
+            hi: *products.iter().max().unwrap(),
+        }
+    }
+
+    // The sign abstraction of the interval
+    pub(crate) fn sign(self) -> &'static str {
+        if self == Interval::exactly(0) {
+            "zero"
+        } else if self.lo > 0 {
+            "positive"
+        } else if self.hi < 0 {
+            "negative"
+        } else if self.lo >= 0 {
+            "non-negative"
+        } else if self.hi <= 0 {
+            "non-positive"
+        } else {
+            "any sign"
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let end = |number: i64| match number {
+            i64::MIN => "-inf".to_string(),
+            i64::MAX => "+inf".to_string(),
+            _ => number.to_string(),
+        };
+        write!(f, "[{}, {}]", end(self.lo), end(self.hi))
+    }
+}
+
+// Something the analysis found out about a program before running it
+pub(crate) struct Finding {
+    // Errors always happen when the node is evaluated, warnings may happen
+    pub(crate) error: bool,
+    path: NodePath,
+    message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = if self.error { "error" } else { "warning" };
+        write!(f, "{} {}: {}", severity, self.path, self.message)
+    }
+}
+
+// Abstract interpretation of a program over intervals. Lambdas applied in
+// place are analyzed with the intervals of their arguments; other lambdas
+// are analyzed once with unknown parameters, and calls through variables
+// give unknown results.
+pub(crate) struct IntervalAnalysis {
+    findings: Vec<Finding>,
+}
+
+impl IntervalAnalysis {
+    pub(crate) fn analyze(program: &Expr, globals: &Env) -> (Interval, Vec<Finding>) {
+        let mut analysis = IntervalAnalysis {
+            findings: Vec::new(),
+        };
+        let scope: Vec<(String, Interval)> = globals
+            .vars()
+            .iter()
+            .map(|(name, value)| match value {
+                ResultValue::Number(number) => (name.to_string(), Interval::exactly(*number)),
+                _ => (name.to_string(), Interval::TOP),
+            })
+            .collect();
+        let result = analysis.expr(program, &NodePath::default(), &scope);
+        (result, analysis.findings)
+    }
+
+    // `scope` holds the variables in scope, innermost last
+    fn expr(&mut self, expr: &Expr, path: &NodePath, scope: &[(String, Interval)]) -> Interval {
+        let lookup = |name: &str| scope.iter().rev().find(|(bound, _)| bound == name);
+        match expr {
+            Expr::Number(number) => Interval::exactly(*number),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => {
+                lookup(name).map_or(Interval::TOP, |(_, value)| *value)
+            }
+            Expr::Application(items) => {
+                let arguments: Vec<Interval> = items
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .map(|(i, item)| self.expr(item, &path.child(i), scope))
+                    .collect();
+                match &items[0] {
+                    Expr::Lambda(parameters, body, _) => {
+                        let mut inner = scope.to_vec();
+                        for (parameter, argument) in parameters.iter().zip(&arguments) {
+                            inner.push((parameter.clone(), *argument));
+                        }
+                        self.body(body, &path.child(0), &inner)
+                    }
+                    Expr::Identifier(name)
+                        if name.starts_with(BUILTIN_PREFIX) || lookup(name).is_none() =>
+                    {
+                        self.builtin(builtin_name(name), &arguments, path)
+                    }
+                    function => {
+                        self.expr(function, &path.child(0), scope);
+                        Interval::TOP
+                    }
+                }
+            }
+            Expr::Lambda(parameters, body, _) => {
+                let mut inner = scope.to_vec();
+                for parameter in parameters {
+                    inner.push((parameter.clone(), Interval::TOP));
+                }
+                self.body(body, path, &inner);
+                Interval::TOP
+            }
+            Expr::Cond(clauses) => {
+                let mut result: Option<Interval> = None;
+                for (i, (test, value)) in clauses.iter().enumerate() {
+                    self.expr(test, &path.child(2 * i), scope);
+                    let value = self.expr(value, &path.child(2 * i + 1), scope);
+                    result = Some(result.map_or(value, |result| result.join(value)));
+                }
+                result.unwrap_or(Interval::TOP)
+            }
+            Expr::Array(_) | Expr::Dict(_) => {
+                for (i, child) in expr.children().into_iter().enumerate() {
+                    self.expr(child, &path.child(i), scope);
+                }
+                Interval::TOP
+            }
+            Expr::Letrec(bindings, body) => {
+                let mut inner = scope.to_vec();
+                for (name, _) in bindings {
+                    inner.push((name.clone(), Interval::TOP));
+                }
+                let mut result = Interval::TOP;
+                for (i, child) in expr.children().into_iter().enumerate() {
+                    result = self.expr(child, &path.child(i), &inner);
+                }
+                if body.is_empty() {
+                    Interval::TOP
+                } else {
+                    result
+                }
+            }
+            Expr::String(_) | Expr::Bool(_) => Interval::TOP,
+        }
+    }
+
+    fn body(&mut self, body: &[Expr], path: &NodePath, scope: &[(String, Interval)]) -> Interval {
+        let mut result = Interval::TOP;
+        for (i, item) in body.iter().enumerate() {
+            result = self.expr(item, &path.child(i), scope);
+        }
+        result
+    }
+
+    fn builtin(&mut self, name: &str, arguments: &[Interval], path: &NodePath) -> Interval {
+        match name {
+            "add" => arguments
+                .iter()
+                .fold(Interval::exactly(0), |sum, argument| sum.add(*argument)),
+            "sub" => match arguments.split_first() {
+                Some((first, rest)) => rest
+                    .iter()
+                    .fold(*first, |difference, argument| difference.sub(*argument)),
+                None => Interval::TOP,
+            },
+            "mul" => arguments
+                .iter()
+                .fold(Interval::exactly(1), |product, argument| {
+                    product.mul(*argument)
+                }),
+            "div" => {
+                for (i, divisor) in arguments.iter().enumerate() {
+                    if *divisor == Interval::exactly(0) {
+                        self.findings.push(Finding {
+                            error: true,
+                            path: path.child(i + 1),
+                            message: "division by zero, the divisor is always 0".to_string(),
+                        });
+                    } else if divisor.contains(0) {
+                        self.findings.push(Finding {
+                            error: false,
+                            path: path.child(i + 1),
+                            message: format!(
+                                "possible division by zero, the divisor is in {}",
+                                divisor
+                            ),
+                        });
+                    }
+                }
+                // Dividing 1 by numbers other than 0 gives -1, 0 or 1
+                Interval { lo: -1, hi: 1 }
+            }
+            _ => Interval::TOP,
+        }
+    }
+}
+
+// Warn about calls whose result depends on the order their parts are
+// evaluated in: one part declares facts or rules with `fact` or `rule` and
+// another part declares or queries too. Evaluation is left to right, but a
+// program shouldn't rely on that between arguments.
+pub(crate) fn order_warnings(program: &Expr) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    program.walk(&mut |_, path, node| {
+        let Expr::Application(items) = node else {
+            return;
+        };
+        let declares: Vec<bool> = items
+            .iter()
+            .map(|item| calls_any(item, &["fact", "rule"]))
+            .collect();
+        let uses: Vec<bool> = items
+            .iter()
+            .map(|item| calls_any(item, &["fact", "rule", "query"]))
+            .collect();
+        let conflict = (0..items.len())
+            .any(|i| declares[i] && (0..items.len()).any(|j| j != i && uses[j]));
+        if conflict {
+            findings.push(Finding {
+                error: false,
+                path: path.clone(),
+                message: format!(
+                    "the result of {} depends on evaluation order, its parts declare and query facts",
+                    node
+                ),
+            });
+        }
+    });
+    findings
+}
+
+// Whether an expression calls one of the builtins `names`
+fn calls_any(expr: &Expr, names: &[&str]) -> bool {
+    if let Expr::Application(items) = expr {
+        if let Some(Expr::Identifier(function)) = items.first() {
+            if names.contains(&function.as_str()) {
+                return true;
+            }
+        }
+    }
+    expr.children()
+        .into_iter()
+        .any(|child| calls_any(child, names))
+}
+
+// Warn about recursive calls that may never terminate. A call is taken to
+// be recursive when a function parameter is applied to itself, as in
+// `f(f, sub(n, 1))`; it is fine when some argument decreases a parameter
+// of the enclosing lambda by a constant, otherwise it is reported.
+pub(crate) fn termination_warnings(program: &Expr) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    find_nonterminating(program, &NodePath::default(), &[], &mut findings);
+    findings
+}
+
+fn find_nonterminating(
+    expr: &Expr,
+    path: &NodePath,
+    parameters: &[String],
+    findings: &mut Vec<Finding>,
+) {
+    if let Expr::Application(items) = expr {
+        if let Some(Expr::Identifier(function)) = items.first() {
+            let recursive = in_scope_of(parameters, function)
+                && items[1..].contains(&Expr::Identifier(*function));
+            if recursive && !items[1..].iter().any(|item| decreases(item, parameters)) {
+                findings.push(Finding {
+                    error: false,
+                    path: path.clone(),
+                    message: format!(
+                        "possible infinite recursion, no argument of {} decreases",
+                        expr
+                    ),
+                });
+            }
+        }
+    }
+    let parameters = match expr {
+        Expr::Lambda(inner, _, _) => inner.as_slice(),
+        _ => parameters,
+    };
+    for (i, child) in expr.children().into_iter().enumerate() {
+        find_nonterminating(child, &path.child(i), parameters, findings);
+    }
+}
+
+// Whether an argument is a parameter minus a positive constant, or divided
+// by a constant larger than one
+fn decreases(argument: &Expr, parameters: &[String]) -> bool {
+    let Expr::Application(items) = argument else {
+        return false;
+    };
+    match items.as_slice() {
+        [Expr::Identifier(operator), Expr::Identifier(name), rest @ ..]
+            if in_scope_of(parameters, name) && !rest.is_empty() =>
+        {
+            let constants: Option<Vec<i64>> = rest.iter().map(as_number).collect();
+            match (operator.as_str(), constants) {
+                ("sub", Some(constants)) => constants.iter().sum::<i64>() > 0,
+                ("add", Some(constants)) => constants.iter().sum::<i64>() < 0,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// Whether a name is one of `names`
+fn in_scope_of(names: &[String], name: &str) -> bool {
+    names.iter().any(|bound| bound == name)
+}
+
+// Something a function may do besides computing its result. The world
+// builtins like `printW` are not effects: they take the world they change
+// as an argument and return the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Effect {
+    Print,
+    Io,
+    Clock,
+    Draw,
+    Mutation,
+}
+
+pub(crate) type Effects = BTreeSet<Effect>;
+
+impl Effect {
+    const ALL: [Effect; 5] = [
+        Effect::Print,
+        Effect::Io,
+        Effect::Clock,
+        Effect::Draw,
+        Effect::Mutation,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Effect::Print => "print",
+            Effect::Io => "io",
+            Effect::Clock => "clock",
+            Effect::Draw => "draw",
+            Effect::Mutation => "mutation",
+        }
+    }
+
+    // The effects of calling a builtin, not counting those of the functions
+    // passed to it
+    fn of_builtin(name: &str) -> &'static [Effect] {
+        match name {
+            "print" => &[Effect::Print],
+            "readBytes" | "writeBytes" => &[Effect::Io],
+            "nowUtc" => &[Effect::Clock],
+            "penDown" | "penUp" | "forward" | "turn" | "circle" => &[Effect::Draw],
+            "fact" | "rule" => &[Effect::Mutation],
+            // The program it evaluates may do anything
+            "evalAst" => &Effect::ALL,
+            _ => &[],
+        }
+    }
+}
+
+// The arguments of builtins that must be pure functions under
+// `--check-purity`: map, filter and iterate call theirs whenever the
+// iterator they make is forced, and contracts check theirs around every
+// call, so effects there happen at times the program doesn't say
+const PURE_ARGUMENTS: [(&str, &[usize]); 4] = [
+    ("map", &[0]),
+    ("filter", &[0]),
+    ("iterate", &[1, 2]),
+    ("contract", &[0, 1]),
+];
+
+// Effect inference. Every expression has the effects evaluating it has,
+// and the effects calling the functions it may evaluate to would have.
+// A call has the effects of its parts and of the function called, and
+// also those of the functions passed to it, since they may be called in
+// turn; a parameter called in the body of a lambda adds nothing of its
+// own. Letrec bindings are analyzed until their effects stop growing.
+struct EffectAnalysis {
+    // Where names no lambda or letrec of the analyzed code binds are looked
+    // up: the globals of a program, or the environment of a closure
+    env: Option<Env>,
+    // Bodies of the closures being analyzed, to stop at recursion
+    visiting: Vec<*const Expr>,
+    findings: Vec<Finding>,
+}
+
+impl EffectAnalysis {
+    // The effects of evaluating an expression and of calling what it
+    // evaluates to. `scope` holds the effects of the functions the
+    // variables in scope are bound to, innermost last.
+    fn expr(
+        &mut self,
+        expr: &Expr,
+        path: &NodePath,
+        scope: &[(String, Effects)],
+    ) -> (Effects, Effects) {
+        match expr {
+            Expr::Number(_) | Expr::String(_) | Expr::Bool(_) => (Effects::new(), Effects::new()),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => {
+                match scope.iter().rev().find(|(bound, _)| bound == name) {
+                    Some((_, latent)) => (Effects::new(), latent.clone()),
+                    None => (Effects::new(), self.global(name)),
+                }
+            }
+            Expr::Lambda(parameters, body, _) => {
+                let mut inner = scope.to_vec();
+                for parameter in parameters {
+                    inner.push((parameter.clone(), Effects::new()));
+                }
+                let (effects, _) = self.body(body, path, &inner);
+                (Effects::new(), effects)
+            }
+            Expr::Application(items) => {
+                let mut effects = Effects::new();
+                let mut arguments = Vec::new();
+                for (i, item) in items.iter().enumerate().skip(1) {
+                    let (performed, latent) = self.expr(item, &path.child(i), scope);
+                    effects.extend(performed);
+                    arguments.push(latent);
+                }
+                for latent in &arguments {
+                    effects.extend(latent.iter().copied());
+                }
+                match &items[0] {
+                    Expr::Lambda(parameters, body, _) => {
+                        let mut inner = scope.to_vec();
+                        for (parameter, latent) in parameters.iter().zip(&arguments) {
+                            inner.push((parameter.clone(), latent.clone()));
+                        }
+                        let (performed, latent) = self.body(body, &path.child(0), &inner);
+                        effects.extend(performed);
+                        (effects, latent)
+                    }
+                    Expr::Identifier(name) if self.is_builtin(name, scope) => {
+                        let name = builtin_name(name);
+                        effects.extend(Effect::of_builtin(name));
+                        self.check_pure_arguments(name, &arguments, path);
+                        (effects, Effects::new())
+                    }
+                    function => {
+                        let (performed, latent) = self.expr(function, &path.child(0), scope);
+                        effects.extend(performed);
+                        effects.extend(latent);
+                        (effects, Effects::new())
+                    }
+                }
+            }
+            // Any clause may be the one taken
+            Expr::Cond(_) | Expr::Array(_) | Expr::Dict(_) => {
+                let mut effects = Effects::new();
+                let mut latent = Effects::new();
+                for (i, child) in expr.children().into_iter().enumerate() {
+                    let (performed, value) = self.expr(child, &path.child(i), scope);
+                    effects.extend(performed);
+                    if !matches!(expr, Expr::Cond(_)) || i % 2 == 1 {
+                        latent.extend(value);
+                    }
+                }
+                (effects, latent)
+            }
+            Expr::Letrec(bindings, _) => {
+                let mut inner = scope.to_vec();
+                for (name, _) in bindings {
+                    inner.push((name.clone(), Effects::new()));
+                }
+                let first = scope.len();
+                let findings = self.findings.len();
+                loop {
+                    // Only the findings of the last round count
+                    self.findings.truncate(findings);
+                    let mut grew = false;
+                    for (i, (_, value)) in bindings.iter().enumerate() {
+                        let (_, latent) = self.expr(value, &path.child(i), &inner);
+                        if latent != inner[first + i].1 {
+                            inner[first + i].1 = latent;
+                            grew = true;
+                        }
+                    }
+                    if !grew {
+                        break;
+                    }
+                }
+                let mut effects = Effects::new();
+                let mut latent = Effects::new();
+                for (i, child) in expr.children().into_iter().enumerate().skip(bindings.len()) {
+                    let (performed, value) = self.expr(child, &path.child(i), &inner);
+                    effects.extend(performed);
+                    latent = value;
+                }
+                (effects, latent)
+            }
+        }
+    }
+
+    fn body(
+        &mut self,
+        body: &[Expr],
+        path: &NodePath,
+        scope: &[(String, Effects)],
+    ) -> (Effects, Effects) {
+        let mut effects = Effects::new();
+        let mut latent = Effects::new();
+        for (i, item) in body.iter().enumerate() {
+            let (performed, value) = self.expr(item, &path.child(i), scope);
+            effects.extend(performed);
+            latent = value;
+        }
+        (effects, latent)
+    }
+
+    // Whether a name called as a function is a builtin, because nothing
+    // binds it
+    fn is_builtin(&self, name: &str, scope: &[(String, Effects)]) -> bool {
+        if name.starts_with(BUILTIN_PREFIX) {
+            return true;
+        }
+        let bound = scope.iter().any(|(bound, _)| bound == name)
+            || self.env.as_ref().is_some_and(|env| env.get(name).is_some());
+        !bound
+    }
+
+    // The effects of calling the value a name has in the environment
+    fn global(&mut self, name: &str) -> Effects {
+        match self.env.as_ref().and_then(|env| env.get(name)) {
+            Some(value) => self.value(&value),
+            None => Effects::new(),
+        }
+    }
+
+    // The effects of calling a function value, or the functions a
+    // collection holds
+    fn value(&mut self, value: &ResultValue) -> Effects {
+        match value {
+            ResultValue::Lambda(parameters, body, env, _) => {
+                let key = body.as_ptr();
+                if self.visiting.contains(&key) {
+                    return Effects::new();
+                }
+                self.visiting.push(key);
+                // Like a call, see `Env::tie`
+                let outer = self.env.replace(env.clone().tie());
+                let scope: Vec<(String, Effects)> = parameters
+                    .iter()
+                    .map(|parameter| (parameter.clone(), Effects::new()))
+                    .collect();
+                // What is wrong inside the closure is reported where it is
+                // written, not where it is called
+                let findings = self.findings.len();
+                let (effects, _) = self.body(body, &NodePath::default(), &scope);
+                self.findings.truncate(findings);
+                self.env = outer;
+                self.visiting.pop();
+                effects
+            }
+            ResultValue::Contract(parts) => {
+                let (pre, post, function) = parts.as_ref();
+                let mut effects = self.value(pre);
+                effects.extend(self.value(post));
+                effects.extend(self.value(function));
+                effects
+            }
+            ResultValue::Array(elements) | ResultValue::Set(elements) => elements
+                .iter()
+                .flat_map(|element| self.value(element))
+                .collect(),
+            ResultValue::Dict(entries) => entries
+                .iter()
+                .flat_map(|(_, value)| self.value(value))
+                .collect(),
+            _ => Effects::new(),
+        }
+    }
+
+    fn check_pure_arguments(&mut self, name: &str, arguments: &[Effects], path: &NodePath) {
+        let Some((_, indices)) = PURE_ARGUMENTS.iter().find(|(builtin, _)| *builtin == name) else {
+            return;
+        };
+        for &i in indices.iter() {
+            let Some(effects) = arguments.get(i).filter(|effects| !effects.is_empty()) else {
+                continue;
+            };
+            let names: Vec<&str> = effects.iter().map(|effect| effect.name()).collect();
+            self.findings.push(Finding {
+                error: true,
+                path: path.child(i + 1),
+                message: format!(
+                    "{} needs a pure function, but this one has effects: {}",
+                    name,
+                    names.join(", ")
+                ),
+            });
+        }
+    }
+}
+
+// The effects calling a function may have, with the names its body doesn't
+// bind looked up in its closure
+pub(crate) fn function_effects(function: &ResultValue) -> Effects {
+    let mut analysis = EffectAnalysis {
+        env: None,
+        visiting: Vec::new(),
+        findings: Vec::new(),
+    };
+    analysis.value(function)
+}
+
+// Find the functions with effects that a program passes where only pure
+// functions are allowed (see PURE_ARGUMENTS)
+pub(crate) fn purity_errors(program: &Expr, globals: &Env) -> Vec<Finding> {
+    let mut analysis = EffectAnalysis {
+        env: Some(globals.clone()),
+        visiting: Vec::new(),
+        findings: Vec::new(),
+    };
+    analysis.expr(program, &NodePath::default(), &[]);
+    analysis.findings
+}
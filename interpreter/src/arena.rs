@@ -0,0 +1,274 @@
+use crate::ast::{Expr, Site};
+use crate::symbol::Symbol;
+use serde_json::Value;
+
+// The program as a flat table of nodes instead of a tree: every node lives
+// in one `ExprArena` and refers to its children by `ExprId`, so a pass can
+// hold on to nodes, or share them, without cloning subtrees. Children and
+// names are runs of the arena's `children` and `names` tables, so a node
+// owns no allocations of its own apart from string literals.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExprArena {
+    nodes: Vec<Node>,
+    children: Vec<ExprId>,
+    names: Vec<String>,
+}
+
+// A node of an arena, only meaningful for the arena that made it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+// A run of the `children` or `names` table of an arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Run {
+    start: u32,
+    len: u32,
+}
+
+impl Run {
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.start as usize..(self.start + self.len) as usize
+    }
+}
+
+// The variants of `Expr`, with runs in place of the nested vectors
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Number(i64),
+    String(String),
+    Bool(bool),
+    Identifier(Symbol),
+    Local(Symbol, usize, usize),
+    // The function, then the arguments
+    Application(Run),
+    // The parameter names, and the expressions of the body block
+    Lambda(Run, Run),
+    // The tests and results of the clauses, alternating
+    Cond(Run),
+    Array(Run),
+    // The keys, and the values in the same order
+    Dict(Run, Run),
+    // The bound names; the values of the bindings in the same order,
+    // followed by the expressions of the body block
+    Letrec(Run, Run),
+}
+
+impl ExprArena {
+    pub fn new() -> ExprArena {
+        ExprArena::default()
+    }
+
+    // Read a program from its JSON form, like `Expr::from_json`, into a
+    // new arena, returning the arena and the root of the program
+    pub fn from_json(json: &Value) -> Result<(ExprArena, ExprId), String> {
+        let expr = Expr::from_json(json)?;
+        let mut arena = ExprArena::new();
+        let root = arena.alloc(&expr);
+        Ok((arena, root))
+    }
+
+    // Copy a tree into the arena, children before their parents, and
+    // return the id of its root
+    pub fn alloc(&mut self, expr: &Expr) -> ExprId {
+        let node = match expr {
+            Expr::Number(number) => Node::Number(*number),
+            Expr::String(string) => Node::String(string.clone()),
+            Expr::Bool(bool) => Node::Bool(*bool),
+            Expr::Identifier(name) => Node::Identifier(*name),
+            Expr::Local(name, depth, slot) => Node::Local(*name, *depth, *slot),
+            Expr::Application(items) => Node::Application(self.alloc_all(items)),
+            Expr::Lambda(parameters, body, _) => {
+                Node::Lambda(self.push_names(parameters.iter()), self.alloc_all(body))
+            }
+            Expr::Cond(clauses) => {
+                let children: Vec<ExprId> = clauses
+                    .iter()
+                    .flat_map(|(test, result)| [test, result])
+                    .map(|child| self.alloc(child))
+                    .collect();
+                Node::Cond(self.push_children(children))
+            }
+            Expr::Array(elements) => Node::Array(self.alloc_all(elements)),
+            Expr::Dict(entries) => {
+                let values: Vec<ExprId> =
+                    entries.iter().map(|(_, value)| self.alloc(value)).collect();
+                let keys = self.push_names(entries.iter().map(|(key, _)| key));
+                Node::Dict(keys, self.push_children(values))
+            }
+            Expr::Letrec(bindings, body) => {
+                let children: Vec<ExprId> = bindings
+                    .iter()
+                    .map(|(_, value)| value)
+                    .chain(body)
+                    .map(|child| self.alloc(child))
+                    .collect();
+                let names = self.push_names(bindings.iter().map(|(name, _)| name));
+                Node::Letrec(names, self.push_children(children))
+            }
+        };
+        let id = ExprId(u32::try_from(self.nodes.len()).expect("Too many nodes"));
+        self.nodes.push(node);
+        id
+    }
+
+    fn alloc_all(&mut self, exprs: &[Expr]) -> Run {
+        let ids: Vec<ExprId> = exprs.iter().map(|expr| self.alloc(expr)).collect();
+        self.push_children(ids)
+    }
+
+    // The children of a node are allocated before the node itself, and
+    // allocating them pushes their own runs, so a node's run is only
+    // pushed once all of its children exist
+    fn push_children(&mut self, ids: Vec<ExprId>) -> Run {
+        let start = self.children.len();
+        self.children.extend(ids);
+        run(start, self.children.len())
+    }
+
+    fn push_names<'a>(&mut self, names: impl Iterator<Item = &'a String>) -> Run {
+        let start = self.names.len();
+        self.names.extend(names.cloned());
+        run(start, self.names.len())
+    }
+
+    // The tree under a node, the inverse of `alloc`
+    pub fn to_expr(&self, id: ExprId) -> Expr {
+        let exprs = |run: &Run| -> Vec<Expr> {
+            self.run(*run)
+                .iter()
+                .map(|&child| self.to_expr(child))
+                .collect()
+        };
+        match self.node(id) {
+            Node::Number(number) => Expr::Number(*number),
+            Node::String(string) => Expr::String(string.clone()),
+            Node::Bool(bool) => Expr::Bool(*bool),
+            Node::Identifier(name) => Expr::Identifier(*name),
+            Node::Local(name, depth, slot) => Expr::Local(*name, *depth, *slot),
+            Node::Application(items) => Expr::Application(exprs(items)),
+            Node::Lambda(parameters, body) => Expr::Lambda(
+                self.names(*parameters).to_vec(),
+                exprs(body).into(),
+                Site::default(),
+            ),
+            Node::Cond(clauses) => Expr::Cond(
+                exprs(clauses)
+                    .chunks(2)
+                    .map(|clause| (clause[0].clone(), clause[1].clone()))
+                    .collect(),
+            ),
+            Node::Array(elements) => Expr::Array(exprs(elements)),
+            Node::Dict(keys, values) => Expr::Dict(
+                self.names(*keys)
+                    .iter()
+                    .cloned()
+                    .zip(exprs(values))
+                    .collect(),
+            ),
+            Node::Letrec(names, children) => {
+                let mut values = exprs(children);
+                let body = values.split_off(names.len());
+                Expr::Letrec(
+                    self.names(*names).iter().cloned().zip(values).collect(),
+                    body,
+                )
+            }
+        }
+    }
+
+    pub fn node(&self, id: ExprId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub fn run(&self, run: Run) -> &[ExprId] {
+        &self.children[run.range()]
+    }
+
+    pub fn names(&self, run: Run) -> &[String] {
+        &self.names[run.range()]
+    }
+
+    // The number of nodes in the arena
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // The direct sub-expressions of a node, in the order of `Expr::children`
+    pub fn children(&self, id: ExprId) -> &[ExprId] {
+        match self.node(id) {
+            Node::Number(_)
+            | Node::String(_)
+            | Node::Bool(_)
+            | Node::Identifier(_)
+            | Node::Local(_, _, _) => &[],
+            Node::Application(children)
+            | Node::Lambda(_, children)
+            | Node::Cond(children)
+            | Node::Array(children)
+            | Node::Dict(_, children)
+            | Node::Letrec(_, children) => self.run(*children),
+        }
+    }
+
+    // The names a node binds for its children, as in `Expr::binds`
+    pub fn binds(&self, id: ExprId) -> &[String] {
+        match self.node(id) {
+            Node::Lambda(names, _) | Node::Letrec(names, _) => self.names(*names),
+            _ => &[],
+        }
+    }
+
+    // Every node under a node, itself included, parents before children
+    pub fn walk(&self, id: ExprId) -> Vec<ExprId> {
+        let mut order = Vec::new();
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            order.push(id);
+            pending.extend(self.children(id).iter().rev());
+        }
+        order
+    }
+
+    // Identifiers used under a node without a lambda or letrec binding
+    // them, in order of first use, like `Expr::free_variables`
+    pub fn free_variables(&self, id: ExprId) -> Vec<String> {
+        let mut free = Vec::new();
+        self.collect_free(id, &mut Vec::new(), &mut free);
+        free
+    }
+
+    fn collect_free<'a>(&'a self, id: ExprId, scope: &mut Vec<&'a str>, free: &mut Vec<String>) {
+        if let Node::Identifier(name) | Node::Local(name, _, _) = self.node(id) {
+            if !scope.contains(&name.as_str()) && !free.iter().any(|free| free == name) {
+                free.push(name.to_string());
+            }
+        }
+        let depth = scope.len();
+        scope.extend(self.binds(id).iter().map(String::as_str));
+        for &child in self.children(id) {
+            self.collect_free(child, scope, free);
+        }
+        scope.truncate(depth);
+    }
+}
+
+fn run(start: usize, end: usize) -> Run {
+    let start = u32::try_from(start).expect("Too many nodes");
+    let end = u32::try_from(end).expect("Too many nodes");
+    Run {
+        start,
+        len: end - start,
+    }
+}
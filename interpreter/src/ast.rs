@@ -0,0 +1,784 @@
+use crate::pretty;
+pub use crate::symbol::Symbol;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+// Language versions, selected with `--lang-version`. Every new piece of
+// semantics is gated behind a version so older course material keeps
+// evaluating exactly as it did when it was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LangVersion {
+    // Checkpoint 3 semantics, the default
+    Cp3,
+    // Checkpoint 4: calls returning booleans as tests, block scoping and
+    // letrec
+    Cp4,
+}
+
+impl LangVersion {
+    pub fn parse(name: &str) -> Result<LangVersion, String> {
+        match name {
+            "cp3" => Ok(LangVersion::Cp3),
+            "cp4" => Ok(LangVersion::Cp4),
+            _ => Err(format!(
+                "Unknown language version: {} (expected cp3 or cp4)",
+                name
+            )),
+        }
+    }
+
+    // Any call that returns a boolean can be a cond test, not only the
+    // comparisons and `zero?`
+    pub(crate) fn boolean_calls(self) -> bool {
+        self >= LangVersion::Cp4
+    }
+
+    // Blocks evaluate every expression and open their own scope
+    pub(crate) fn block_scoping(self) -> bool {
+        self >= LangVersion::Cp4
+    }
+
+    // `letrec` binds functions that can call themselves and each other
+    pub(crate) fn letrec(self) -> bool {
+        self >= LangVersion::Cp4
+    }
+
+    // Arithmetic on the unit value is a type error. In checkpoint 3 an
+    // unbound name evaluated to no value, which arithmetic took as the
+    // smallest number, and it still does.
+    pub(crate) fn unit_arithmetic_fails(self) -> bool {
+        self >= LangVersion::Cp4
+    }
+}
+
+// A program, as loaded from the parser's JSON output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    String(String),
+    // `true` or `false`, written as a JSON boolean
+    Bool(bool),
+    Identifier(Symbol),
+    // An identifier bound by an enclosing lambda or letrec, as rewritten
+    // by the resolver: the name, the number of frames out from the current
+    // one to the binder's frame, and the slot of the name in that frame
+    Local(Symbol, usize, usize),
+    // The first element is the function, the rest are the arguments
+    Application(Vec<Expr>),
+    // Parameter names, the expressions of the body block, which closures
+    // share with the program, and where the lambda is in the program
+    Lambda(Vec<String>, Arc<[Expr]>, Site),
+    // (test, result) clauses, tried in order
+    Cond(Vec<(Expr, Expr)>),
+    // `[a, b]`: an array of the values of the elements
+    Array(Vec<Expr>),
+    // `{"k": v}`: a dict of the values of the entries
+    Dict(Vec<(String, Expr)>),
+    // Names bound to lambdas that can refer to each other and themselves,
+    // and the expressions of the body block
+    Letrec(Vec<(String, Expr)>, Vec<Expr>),
+}
+
+// The location of a node: the child indices to follow from the root
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct NodePath(pub(crate) Vec<usize>);
+
+// The path of a lambda in the program it is part of, attached by the
+// resolver (see `resolver.rs`) for the closures it makes to carry. Lambdas
+// are the same wherever they are, so sites are all equal to each other.
+#[derive(Debug, Clone, Default)]
+pub struct Site(pub(crate) Option<NodePath>);
+
+impl PartialEq for Site {
+    fn eq(&self, _: &Site) -> bool {
+        true
+    }
+}
+
+// Where a node is written in the source: the lines and columns, counted
+// from 1, of its first char and of the char after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+// The spans of the nodes of a program, by path, for front ends that know
+// them. Nodes keep no positions themselves, so programs built in code or
+// read from JSON simply have none.
+#[derive(Debug, Default)]
+pub(crate) struct SourceMap(pub(crate) HashMap<NodePath, Span>);
+
+impl SourceMap {
+    pub(crate) fn span(&self, path: &NodePath) -> Option<Span> {
+        self.0.get(path).copied()
+    }
+}
+
+// The position of a node in a pre-order walk of the program. Ids only
+// depend on the shape of the program, so they are stable across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct NodeId(pub(crate) usize);
+
+impl NodePath {
+    // Parse a path written as `/1/0`; `/` is the root
+    pub(crate) fn parse(text: &str) -> Result<NodePath, String> {
+        let mut indices = Vec::new();
+        for part in text.split('/').filter(|part| !part.is_empty()) {
+            let index = part
+                .parse()
+                .map_err(|_| format!("Invalid node path: {}", text))?;
+            indices.push(index);
+        }
+        Ok(NodePath(indices))
+    }
+
+    pub(crate) fn child(&self, index: usize) -> NodePath {
+        let mut indices = self.0.clone();
+        indices.push(index);
+        NodePath(indices)
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// Print an expression in the parser's surface syntax
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(number) => write!(f, "{}", number),
+            Expr::String(string) => write!(f, "{:?}", string),
+            Expr::Bool(value) => write!(f, "{}", value),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => write!(f, "{}", name),
+            Expr::Application(items) => {
+                match items.first() {
+                    Some(function @ Expr::Lambda(_, _, _)) => write!(f, "{}(", function)?,
+                    Some(function) => write!(f, "{}(", function)?,
+                    None => write!(f, "(")?,
+                }
+                for (i, item) in items.iter().skip(1).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Lambda(parameters, body, _) => {
+                write!(f, "λ({}) {{ ", parameters.join(", "))?;
+                for (i, item) in body.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, " }}")
+            }
+            Expr::Cond(clauses) => {
+                write!(f, "cond")?;
+                for (test, result) in clauses {
+                    write!(f, " ({} => {})", test, result)?;
+                }
+                Ok(())
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Expr::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Expr::Dict(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{:?}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Expr::Letrec(bindings, body) => {
+                let bindings: Vec<String> = bindings
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                let body: Vec<String> = body.iter().map(Expr::to_string).collect();
+                write!(
+                    f,
+                    "letrec {} {{ {} }}",
+                    bindings.join(", "),
+                    body.join("; ")
+                )
+            }
+        }
+    }
+}
+
+impl Expr {
+    // A document that prints like `Display` when it fits the width, and
+    // breaks arguments, lambda bodies and cond clauses over indented lines
+    // otherwise
+    pub(crate) fn to_doc(&self) -> pretty::Doc {
+        use pretty::Doc;
+        match self {
+            Expr::Application(items) if items.len() > 1 => {
+                let arguments = items[1..].iter().map(Expr::to_doc).collect();
+                let separator = Doc::Concat(vec![Doc::text(","), Doc::line()]);
+                Doc::group(Doc::Concat(vec![
+                    items[0].to_doc(),
+                    Doc::text("("),
+                    Doc::nest(
+                        4,
+                        Doc::Concat(vec![Doc::softline(), Doc::join(arguments, separator)]),
+                    ),
+                    Doc::softline(),
+                    Doc::text(")"),
+                ]))
+            }
+            Expr::Lambda(parameters, body, _) => {
+                let body = body.iter().map(Expr::to_doc).collect();
+                let separator = Doc::Concat(vec![Doc::text(";"), Doc::line()]);
+                Doc::group(Doc::Concat(vec![
+                    Doc::text(&format!("λ({}) {{", parameters.join(", "))),
+                    Doc::nest(
+                        4,
+                        Doc::Concat(vec![Doc::line(), Doc::join(body, separator)]),
+                    ),
+                    Doc::line(),
+                    Doc::text("}"),
+                ]))
+            }
+            Expr::Cond(clauses) => {
+                let clauses = clauses.iter().map(|(test, result)| {
+                    Doc::Concat(vec![
+                        Doc::line(),
+                        Doc::group(Doc::Concat(vec![
+                            Doc::text("("),
+                            test.to_doc(),
+                            Doc::text(" =>"),
+                            Doc::nest(4, Doc::Concat(vec![Doc::line(), result.to_doc()])),
+                            Doc::text(")"),
+                        ])),
+                    ])
+                });
+                Doc::group(Doc::Concat(vec![
+                    Doc::text("cond"),
+                    Doc::nest(4, Doc::Concat(clauses.collect())),
+                ]))
+            }
+            _ => Doc::text(&self.to_string()),
+        }
+    }
+
+    // Convert the parser's JSON output into an expression tree
+    pub fn from_json(json: &Value) -> Result<Expr, String> {
+        if let Some(number) = json.as_i64() {
+            return Ok(Expr::Number(number));
+        }
+        if let Some(string) = json.as_str() {
+            return Ok(Expr::String(string.to_string()));
+        }
+        if let Some(value) = json.as_bool() {
+            return Ok(Expr::Bool(value));
+        }
+        if let Some(identifier) = json.get("Identifier").and_then(|id| id.as_str()) {
+            return Ok(Expr::Identifier(identifier.into()));
+        }
+        if let Some(items) = json.get("Application").and_then(|a| a.as_array()) {
+            let items = items
+                .iter()
+                .map(Expr::from_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Application(items));
+        }
+        if let Some(lambda) = json.get("Lambda") {
+            let mut parameters = Vec::new();
+            if let Some(list) = lambda
+                .get(0)
+                .and_then(|p| p.get("Parameters"))
+                .and_then(|p| p.as_array())
+            {
+                for parameter in list {
+                    match parameter.get("Identifier").and_then(|id| id.as_str()) {
+                        Some(name) => parameters.push(name.to_string()),
+                        None => return Err(format!("Invalid parameter: {}", parameter)),
+                    }
+                }
+            }
+            let block = lambda
+                .get(1)
+                .and_then(|b| b.get("Block"))
+                .and_then(|b| b.as_array())
+                .ok_or_else(|| format!("Lambda expression has no block: {}", lambda))?;
+            let body = block
+                .iter()
+                .map(Expr::from_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Lambda(parameters, body.into(), Site::default()));
+        }
+        if let Some(clauses) = json.get("Cond").and_then(|c| c.as_array()) {
+            let mut pairs = Vec::new();
+            for clause in clauses {
+                match clause.get("Clause").and_then(|c| c.as_array()) {
+                    Some(parts) if parts.len() == 2 => {
+                        pairs.push((Expr::from_json(&parts[0])?, Expr::from_json(&parts[1])?))
+                    }
+                    _ => return Err(format!("Invalid cond clause: {}", clause)),
+                }
+            }
+            return Ok(Expr::Cond(pairs));
+        }
+        if let Some(elements) = json.get("Array").and_then(|a| a.as_array()) {
+            let elements = elements
+                .iter()
+                .map(Expr::from_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Array(elements));
+        }
+        if let Some(entries) = json.get("Dict").and_then(|d| d.as_array()) {
+            let mut pairs = Vec::new();
+            for entry in entries {
+                match entry
+                    .get("Entry")
+                    .and_then(|e| e.as_array())
+                    .map(Vec::as_slice)
+                {
+                    Some([Value::String(key), value]) => {
+                        pairs.push((key.clone(), Expr::from_json(value)?))
+                    }
+                    _ => return Err(format!("Invalid dict entry: {}", entry)),
+                }
+            }
+            return Ok(Expr::Dict(pairs));
+        }
+        if let Some(letrec) = json.get("Letrec") {
+            let mut bindings = Vec::new();
+            let list = letrec
+                .get(0)
+                .and_then(|b| b.get("Bindings"))
+                .and_then(|b| b.as_array())
+                .ok_or_else(|| format!("Letrec expression has no bindings: {}", letrec))?;
+            for binding in list {
+                match binding
+                    .get("Binding")
+                    .and_then(|b| b.as_array())
+                    .map(Vec::as_slice)
+                {
+                    Some([name, value]) => {
+                        match name.get("Identifier").and_then(|id| id.as_str()) {
+                            Some(name) => {
+                                bindings.push((name.to_string(), Expr::from_json(value)?))
+                            }
+                            None => return Err(format!("Invalid letrec binding: {}", binding)),
+                        }
+                    }
+                    _ => return Err(format!("Invalid letrec binding: {}", binding)),
+                }
+            }
+            let block = letrec
+                .get(1)
+                .and_then(|b| b.get("Block"))
+                .and_then(|b| b.as_array())
+                .ok_or_else(|| format!("Letrec expression has no block: {}", letrec))?;
+            let body = block
+                .iter()
+                .map(Expr::from_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Expr::Letrec(bindings, body));
+        }
+        Err(format!("Unknown expression: {}", json))
+    }
+
+    // Convert back into the parser's JSON format
+    pub fn to_json(&self) -> Value {
+        match self {
+            Expr::Number(number) => Value::from(*number),
+            Expr::String(string) => Value::from(string.as_str()),
+            Expr::Bool(value) => Value::from(*value),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => {
+                serde_json::json!({ "Identifier": name })
+            }
+            Expr::Application(items) => {
+                let items: Vec<Value> = items.iter().map(Expr::to_json).collect();
+                serde_json::json!({ "Application": items })
+            }
+            Expr::Lambda(parameters, body, _) => {
+                let parameters: Vec<Value> = parameters
+                    .iter()
+                    .map(|name| serde_json::json!({ "Identifier": name }))
+                    .collect();
+                let body: Vec<Value> = body.iter().map(Expr::to_json).collect();
+                serde_json::json!({
+                    "Lambda": [{ "Parameters": parameters }, { "Block": body }]
+                })
+            }
+            Expr::Cond(clauses) => {
+                let clauses: Vec<Value> = clauses
+                    .iter()
+                    .map(|(test, result)| {
+                        serde_json::json!({ "Clause": [test.to_json(), result.to_json()] })
+                    })
+                    .collect();
+                serde_json::json!({ "Cond": clauses })
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<Value> = elements.iter().map(Expr::to_json).collect();
+                serde_json::json!({ "Array": elements })
+            }
+            Expr::Dict(entries) => {
+                let entries: Vec<Value> = entries
+                    .iter()
+                    .map(|(key, value)| serde_json::json!({ "Entry": [key, value.to_json()] }))
+                    .collect();
+                serde_json::json!({ "Dict": entries })
+            }
+            Expr::Letrec(bindings, body) => {
+                let bindings: Vec<Value> = bindings
+                    .iter()
+                    .map(|(name, value)| {
+                        serde_json::json!({ "Binding": [{ "Identifier": name }, value.to_json()] })
+                    })
+                    .collect();
+                let body: Vec<Value> = body.iter().map(Expr::to_json).collect();
+                serde_json::json!({
+                    "Letrec": [{ "Bindings": bindings }, { "Block": body }]
+                })
+            }
+        }
+    }
+
+    // The direct sub-expressions, in the order their indices appear in paths
+    pub(crate) fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Number(_)
+            | Expr::String(_)
+            | Expr::Bool(_)
+            | Expr::Identifier(_)
+            | Expr::Local(_, _, _) => Vec::new(),
+            Expr::Application(items) => items.iter().collect(),
+            Expr::Lambda(_, body, _) => body.iter().collect(),
+            Expr::Cond(clauses) => clauses
+                .iter()
+                .flat_map(|(test, result)| [test, result])
+                .collect(),
+            Expr::Array(elements) => elements.iter().collect(),
+            Expr::Dict(entries) => entries.iter().map(|(_, value)| value).collect(),
+            Expr::Letrec(bindings, body) => bindings
+                .iter()
+                .map(|(_, value)| value)
+                .chain(body)
+                .collect(),
+        }
+    }
+
+    // The node with its children replaced, given in the order of `children`
+    pub(crate) fn with_children(&self, children: Vec<Expr>) -> Expr {
+        match self {
+            Expr::Number(_)
+            | Expr::String(_)
+            | Expr::Bool(_)
+            | Expr::Identifier(_)
+            | Expr::Local(_, _, _) => self.clone(),
+            Expr::Application(_) => Expr::Application(children),
+            Expr::Lambda(parameters, _, site) => {
+                Expr::Lambda(parameters.clone(), children.into(), site.clone())
+            }
+            Expr::Cond(_) => Expr::Cond(
+                children
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect(),
+            ),
+            Expr::Array(_) => Expr::Array(children),
+            Expr::Dict(entries) => Expr::Dict(
+                entries
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .zip(children)
+                    .collect(),
+            ),
+            Expr::Letrec(bindings, _) => {
+                let mut children = children;
+                let body = children.split_off(bindings.len());
+                Expr::Letrec(
+                    bindings
+                        .iter()
+                        .map(|(name, _)| name.clone())
+                        .zip(children)
+                        .collect(),
+                    body,
+                )
+            }
+        }
+    }
+
+    // Look up the node at a path, if the path exists in this program
+    pub(crate) fn node_at(&self, path: &NodePath) -> Option<&Expr> {
+        let mut node = self;
+        for &index in &path.0 {
+            node = *node.children().get(index)?;
+        }
+        Some(node)
+    }
+
+    // Visit every node in pre-order with its id and path
+    pub(crate) fn walk<'a>(&'a self, visit: &mut impl FnMut(NodeId, &NodePath, &'a Expr)) {
+        let mut next_id = 0;
+        self.walk_from(&NodePath::default(), &mut next_id, visit);
+    }
+
+    pub(crate) fn walk_from<'a>(
+        &'a self,
+        path: &NodePath,
+        next_id: &mut usize,
+        visit: &mut impl FnMut(NodeId, &NodePath, &'a Expr),
+    ) {
+        visit(NodeId(*next_id), path, self);
+        *next_id += 1;
+        for (index, child) in self.children().into_iter().enumerate() {
+            child.walk_from(&path.child(index), next_id, visit);
+        }
+    }
+
+    // Short description of a node, without its children
+    pub(crate) fn label(&self) -> String {
+        match self {
+            Expr::Number(number) => number.to_string(),
+            Expr::String(string) => format!("{:?}", string),
+            Expr::Bool(value) => value.to_string(),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => name.to_string(),
+            Expr::Application(items) => format!("Application ({} items)", items.len()),
+            Expr::Lambda(parameters, _, _) => format!("Lambda ({})", parameters.join(", ")),
+            Expr::Cond(clauses) => format!("Cond ({} clauses)", clauses.len()),
+            Expr::Array(elements) => format!("Array ({} elements)", elements.len()),
+            Expr::Dict(entries) => format!("Dict ({} entries)", entries.len()),
+            Expr::Letrec(bindings, _) => {
+                let names: Vec<&str> = bindings.iter().map(|(name, _)| name.as_str()).collect();
+                format!("Letrec ({})", names.join(", "))
+            }
+        }
+    }
+}
+
+// 64-bit FNV-1a, used instead of `DefaultHasher` so hashes are the same on
+// every platform and compiler version
+pub(crate) struct Fnv64(pub(crate) u64);
+
+impl Fnv64 {
+    pub(crate) fn new() -> Fnv64 {
+        Fnv64(0xcbf29ce484222325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+impl Expr {
+    // Hash of the shape of a program. Parameter names do not matter, so two
+    // programs that only differ by renaming bound variables hash the same.
+    pub(crate) fn structural_hash(&self) -> u64 {
+        self.structural_hash_in(&mut Vec::new())
+    }
+
+    // `scope` holds the parameters bound around this node, innermost last
+    pub(crate) fn structural_hash_in<'a>(&'a self, scope: &mut Vec<&'a str>) -> u64 {
+        let mut hasher = Fnv64::new();
+        match self {
+            Expr::Number(number) => {
+                hasher.write(b"N");
+                hasher.write(&number.to_le_bytes());
+            }
+            Expr::String(string) => {
+                hasher.write(b"S");
+                hasher.write(string.as_bytes());
+            }
+            Expr::Bool(value) => {
+                hasher.write(b"T");
+                hasher.write(&[*value as u8]);
+            }
+            Expr::Identifier(name) | Expr::Local(name, _, _) => {
+                match scope.iter().rev().position(|bound| bound == name) {
+                    // Bound variables are hashed by how far away their binder is
+                    Some(distance) => {
+                        hasher.write(b"B");
+                        hasher.write_u64(distance as u64);
+                    }
+                    None => {
+                        hasher.write(b"F");
+                        hasher.write(name.as_bytes());
+                    }
+                }
+            }
+            Expr::Application(items) => {
+                hasher.write(b"A");
+                for item in items {
+                    hasher.write_u64(item.structural_hash_in(scope));
+                }
+            }
+            Expr::Lambda(parameters, body, _) => {
+                hasher.write(b"L");
+                hasher.write_u64(parameters.len() as u64);
+                let depth = scope.len();
+                scope.extend(parameters.iter().map(|name| name.as_str()));
+                for item in body.iter() {
+                    hasher.write_u64(item.structural_hash_in(scope));
+                }
+                scope.truncate(depth);
+            }
+            Expr::Cond(clauses) => {
+                hasher.write(b"C");
+                for (test, result) in clauses {
+                    hasher.write_u64(test.structural_hash_in(scope));
+                    hasher.write_u64(result.structural_hash_in(scope));
+                }
+            }
+            Expr::Array(elements) => {
+                hasher.write(b"R");
+                for element in elements {
+                    hasher.write_u64(element.structural_hash_in(scope));
+                }
+            }
+            Expr::Dict(entries) => {
+                hasher.write(b"D");
+                for (key, value) in entries {
+                    hasher.write_u64(key.len() as u64);
+                    hasher.write(key.as_bytes());
+                    hasher.write_u64(value.structural_hash_in(scope));
+                }
+            }
+            Expr::Letrec(bindings, body) => {
+                hasher.write(b"E");
+                hasher.write_u64(bindings.len() as u64);
+                let depth = scope.len();
+                scope.extend(bindings.iter().map(|(name, _)| name.as_str()));
+                for item in bindings.iter().map(|(_, value)| value).chain(body) {
+                    hasher.write_u64(item.structural_hash_in(scope));
+                }
+                scope.truncate(depth);
+            }
+        }
+        hasher.0
+    }
+
+    // Names this node binds for its children
+    pub(crate) fn binds(&self) -> Vec<&str> {
+        match self {
+            Expr::Lambda(parameters, _, _) => parameters.iter().map(String::as_str).collect(),
+            Expr::Letrec(bindings, _) => bindings.iter().map(|(name, _)| name.as_str()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Expr {
+    // Name of the variant, as used in the JSON format
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Expr::Number(_) => "Number",
+            Expr::String(_) => "String",
+            Expr::Bool(_) => "Bool",
+            Expr::Identifier(_) | Expr::Local(_, _, _) => "Identifier",
+            Expr::Application(_) => "Application",
+            Expr::Lambda(_, _, _) => "Lambda",
+            Expr::Cond(_) => "Cond",
+            Expr::Array(_) => "Array",
+            Expr::Dict(_) => "Dict",
+            Expr::Letrec(_, _) => "Letrec",
+        }
+    }
+
+    // Identifiers used somewhere in the program without a lambda binding
+    // them, in order of first use
+    pub(crate) fn free_variables(&self) -> Vec<String> {
+        let mut free = Vec::new();
+        self.collect_free(&mut Vec::new(), &mut free);
+        free
+    }
+
+    fn collect_free<'a>(&'a self, scope: &mut Vec<&'a str>, free: &mut Vec<String>) {
+        if let Expr::Identifier(name) | Expr::Local(name, _, _) = self {
+            if !scope.contains(&name.as_str()) && !free.iter().any(|free| free == name) {
+                free.push(name.to_string());
+            }
+        }
+        let depth = scope.len();
+        scope.extend(self.binds());
+        for child in self.children() {
+            child.collect_free(scope, free);
+        }
+        scope.truncate(depth);
+    }
+
+    // Length of the longest path from this node down to a leaf
+    pub(crate) fn depth(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(Expr::depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Approximate number of bytes the tree occupies in memory
+    pub(crate) fn estimated_size(&self) -> usize {
+        let own = match self {
+            Expr::Number(_) | Expr::Bool(_) => 0,
+            Expr::String(string) => string.len(),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => name.len(),
+            Expr::Application(items) => items.len() * std::mem::size_of::<Expr>(),
+            Expr::Lambda(parameters, body, _) => {
+                parameters.iter().map(|name| name.len()).sum::<usize>()
+                    + parameters.len() * std::mem::size_of::<String>()
+                    + body.len() * std::mem::size_of::<Expr>()
+            }
+            Expr::Cond(clauses) => clauses.len() * std::mem::size_of::<(Expr, Expr)>(),
+            Expr::Array(elements) => elements.len() * std::mem::size_of::<Expr>(),
+            Expr::Dict(entries) => entries
+                .iter()
+                .map(|(key, _)| key.len() + std::mem::size_of::<(String, Expr)>())
+                .sum(),
+            Expr::Letrec(bindings, body) => {
+                bindings
+                    .iter()
+                    .map(|(name, _)| name.len() + std::mem::size_of::<(String, Expr)>())
+                    .sum::<usize>()
+                    + body.len() * std::mem::size_of::<Expr>()
+            }
+        };
+        let children: usize = self
+            .children()
+            .into_iter()
+            .map(|child| child.estimated_size() - std::mem::size_of::<Expr>())
+            .sum();
+        std::mem::size_of::<Expr>() + own + children
+    }
+}
@@ -0,0 +1,161 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("add", Arity::AtLeast(0)),
+        ("sub", Arity::AtLeast(1)),
+        ("mul", Arity::AtLeast(0)),
+        ("div", Arity::AtLeast(0)),
+        ("formatFloat", Arity::Between(1, 2)),
+        ("round", Arity::Exactly(2)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The arithmetic builtins: add sums up its arguments, sub subtracts the
+// others from the first one, mul multiplies them and div divides 1 by each
+// of them in turn. formatFloat(x, digits) writes x with that many digits
+// after the decimal point, or with the `--precision` setting when digits
+// are left out, and round(x, digits) rounds.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("formatFloat", [ResultValue::Number(number)]) => {
+            return ResultValue::String(format_fixed(*number, eval.precision));
+        }
+        ("formatFloat", [ResultValue::Number(number), ResultValue::Number(digits)])
+            if *digits >= 0 =>
+        {
+            return ResultValue::String(format_fixed(*number, *digits as usize));
+        }
+        // Rust formats floats the same way on every platform
+        ("formatFloat", [ResultValue::Float(float)]) => {
+            return ResultValue::String(format!("{:.*}", eval.precision, float));
+        }
+        ("formatFloat", [ResultValue::Float(float), ResultValue::Number(digits)])
+            if *digits >= 0 =>
+        {
+            return ResultValue::String(format!("{:.*}", *digits as usize, float));
+        }
+        ("formatFloat", _) => fail(EvalError::TypeError(
+            "formatFloat expects a number and a count of digits".to_string(),
+        )),
+        ("round", [ResultValue::Number(number), ResultValue::Number(digits)]) => {
+            return ResultValue::Number(round_to(*number, *digits).unwrap_or_else(|| {
+                fail(EvalError::Overflow(format!(
+                    "round({}, {})",
+                    number, digits
+                )))
+            }));
+        }
+        ("round", [ResultValue::Float(float), ResultValue::Number(digits)]) => {
+            let unit = 10f64.powi(i32::try_from(*digits).unwrap_or(i32::MAX));
+            return ResultValue::Float((float * unit).round() / unit);
+        }
+        ("round", _) => fail(EvalError::TypeError(
+            "round expects a number and a count of digits".to_string(),
+        )),
+        _ => {}
+    }
+    let arguments: Vec<ResultValue> = if eval.version.unit_arithmetic_fails() {
+        arguments
+    } else {
+        arguments
+            .into_iter()
+            .map(|argument| match argument {
+                ResultValue::Unit => ResultValue::Number(i64::MIN),
+                argument => argument,
+            })
+            .collect()
+    };
+    for argument in &arguments {
+        if !matches!(argument, ResultValue::Number(_) | ResultValue::Float(_)) {
+            fail(EvalError::TypeError(format!(
+                "{} expects numbers but got {}",
+                name, argument
+            )));
+        }
+    }
+    let (first, rest) = match name {
+        "add" => (ResultValue::Number(0), &arguments[..]),
+        "sub" => (arguments[0].clone(), &arguments[1..]),
+        _ => (ResultValue::Number(1), &arguments[..]),
+    };
+    rest.iter().fold(first, |result, argument| {
+        arithmetic(name, &result, argument, eval.overflow)
+    })
+}
+
+// One step of add, sub, mul or div. Every arithmetic builtin goes through
+// here, so a result that doesn't fit in a number is handled the same way
+// by all of them, as `--overflow` says. The result is computed exactly,
+// which two numbers always can be in an i128, and then made to fit.
+// Arithmetic with a float gives a float, which never overflows.
+fn arithmetic(
+    name: &str,
+    left: &ResultValue,
+    right: &ResultValue,
+    overflow: Overflow,
+) -> ResultValue {
+    if name == "div" && matches!(right, ResultValue::Number(0)) {
+        fail(EvalError::DivisionByZero);
+    }
+    let (ResultValue::Number(a), ResultValue::Number(b)) = (left, right) else {
+        let (a, b) = (left.as_float(), right.as_float());
+        if name == "div" && b == 0.0 {
+            fail(EvalError::DivisionByZero);
+        }
+        return ResultValue::Float(match name {
+            "add" => a + b,
+            "sub" => a - b,
+            "mul" => a * b,
+            _ => a / b,
+        });
+    };
+    let (a, b) = (i128::from(*a), i128::from(*b));
+    let exact = match name {
+        "add" => a + b,
+        "sub" => a - b,
+        "mul" => a * b,
+        _ => a / b,
+    };
+    if let Ok(number) = i64::try_from(exact) {
+        return ResultValue::Number(number);
+    }
+    match overflow {
+        Overflow::Error => fail(EvalError::Overflow(format!("{}({}, {})", name, a, b))),
+        // The low 64 bits, as two's complement arithmetic gives them
+        Overflow::Wrap => ResultValue::Number(exact as i64),
+        Overflow::Saturate => {
+            ResultValue::Number(exact.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64)
+        }
+        Overflow::PromoteFloat => ResultValue::Float(exact as f64),
+    }
+}
+
+// The number with a fixed number of digits after the decimal point. Numbers
+// are integers, so the digits are all zero, but the text is the same on
+// every platform.
+fn format_fixed(number: i64, digits: usize) -> String {
+    if digits == 0 {
+        return number.to_string();
+    }
+    format!("{}.{}", number, "0".repeat(digits))
+}
+
+// The number rounded to `digits` digits after the decimal point, half away
+// from zero. Negative digits round to tens, hundreds, ...
+fn round_to(number: i64, digits: i64) -> Option<i64> {
+    if digits >= 0 {
+        return Some(number);
+    }
+    let unit = 10i64.checked_pow(u32::try_from(-digits).ok()?)?;
+    let down = number / unit * unit;
+    let rest = number - down;
+    if rest.unsigned_abs() * 2 >= unit.unsigned_abs() {
+        down.checked_add(rest.signum() * unit)
+    } else {
+        Some(down)
+    }
+}
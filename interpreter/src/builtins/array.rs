@@ -0,0 +1,164 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("array", Arity::AtLeast(0)),
+        ("append", Arity::Exactly(2)),
+        ("get", Arity::Exactly(2)),
+        ("getOr", Arity::Exactly(3)),
+        ("set", Arity::Exactly(3)),
+        ("remove", Arity::Exactly(2)),
+        ("slice", Arity::Between(2, 3)),
+        ("isHomogeneous", Arity::Exactly(1)),
+        ("binarySearch", Arity::Exactly(2)),
+        ("sortedInsert", Arity::Exactly(2)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The position of an index in a sequence of `len` elements, where negative
+// indices count from the end: -1 is the last element
+pub(super) fn position(index: i64, len: usize) -> Result<usize, EvalError> {
+    let from_end = || len.checked_sub(usize::try_from(index.unsigned_abs()).ok()?);
+    let position = if index < 0 {
+        from_end()
+    } else {
+        usize::try_from(index).ok()
+    };
+    position
+        .filter(|position| *position < len)
+        .ok_or(EvalError::IndexOutOfBounds { index, len })
+}
+
+// The range `start..end` of a slice of a sequence of `len` elements.
+// Negative bounds count from the end, and bounds outside the sequence are
+// clamped to it, so a slice never fails.
+fn slice_range(start: i64, end: i64, len: usize) -> std::ops::Range<usize> {
+    let clamp = |bound: i64| {
+        let bound = if bound < 0 {
+            bound.saturating_add(len as i64)
+        } else {
+            bound
+        };
+        bound.clamp(0, len as i64) as usize
+    };
+    let (start, end) = (clamp(start), clamp(end));
+    start..end.max(start)
+}
+
+// The array builtins: array(...) makes an array of its arguments, get(a, i)
+// is the element at index `i`, append(a, v), set(a, i, v) and remove(a, i)
+// return a copy of `a` with `v` added at the end, put at index `i` or with
+// the element at `i` left out (append shares the elements of `a` instead of
+// copying them when it can, see `Elements::push`), slice(a, start, end) is the elements from
+// `start` up to `end` (or to the end of `a`), and isHomogeneous(a) tells
+// whether the elements are all of the same kind. Elements can be any
+// values, and negative indices count from the end. binarySearch(a, v) and
+// sortedInsert(a, v) work on arrays of numbers in ascending order and only
+// look at O(log n) elements.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    let at = |index: i64, len: usize| position(index, len).unwrap_or_else(|err| fail(err));
+    let number = |value: &ResultValue| match value {
+        ResultValue::Number(number) => *number,
+        _ => fail(EvalError::TypeError(format!(
+            "{} expects a sorted array of numbers but got {}",
+            name,
+            value.described()
+        ))),
+    };
+    match (name, arguments.as_slice()) {
+        ("array", _) => ResultValue::Array(arguments.into()),
+        ("append", [ResultValue::Array(values), value]) => {
+            ResultValue::Array(values.push(value.clone()))
+        }
+        ("append", _) => fail(EvalError::TypeError(
+            "append expects an array and a value".to_string(),
+        )),
+        ("get", [ResultValue::Array(values), ResultValue::Number(index)]) => {
+            values[at(*index, values.len())].clone()
+        }
+        ("get", _) => fail(EvalError::TypeError(
+            "get expects an array and an index".to_string(),
+        )),
+        ("getOr", [ResultValue::Array(values), ResultValue::Number(index), default]) => {
+            match position(*index, values.len()) {
+                Ok(index) => values[index].clone(),
+                Err(_) => default.clone(),
+            }
+        }
+        ("getOr", [ResultValue::Dict(entries), ResultValue::String(key), default]) => entries
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map_or_else(|| default.clone(), |(_, value)| value.clone()),
+        ("getOr", _) => fail(EvalError::TypeError(
+            "getOr expects an array and an index or a dict and a key, and a default".to_string(),
+        )),
+        ("set", [ResultValue::Array(values), ResultValue::Number(index), value]) => {
+            let mut values = values.to_vec();
+            let index = at(*index, values.len());
+            values[index] = value.clone();
+            ResultValue::Array(values.into())
+        }
+        ("set", _) => fail(EvalError::TypeError(
+            "set expects an array, an index and a value".to_string(),
+        )),
+        ("remove", [ResultValue::Array(values), ResultValue::Number(index)]) => {
+            let mut values = values.to_vec();
+            values.remove(at(*index, values.len()));
+            ResultValue::Array(values.into())
+        }
+        ("remove", _) => fail(EvalError::TypeError(
+            "remove expects an array and an index".to_string(),
+        )),
+        ("slice", [ResultValue::Array(values), ResultValue::Number(start), rest @ ..]) => {
+            let end = match rest {
+                [] => values.len() as i64,
+                [ResultValue::Number(end)] => *end,
+                _ => fail(EvalError::TypeError(
+                    "slice expects an array, a start and an optional end".to_string(),
+                )),
+            };
+            ResultValue::Array(
+                values[slice_range(*start, end, values.len())]
+                    .to_vec()
+                    .into(),
+            )
+        }
+        ("slice", _) => fail(EvalError::TypeError(
+            "slice expects an array, a start and an optional end".to_string(),
+        )),
+        ("isHomogeneous", [ResultValue::Array(values)]) => {
+            let homogeneous = values
+                .windows(2)
+                .all(|pair| pair[0].kind() == pair[1].kind());
+            ResultValue::Number(homogeneous as i64)
+        }
+        ("isHomogeneous", _) => fail(EvalError::TypeError(
+            "isHomogeneous expects an array".to_string(),
+        )),
+        ("binarySearch", [ResultValue::Array(values), ResultValue::Number(wanted)]) => {
+            // The first of equal elements, or the unit value
+            let index = values.partition_point(|value| number(value) < *wanted);
+            match values.get(index) {
+                Some(value) if number(value) == *wanted => ResultValue::Number(index as i64),
+                _ => ResultValue::Unit,
+            }
+        }
+        ("binarySearch", _) => fail(EvalError::TypeError(
+            "binarySearch expects a sorted array and a number".to_string(),
+        )),
+        ("sortedInsert", [ResultValue::Array(values), ResultValue::Number(value)]) => {
+            // After any equal elements
+            let index = values.partition_point(|existing| number(existing) <= *value);
+            let mut values = values.to_vec();
+            values.insert(index, ResultValue::Number(*value));
+            ResultValue::Array(values.into())
+        }
+        ("sortedInsert", _) => fail(EvalError::TypeError(
+            "sortedInsert expects a sorted array and a number".to_string(),
+        )),
+        _ => unreachable!("Not an array builtin: {}", name),
+    }
+}
@@ -0,0 +1,73 @@
+use super::array::position;
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("bytesFromString", Arity::Exactly(1)),
+        ("byteAt", Arity::Exactly(2)),
+        ("bytesLen", Arity::Exactly(1)),
+        ("hexEncode", Arity::Exactly(1)),
+        ("hexDecode", Arity::Exactly(1)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The bytes as lowercase hexadecimal digits
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The binary data builtins: bytesFromString(s) is the UTF-8 encoding of s,
+// byteAt(b, i) and bytesLen(b) look into data, and hexEncode(b) and
+// hexDecode(s) convert to and from hexadecimal. Reading and writing files
+// of bytes are I/O builtins.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("bytesFromString", [ResultValue::String(text)]) => {
+            ResultValue::Bytes(text.as_bytes().to_vec())
+        }
+        ("bytesFromString", _) => fail(EvalError::TypeError(
+            "bytesFromString expects a string".to_string(),
+        )),
+        ("byteAt", [ResultValue::Bytes(bytes), ResultValue::Number(index)]) => {
+            let index = position(*index, bytes.len()).unwrap_or_else(|err| fail(err));
+            ResultValue::Number(bytes[index] as i64)
+        }
+        ("byteAt", _) => fail(EvalError::TypeError(
+            "byteAt expects bytes and an index".to_string(),
+        )),
+        ("bytesLen", [ResultValue::Bytes(bytes)]) => ResultValue::Number(bytes.len() as i64),
+        ("bytesLen", _) => fail(EvalError::TypeError("bytesLen expects bytes".to_string())),
+        ("hexEncode", [ResultValue::Bytes(bytes)]) => ResultValue::String(hex_encode(bytes)),
+        ("hexEncode", _) => fail(EvalError::TypeError("hexEncode expects bytes".to_string())),
+        ("hexDecode", [ResultValue::String(hex)]) => {
+            let digits: Vec<u32> = hex
+                .chars()
+                .map(|digit| {
+                    digit.to_digit(16).unwrap_or_else(|| {
+                        fail(EvalError::TypeError(format!(
+                            "Not a hexadecimal digit: {}",
+                            digit
+                        )))
+                    })
+                })
+                .collect();
+            if !digits.len().is_multiple_of(2) {
+                fail(EvalError::TypeError(
+                    "hexDecode expects an even number of digits".to_string(),
+                ));
+            }
+            let bytes = digits
+                .chunks(2)
+                .map(|pair| (pair[0] * 16 + pair[1]) as u8)
+                .collect();
+            ResultValue::Bytes(bytes)
+        }
+        ("hexDecode", _) => fail(EvalError::TypeError(
+            "hexDecode expects a string".to_string(),
+        )),
+        _ => unreachable!("Not a bytes builtin: {}", name),
+    }
+}
@@ -0,0 +1,47 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use std::cmp::Ordering;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("zero?", Arity::Exactly(1)),
+        ("=", Arity::Exactly(2)),
+        ("<", Arity::Exactly(2)),
+        ("<=", Arity::Exactly(2)),
+        (">", Arity::Exactly(2)),
+        (">=", Arity::Exactly(2)),
+        ("true", Arity::Exactly(0)),
+        ("false", Arity::Exactly(0)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The comparisons as procedures that return a boolean. A cond test that
+// calls one is decided by `evaluate_bool` without calling it, so these only
+// run when a comparison is called anywhere else, like in an argument or
+// the value of a definition. Numbers, strings, dates, bytes and arrays can
+// be compared.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    ResultValue::Bool(match (name, arguments.as_slice()) {
+        ("true", []) => true,
+        ("false", []) => false,
+        ("zero?", [ResultValue::Number(number)]) => *number == 0,
+        ("zero?", [ResultValue::Float(float)]) => *float == 0.0,
+        ("zero?", [value]) => fail(EvalError::TypeError(format!(
+            "Expected a number but got {}",
+            value
+        ))),
+        (_, [left, right]) => {
+            let ordering = left.compare(right).unwrap_or_else(|err| fail(err));
+            match name {
+                "=" => ordering == Ordering::Equal,
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }
+        }
+        _ => unreachable!("{} called with the wrong number of arguments", name),
+    })
+}
@@ -0,0 +1,43 @@
+use super::{Arity, BuiltinRegistry};
+use crate::date::DateTime;
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for name in ["parseDate", "formatDate", "addDays"] {
+        registry.register(name, Arity::Exactly(2), apply);
+    }
+}
+
+// The date builtins: parseDate(s, format) and formatDate(d, format) convert
+// to and from text, and addDays(d, n) moves a date by whole days. Reading
+// the clock is an I/O builtin.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("parseDate", [ResultValue::String(text), ResultValue::String(format)]) => {
+            ResultValue::DateTime(
+                DateTime::parse(text, format).unwrap_or_else(|err| fail(EvalError::Other(err))),
+            )
+        }
+        ("parseDate", _) => fail(EvalError::TypeError(
+            "parseDate expects a string and a format".to_string(),
+        )),
+        ("formatDate", [ResultValue::DateTime(date), ResultValue::String(format)]) => {
+            ResultValue::String(
+                date.format(format)
+                    .unwrap_or_else(|err| fail(EvalError::Other(err))),
+            )
+        }
+        ("formatDate", _) => fail(EvalError::TypeError(
+            "formatDate expects a date and a format".to_string(),
+        )),
+        ("addDays", [ResultValue::DateTime(date), ResultValue::Number(days)]) => {
+            ResultValue::DateTime(
+                date.add_days(*days)
+                    .unwrap_or_else(|err| fail(EvalError::Other(err))),
+            )
+        }
+        _ => fail(EvalError::TypeError(
+            "addDays expects a date and a number".to_string(),
+        )),
+    }
+}
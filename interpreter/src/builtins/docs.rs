@@ -0,0 +1,59 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::pretty::Doc;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("text", Arity::Exactly(1)),
+        ("line", Arity::Exactly(0)),
+        ("nest", Arity::Exactly(2)),
+        ("group", Arity::Exactly(1)),
+        ("concat", Arity::AtLeast(0)),
+        ("render", Arity::Exactly(2)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The pretty-printing builtins: text(s), line(), nest(indent, doc),
+// group(doc) and concat(doc, ...) build documents, and render(doc, width)
+// lays one out as a string.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    let doc = match (name, arguments.as_slice()) {
+        ("text", [ResultValue::String(text)]) => Doc::text(text),
+        ("text", _) => fail(EvalError::TypeError("text expects one string".to_string())),
+        ("line", []) => Doc::line(),
+        ("line", _) => fail(EvalError::TypeError(
+            "line expects no arguments".to_string(),
+        )),
+        ("nest", [ResultValue::Number(indent), ResultValue::Doc(doc)]) if *indent >= 0 => {
+            Doc::nest(*indent as usize, doc.clone())
+        }
+        ("nest", _) => fail(EvalError::TypeError(
+            "nest expects an indentation and a document".to_string(),
+        )),
+        ("group", [ResultValue::Doc(doc)]) => Doc::group(doc.clone()),
+        ("group", _) => fail(EvalError::TypeError(
+            "group expects one document".to_string(),
+        )),
+        ("concat", _) => Doc::Concat(
+            arguments
+                .iter()
+                .map(|argument| match argument {
+                    ResultValue::Doc(doc) => doc.clone(),
+                    value => fail(EvalError::TypeError(format!(
+                        "concat expects documents but got {}",
+                        value
+                    ))),
+                })
+                .collect(),
+        ),
+        ("render", [ResultValue::Doc(doc), ResultValue::Number(width)]) if *width >= 0 => {
+            return ResultValue::String(doc.render(*width as usize));
+        }
+        _ => fail(EvalError::TypeError(
+            "render expects a document and a width".to_string(),
+        )),
+    };
+    ResultValue::Doc(doc)
+}
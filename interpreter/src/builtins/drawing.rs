@@ -0,0 +1,34 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::turtle;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("penDown", Arity::Exactly(0)),
+        ("penUp", Arity::Exactly(0)),
+        ("forward", Arity::Exactly(1)),
+        ("turn", Arity::Exactly(1)),
+        ("circle", Arity::Exactly(1)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The turtle graphics builtins, which need the draw capability: penDown()
+// and penUp() lower and raise the pen, forward(n) moves the turtle n units
+// ahead, turn(degrees) turns it left, and circle(r) draws a circle around
+// it. They return the unit value; the drawing is written as SVG when the
+// program ends.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    eval.require(Capability::Draw, name);
+    let turtle = eval.turtle.get_or_insert_with(turtle::Turtle::default);
+    match (name, arguments.as_slice()) {
+        ("penDown", []) => turtle.pen(true),
+        ("penUp", []) => turtle.pen(false),
+        ("forward", [ResultValue::Number(distance)]) => turtle.forward(*distance as f64),
+        ("turn", [ResultValue::Number(degrees)]) => turtle.turn(*degrees as f64),
+        ("circle", [ResultValue::Number(radius)]) => turtle.circle(*radius as f64),
+        _ => fail(EvalError::TypeError(format!("{} expects a number", name))),
+    }
+    ResultValue::Unit
+}
@@ -0,0 +1,132 @@
+use super::sequence::to_iter;
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::graph::Graph;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("graphFromEdges", Arity::Exactly(1)),
+        ("neighbors", Arity::Exactly(2)),
+        ("bfs", Arity::Exactly(2)),
+        ("dfs", Arity::Exactly(2)),
+        ("topoSort", Arity::Exactly(1)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The name of a graph node, which is a number or a string. A string that
+// is a number's name is the same node as the number.
+fn node_name(name: &str, node: &ResultValue) -> String {
+    match node {
+        ResultValue::Number(number) => number.to_string(),
+        ResultValue::String(text) => text.clone(),
+        _ => fail(EvalError::TypeError(format!(
+            "{} expects nodes that are numbers or strings but got {}",
+            name,
+            node.described()
+        ))),
+    }
+}
+
+fn node_value(node: String) -> ResultValue {
+    match node.parse::<i64>() {
+        Ok(number) if number.to_string() == node => ResultValue::Number(number),
+        _ => ResultValue::String(node),
+    }
+}
+
+fn nodes_value(nodes: Vec<String>) -> ResultValue {
+    ResultValue::Array(nodes.into_iter().map(node_value).collect())
+}
+
+// A graph written as a dict from the name of every node to the array of
+// its neighbors
+fn to_graph(name: &str, value: &ResultValue) -> Graph {
+    let bad = || -> ! {
+        fail(EvalError::TypeError(format!(
+            "{} expects a graph, a dict from nodes to arrays of neighbors, but got {}",
+            name, value
+        )))
+    };
+    let ResultValue::Dict(entries) = value else {
+        bad()
+    };
+    let lists: Vec<(String, Vec<String>)> = entries
+        .iter()
+        .map(|(node, neighbors)| match neighbors {
+            ResultValue::Array(neighbors) => (
+                node.clone(),
+                neighbors
+                    .iter()
+                    .map(|neighbor| node_name(name, neighbor))
+                    .collect(),
+            ),
+            _ => bad(),
+        })
+        .collect();
+    Graph::from_adjacency(&lists)
+}
+
+// The graph builtins, on directed graphs written as a dict from every node
+// to the array of its neighbors: graphFromEdges(edges) makes one from
+// [from, to] arrays, neighbors(g, v) is the neighbors of a node, bfs(g, v)
+// and dfs(g, v) are the nodes reachable from one in breadth-first and
+// depth-first order, and topoSort(g) is every node before the nodes its
+// edges lead to.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("graphFromEdges", [edges]) => {
+            let mut iterator = to_iter(edges).unwrap_or_else(|| {
+                fail(EvalError::TypeError(
+                    "graphFromEdges expects an array of edges".to_string(),
+                ))
+            });
+            let mut pairs = Vec::new();
+            while let Some(edge) = iterator.next(eval) {
+                let ResultValue::Array(ends) = &edge else {
+                    fail(EvalError::TypeError(format!(
+                        "graphFromEdges expects [from, to] edges but got {}",
+                        edge
+                    )));
+                };
+                let [from, to] = &ends[..] else {
+                    fail(EvalError::TypeError(format!(
+                        "graphFromEdges expects [from, to] edges but got {}",
+                        edge
+                    )));
+                };
+                pairs.push((node_name(name, from), node_name(name, to)));
+            }
+            let lists = Graph::from_edges(&pairs).to_adjacency();
+            ResultValue::Dict(
+                lists
+                    .into_iter()
+                    .map(|(node, neighbors)| (node, nodes_value(neighbors)))
+                    .collect(),
+            )
+        }
+        ("graphFromEdges", _) => fail(EvalError::TypeError(
+            "graphFromEdges expects an array of edges".to_string(),
+        )),
+        ("neighbors" | "bfs" | "dfs", [graph, node]) => {
+            let (graph, node) = (to_graph(name, graph), node_name(name, node));
+            nodes_value(match name {
+                "neighbors" => graph.neighbors(&node),
+                "bfs" => graph.bfs(&node),
+                _ => graph.dfs(&node),
+            })
+        }
+        ("neighbors" | "bfs" | "dfs", _) => fail(EvalError::TypeError(format!(
+            "{} expects a graph and a node",
+            name
+        ))),
+        ("topoSort", [graph]) => {
+            let order = to_graph(name, graph)
+                .topo_sort()
+                .unwrap_or_else(|err| fail(EvalError::Other(err)));
+            nodes_value(order)
+        }
+        _ => fail(EvalError::TypeError("topoSort expects a graph".to_string())),
+    }
+}
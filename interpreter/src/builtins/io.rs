@@ -0,0 +1,57 @@
+use super::{Arity, BuiltinRegistry};
+use crate::date::DateTime;
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("print", Arity::Exactly(1)),
+        ("readBytes", Arity::Exactly(1)),
+        ("writeBytes", Arity::Exactly(2)),
+        ("nowUtc", Arity::Exactly(0)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The I/O builtins: print(value) writes the value on a line of its own and
+// returns it, readBytes(path) and writeBytes(path, b) read and write files,
+// which needs the files capability, and nowUtc() reads the clock, which
+// needs the clock capability. `--world` replaces them with world-passing
+// versions.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("print", [value]) => {
+            eval.print(&value.to_string());
+            value.clone()
+        }
+        ("readBytes", [ResultValue::String(path)]) => {
+            eval.require(Capability::Files, name);
+            let bytes = std::fs::read(path).unwrap_or_else(|err| {
+                fail(EvalError::IoError(format!(
+                    "Failed to read {}: {}",
+                    path, err
+                )))
+            });
+            ResultValue::Bytes(bytes)
+        }
+        ("readBytes", _) => fail(EvalError::TypeError("readBytes expects a path".to_string())),
+        ("writeBytes", [ResultValue::String(path), ResultValue::Bytes(bytes)]) => {
+            eval.require(Capability::Files, name);
+            std::fs::write(path, bytes).unwrap_or_else(|err| {
+                fail(EvalError::IoError(format!(
+                    "Failed to write {}: {}",
+                    path, err
+                )))
+            });
+            ResultValue::Number(bytes.len() as i64)
+        }
+        ("writeBytes", _) => fail(EvalError::TypeError(
+            "writeBytes expects a path and bytes".to_string(),
+        )),
+        ("nowUtc", []) => {
+            eval.require(Capability::Clock, name);
+            ResultValue::DateTime(DateTime::now())
+        }
+        _ => unreachable!("Not an I/O builtin: {}", name),
+    }
+}
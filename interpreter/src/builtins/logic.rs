@@ -0,0 +1,63 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::unify;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("unify", Arity::Exactly(2)),
+        ("fact", Arity::AtLeast(1)),
+        ("rule", Arity::AtLeast(2)),
+        ("query", Arity::AtLeast(0)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// Parse the string arguments of a logic builtin as terms
+fn parse_terms(name: &str, arguments: &[ResultValue]) -> Vec<unify::Term> {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            ResultValue::String(text) => {
+                unify::Term::parse(text).unwrap_or_else(|err| fail(EvalError::Other(err)))
+            }
+            value => fail(EvalError::TypeError(format!(
+                "{} expects terms as strings but got {}",
+                name, value
+            ))),
+        })
+        .collect()
+}
+
+// The logic builtins, on terms written like `f(X, b)`: unify(t1, t2) is the
+// most general unifier of two terms, or "fail". fact(head) and
+// rule(head, goal...) add a clause to the knowledge base that
+// query(goal...) searches for every answer to the conjunction of goals, or
+// "fail".
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    let mut terms = parse_terms(name, &arguments);
+    match name {
+        "unify" => match unify::unify(&terms[0], &terms[1]) {
+            Ok(substitution) => ResultValue::String(substitution.to_string()),
+            Err(_) => ResultValue::String("fail".to_string()),
+        },
+        "fact" | "rule" => {
+            let head = terms.remove(0);
+            let text = head.to_string();
+            eval.knowledge_base.add(head, terms);
+            ResultValue::String(text)
+        }
+        _ => {
+            let answers: Vec<String> = eval
+                .knowledge_base
+                .query(&terms)
+                .iter()
+                .map(|answer| answer.to_string())
+                .collect();
+            if answers.is_empty() {
+                return ResultValue::String("fail".to_string());
+            }
+            ResultValue::String(answers.join("; "))
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::{Arity, BuiltinRegistry};
+use crate::analysis::function_effects;
+use crate::ast::Expr;
+use crate::env::Env;
+use crate::eval::*;
+use crate::sexpr;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("parse", Arity::Exactly(1)),
+        ("evalAst", Arity::Exactly(2)),
+        ("contract", Arity::Exactly(3)),
+        ("effectsOf", Arity::Exactly(1)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// Whether a contract predicate accepts its arguments: it must return a
+// boolean or a number, and any number but 0 counts as true
+pub(crate) fn holds(
+    predicate: ResultValue,
+    arguments: Vec<ResultValue>,
+    eval: &mut Evaluation,
+) -> bool {
+    match apply_function(predicate.clone(), arguments, eval) {
+        ResultValue::Number(number) => number != 0,
+        ResultValue::Bool(value) => value,
+        value => fail(EvalError::TypeError(format!(
+            "Contract predicate {} returned {}, not a number",
+            predicate, value
+        ))),
+    }
+}
+
+// The builtins for interpreters written in the language itself. A program
+// is a value in the JSON program format, with objects as dicts:
+// parse(text) reads a program written as JSON or, failing that, as an
+// s-expression, and evalAst(ast, env) evaluates a program with the
+// variables of the dict `env` in scope. contract(pre, post, function)
+// checks pre on the arguments and post on the result of every call of the
+// function it returns. effectsOf(function) returns the names of the
+// effects calling the function may have, as inferred from its code.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    match (name, arguments.as_slice()) {
+        ("parse", [ResultValue::String(text)]) => {
+            let expr = match serde_json::from_str(text) {
+                Ok(json) => Expr::from_json(&json),
+                Err(_) => sexpr::parse(text).map(|(expr, _)| expr),
+            };
+            match expr {
+                Ok(expr) => json_value(&expr.to_json()),
+                Err(err) => fail(EvalError::Other(format!("parse: {}", err))),
+            }
+        }
+        ("parse", _) => fail(EvalError::TypeError("parse expects a string".to_string())),
+        ("evalAst", [ast, ResultValue::Dict(entries)]) => {
+            let expr = Expr::from_json(&value_json(ast))
+                .unwrap_or_else(|err| fail(EvalError::TypeError(format!("evalAst: {}", err))));
+            let env = Env::frame(eval.new_frame_id(), entries.iter().cloned().collect(), None);
+            evaluate_expr(&expr, &env, eval)
+        }
+        ("evalAst", _) => fail(EvalError::TypeError(
+            "evalAst expects a program and a dict of variables".to_string(),
+        )),
+        ("contract", [pre, post, function]) => {
+            ResultValue::Contract(Box::new((pre.clone(), post.clone(), function.clone())))
+        }
+        ("effectsOf", [function @ (ResultValue::Lambda(..) | ResultValue::Contract(_))]) => {
+            let effects = function_effects(function);
+            ResultValue::Array(
+                effects
+                    .iter()
+                    .map(|effect| ResultValue::String(effect.name().to_string()))
+                    .collect(),
+            )
+        }
+        ("effectsOf", _) => fail(EvalError::TypeError(
+            "effectsOf expects a function".to_string(),
+        )),
+        _ => unreachable!("Not a meta builtin: {}", name),
+    }
+}
+
+// A program in the JSON format as a value
+fn json_value(json: &serde_json::Value) -> ResultValue {
+    match json {
+        serde_json::Value::Number(number) => ResultValue::Number(number.as_i64().unwrap()),
+        serde_json::Value::String(string) => ResultValue::String(string.clone()),
+        serde_json::Value::Bool(value) => ResultValue::Bool(*value),
+        serde_json::Value::Array(items) => {
+            ResultValue::Array(items.iter().map(json_value).collect())
+        }
+        serde_json::Value::Object(fields) => ResultValue::Dict(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), json_value(value)))
+                .collect(),
+        ),
+        serde_json::Value::Null => unreachable!("Programs have no null"),
+    }
+}
+
+// A value back in the JSON program format. Values that can't be part of a
+// program are left as null, which `Expr::from_json` rejects.
+fn value_json(value: &ResultValue) -> serde_json::Value {
+    match value {
+        ResultValue::Number(number) => serde_json::json!(number),
+        ResultValue::String(string) => serde_json::json!(string),
+        ResultValue::Bool(value) => serde_json::json!(value),
+        ResultValue::Array(items) => {
+            serde_json::Value::Array(items.iter().map(value_json).collect())
+        }
+        ResultValue::Dict(entries) => serde_json::Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.clone(), value_json(value)))
+                .collect(),
+        ),
+        _ => serde_json::Value::Null,
+    }
+}
@@ -0,0 +1,338 @@
+use crate::env::*;
+use crate::eval::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+
+mod arith;
+mod array;
+mod bytes;
+mod compare;
+mod date;
+mod docs;
+mod drawing;
+mod graph;
+mod io;
+mod logic;
+mod meta;
+mod parsing;
+mod sequence;
+mod string;
+mod world;
+
+pub(crate) use bytes::hex_encode;
+pub(crate) use meta::holds;
+
+// The names of the standard builtins, which the evaluator understands
+// without them being bound, in alphabetical order. The analyses that run
+// without an evaluation go by these; an evaluation goes by its
+// `BuiltinRegistry`.
+pub static BUILTINS: LazyLock<Vec<&'static str>> = LazyLock::new(|| STANDARD.names());
+
+static STANDARD: LazyLock<BuiltinRegistry> = LazyLock::new(BuiltinRegistry::standard);
+
+// A procedure programs can call by name: it gets its name, so that one
+// function can implement several builtins, and the arguments, as many as
+// its arity allows. Hosts can register closures that capture state of
+// their own; evaluations on other threads share them, hence `Arc` and
+// `Send + Sync`.
+pub type BuiltinFn =
+    Arc<dyn Fn(&str, Vec<ResultValue>, &mut Evaluation) -> ResultValue + Send + Sync>;
+
+// How many arguments a builtin takes. Calls with any other number fail
+// before the builtin runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    // From the first to the second, both included
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(arity) => count == arity,
+            Arity::AtLeast(minimum) => count >= minimum,
+            Arity::Between(minimum, maximum) => (minimum..=maximum).contains(&count),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exactly(0) => write!(f, "no arguments"),
+            Arity::Exactly(1) => write!(f, "1 argument"),
+            Arity::Exactly(arity) => write!(f, "{} arguments", arity),
+            Arity::AtLeast(1) => write!(f, "at least 1 argument"),
+            Arity::AtLeast(minimum) => write!(f, "at least {} arguments", minimum),
+            Arity::Between(minimum, maximum) => {
+                write!(f, "{} to {} arguments", minimum, maximum)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub arity: Arity,
+    pub function: BuiltinFn,
+}
+
+// The groups the standard builtins come in, each registered by the module
+// of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Arith,
+    Compare,
+    Array,
+    String,
+    Sequence,
+    Bytes,
+    Io,
+    Date,
+    Drawing,
+    Graph,
+    World,
+    Logic,
+    Parsing,
+    Docs,
+    Meta,
+}
+
+impl Category {
+    pub const ALL: [Category; 15] = [
+        Category::Arith,
+        Category::Compare,
+        Category::Array,
+        Category::String,
+        Category::Sequence,
+        Category::Bytes,
+        Category::Io,
+        Category::Date,
+        Category::Drawing,
+        Category::Graph,
+        Category::World,
+        Category::Logic,
+        Category::Parsing,
+        Category::Docs,
+        Category::Meta,
+    ];
+}
+
+// The builtins an evaluation lets programs call. Embedders can start from
+// the standard ones and take some away or replace them, or start from an
+// empty registry and add only the categories and procedures they want.
+#[derive(Clone, Default)]
+pub struct BuiltinRegistry {
+    builtins: HashMap<String, Builtin>,
+}
+
+impl BuiltinRegistry {
+    // No builtins at all: every call of a name no scope binds fails
+    pub fn empty() -> BuiltinRegistry {
+        BuiltinRegistry::default()
+    }
+
+    // Every builtin of every category, the ones named in BUILTINS
+    pub fn standard() -> BuiltinRegistry {
+        let mut registry = BuiltinRegistry::empty();
+        for category in Category::ALL {
+            registry.add_category(category);
+        }
+        registry
+    }
+
+    pub fn add_category(&mut self, category: Category) {
+        match category {
+            Category::Arith => arith::register(self),
+            Category::Compare => compare::register(self),
+            Category::Array => array::register(self),
+            Category::String => string::register(self),
+            Category::Sequence => sequence::register(self),
+            Category::Bytes => bytes::register(self),
+            Category::Io => io::register(self),
+            Category::Date => date::register(self),
+            Category::Drawing => drawing::register(self),
+            Category::Graph => graph::register(self),
+            Category::World => world::register(self),
+            Category::Logic => logic::register(self),
+            Category::Parsing => parsing::register(self),
+            Category::Docs => docs::register(self),
+            Category::Meta => meta::register(self),
+        }
+    }
+
+    // Add a builtin, replacing any of the same name
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&str, Vec<ResultValue>, &mut Evaluation) -> ResultValue
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        let function: BuiltinFn = Arc::new(function);
+        self.builtins
+            .insert(name.to_string(), Builtin { arity, function });
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.builtins.remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Builtin> {
+        self.builtins.get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    // The names of the builtins, in alphabetical order
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.builtins.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+// Apply one of the builtin procedures
+pub(crate) fn apply_builtin(
+    name: &str,
+    arguments: Vec<ResultValue>,
+    eval: &mut Evaluation,
+) -> ResultValue {
+    // Programs can only reach the procedures in the evaluation's registry.
+    // None of the standard ones use the network, environment variables or
+    // processes, and those that use files need the capability
+    let Some(builtin) = eval.builtins.get(name) else {
+        fail(EvalError::UnknownProcedure(name.to_string()));
+    };
+    if !builtin.arity.accepts(arguments.len()) {
+        fail(EvalError::TypeError(format!(
+            "{} expects {} but got {}",
+            name,
+            builtin.arity,
+            arguments.len()
+        )));
+    }
+    (builtin.function)(name, arguments, eval)
+}
+
+// The builtins that reach outside the evaluation: files, the clock and the
+// drawing written when the program ends. A new builtin that reads or
+// changes anything outside belongs here too, so that `--sandbox` takes it
+// away.
+pub(crate) const OUTSIDE_BUILTINS: [&str; 8] = [
+    "readBytes",
+    "writeBytes",
+    "nowUtc",
+    "penDown",
+    "penUp",
+    "forward",
+    "turn",
+    "circle",
+];
+
+// Builds the globals and the builtins of an evaluation, so that embedders
+// can take builtins away or replace them without changing the evaluator
+pub struct EnvBuilder {
+    globals: Env,
+    builtins: BuiltinRegistry,
+}
+
+impl Default for EnvBuilder {
+    fn default() -> EnvBuilder {
+        EnvBuilder::new()
+    }
+}
+
+impl EnvBuilder {
+    pub fn new() -> EnvBuilder {
+        EnvBuilder {
+            globals: default_globals(),
+            builtins: BuiltinRegistry::standard(),
+        }
+    }
+
+    // Replace a builtin, keeping its arity, or add a new one that takes
+    // any number of arguments. Like with `BuiltinRegistry::register`, the
+    // function can be a closure with state of its own.
+    pub fn override_builtin(
+        mut self,
+        name: &str,
+        function: impl Fn(&str, Vec<ResultValue>, &mut Evaluation) -> ResultValue
+            + Send
+            + Sync
+            + 'static,
+    ) -> EnvBuilder {
+        let arity = self
+            .builtins
+            .get(name)
+            .map_or(Arity::AtLeast(0), |builtin| builtin.arity);
+        self.builtins.register(name, arity, function);
+        self
+    }
+
+    pub fn remove_builtin(mut self, name: &str) -> EnvBuilder {
+        self.builtins.remove(name);
+        self
+    }
+
+    // Take away every builtin in OUTSIDE_BUILTINS and make `print` return
+    // its argument without printing it, so that evaluating untrusted
+    // programs has no effect but their value
+    pub fn sandboxed(mut self) -> EnvBuilder {
+        for name in OUTSIDE_BUILTINS {
+            self = self.remove_builtin(name);
+        }
+        self.builtins
+            .register("print", Arity::Exactly(1), |_, mut arguments, _| {
+                arguments.remove(0)
+            });
+        self
+    }
+
+    // Add a global variable, replacing any default of the same name
+    pub fn define(mut self, name: &str, value: ResultValue) -> EnvBuilder {
+        self.globals.define(name, value);
+        self
+    }
+
+    pub fn build(self) -> (Env, BuiltinRegistry) {
+        (self.globals, self.builtins)
+    }
+}
+
+// A builtin written as a plain function, like the host implementations
+// below
+pub(crate) type HostBuiltin = fn(&str, Vec<ResultValue>, &mut Evaluation) -> ResultValue;
+
+// Host implementations that `--override-builtin NAME=HOST` can put in
+// place of a builtin, e.g. to stub out part of a program when grading
+pub(crate) const HOST_BUILTINS: [(&str, HostBuiltin); 3] = [
+    ("identity", |_, arguments, _| {
+        match arguments.into_iter().next() {
+            Some(argument) => argument,
+            None => fail(EvalError::TypeError(
+                "identity expects an argument".to_string(),
+            )),
+        }
+    }),
+    ("zero", |_, _, _| ResultValue::Number(0)),
+    ("disabled", |name, _, _| {
+        fail(EvalError::DisabledBuiltin(name.to_string()))
+    }),
+];
+
+// Global environment where `x`, `v`, and `i` are pre-defined
+pub(crate) fn default_globals() -> Env {
+    let mut globals = Env::new();
+    globals.define("x", ResultValue::Number(10));
+    globals.define("v", ResultValue::Number(5));
+    globals.define("i", ResultValue::Number(1));
+    globals
+}
@@ -0,0 +1,61 @@
+use super::{Arity, BuiltinRegistry};
+use crate::combinators::Parser;
+use crate::eval::*;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("pChar", Arity::Exactly(1)),
+        ("pSeq", Arity::AtLeast(1)),
+        ("pAlt", Arity::AtLeast(1)),
+        ("pMany", Arity::Exactly(1)),
+        ("runParser", Arity::Exactly(2)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The parser combinator builtins: pChar(c) matches the one-character
+// string c, pSeq(p, ...) matches its parsers one after another, pAlt(p, ...)
+// the first one that matches, pMany(p) as many repetitions of p as possible,
+// and runParser(p, input) returns the prefix of input that p matches, or
+// "fail".
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    let parsers = || -> Vec<Parser> {
+        if arguments.is_empty() {
+            fail(EvalError::TypeError(format!(
+                "{} expects at least one parser",
+                name
+            )));
+        }
+        arguments
+            .iter()
+            .map(|argument| match argument {
+                ResultValue::Parser(parser) => parser.clone(),
+                value => fail(EvalError::TypeError(format!(
+                    "{} expects parsers but got {}",
+                    name, value
+                ))),
+            })
+            .collect()
+    };
+    let parser = match (name, arguments.as_slice()) {
+        ("pChar", [ResultValue::String(text)]) if text.chars().count() == 1 => {
+            Parser::Char(text.chars().next().unwrap())
+        }
+        ("pChar", _) => fail(EvalError::TypeError(
+            "pChar expects one one-character string".to_string(),
+        )),
+        ("pSeq", _) => Parser::Seq(parsers()),
+        ("pAlt", _) => Parser::Alt(parsers()),
+        ("pMany", [ResultValue::Parser(parser)]) => Parser::Many(Box::new(parser.clone())),
+        ("pMany", _) => fail(EvalError::TypeError("pMany expects one parser".to_string())),
+        ("runParser", [ResultValue::Parser(parser), ResultValue::String(input)]) => {
+            let matched = parser.run(input).unwrap_or_else(|| "fail".to_string());
+            return ResultValue::String(matched);
+        }
+        _ => fail(EvalError::TypeError(
+            "runParser expects a parser and a string".to_string(),
+        )),
+    };
+    ResultValue::Parser(parser)
+}
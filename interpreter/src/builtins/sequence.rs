@@ -0,0 +1,343 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::{iter, text};
+use std::cmp::Ordering;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("iterRange", Arity::Exactly(2)),
+        ("iterChars", Arity::Exactly(1)),
+        ("iterate", Arity::Exactly(3)),
+        ("toIter", Arity::Exactly(1)),
+        ("map", Arity::Exactly(2)),
+        ("filter", Arity::Exactly(2)),
+        ("take", Arity::Exactly(2)),
+        ("fold", Arity::Exactly(3)),
+        ("for", Arity::Exactly(2)),
+        ("collect", Arity::Exactly(2)),
+        ("indexOf", Arity::Exactly(2)),
+        ("findIndex", Arity::Exactly(2)),
+        ("sort", Arity::Exactly(1)),
+        ("sortCollated", Arity::Exactly(1)),
+        ("min", Arity::AtLeast(1)),
+        ("max", Arity::AtLeast(1)),
+        ("zipWith", Arity::Exactly(3)),
+        ("cartesian", Arity::Exactly(2)),
+        ("chunks", Arity::Exactly(2)),
+        ("windows", Arity::Exactly(2)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The values of anything that can be iterated over: iterators, strings (by
+// char) and collections. A dict gives its entries as [key, value] arrays.
+pub(super) fn to_iter(value: &ResultValue) -> Option<iter::Iter> {
+    match value {
+        ResultValue::Iterator(iterator) => Some(iterator.as_ref().clone()),
+        ResultValue::String(text) => Some(iter::Iter::Chars(text.chars().collect(), 0)),
+        ResultValue::Array(values) | ResultValue::Set(values) => {
+            Some(iter::Iter::Values(values.clone(), 0))
+        }
+        ResultValue::Dict(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    ResultValue::Array(vec![ResultValue::String(key.clone()), value.clone()].into())
+                })
+                .collect();
+            Some(iter::Iter::Values(entries, 0))
+        }
+        _ => None,
+    }
+}
+
+// The iterator builtins: iterRange(low, high), iterChars(s),
+// iterate(seed, next, test) and toIter(x) make iterators, map(f, it),
+// filter(p, it) and take(n, it) make iterators from others, and
+// fold(f, init, it), for(it, f) and collect(it, kind) consume one. `for`
+// calls f for its effects, such as printing. zipWith(f, xs, ys) and
+// cartesian(xs, ys) pair up the values of two into an array, and
+// chunks(xs, n) and windows(xs, n) group the values of one. Wherever an
+// iterator is expected, anything `toIter` accepts will do.
+fn apply(name: &str, arguments: Vec<ResultValue>, eval: &mut Evaluation) -> ResultValue {
+    let iterable = |value: &ResultValue| {
+        to_iter(value).unwrap_or_else(|| {
+            fail(EvalError::TypeError(format!(
+                "{} expects something to iterate over but got {}",
+                name, value
+            )))
+        })
+    };
+    let iterator = match (name, arguments.as_slice()) {
+        ("iterRange", [ResultValue::Number(low), ResultValue::Number(high)]) => {
+            iter::Iter::Range(*low, *high)
+        }
+        ("iterRange", _) => fail(EvalError::TypeError(
+            "iterRange expects two numbers".to_string(),
+        )),
+        ("iterChars", [ResultValue::String(text)]) => iter::Iter::Chars(text.chars().collect(), 0),
+        ("iterChars", _) => fail(EvalError::TypeError(
+            "iterChars expects a string".to_string(),
+        )),
+        ("iterate", [value, next, test]) => iter::Iter::Iterate(Box::new(iter::Iterate {
+            value: value.clone(),
+            next: next.clone(),
+            test: test.clone(),
+            started: false,
+        })),
+        ("iterate", _) => fail(EvalError::TypeError(
+            "iterate expects a seed, a next function and a test".to_string(),
+        )),
+        ("toIter", [value]) => iterable(value),
+        ("toIter", _) => fail(EvalError::TypeError(
+            "toIter expects 1 argument".to_string(),
+        )),
+        ("map" | "filter", [function, inner]) => {
+            let inner = Box::new(iterable(inner));
+            if name == "map" {
+                iter::Iter::Map(function.clone(), inner)
+            } else {
+                iter::Iter::Filter(function.clone(), inner)
+            }
+        }
+        ("map" | "filter", _) => fail(EvalError::TypeError(format!(
+            "{} expects a function and an iterator",
+            name
+        ))),
+        ("take", [ResultValue::Number(count), inner]) if *count >= 0 => {
+            iter::Iter::Take(*count as usize, Box::new(iterable(inner)))
+        }
+        ("take", _) => fail(EvalError::TypeError(
+            "take expects a count and an iterator".to_string(),
+        )),
+        ("fold", [function, init, iterator]) => {
+            let (function, mut iterator) = (function.clone(), iterable(iterator));
+            let mut result = init.clone();
+            while let Some(value) = iterator.next(eval) {
+                result = apply_function(function.clone(), vec![result, value], eval);
+            }
+            return result;
+        }
+        ("fold", _) => fail(EvalError::TypeError(
+            "fold expects a function, an initial value and an iterator".to_string(),
+        )),
+        ("for", [iterator, function]) => {
+            let (mut iterator, function) = (iterable(iterator), function.clone());
+            while let Some(value) = iterator.next(eval) {
+                apply_function(function.clone(), vec![value], eval);
+            }
+            return ResultValue::Unit;
+        }
+        ("for", _) => fail(EvalError::TypeError(
+            "for expects an iterator and a function".to_string(),
+        )),
+        ("collect", [iterator, ResultValue::String(kind)]) => {
+            let mut iterator = iterable(iterator);
+            let mut values = Vec::new();
+            while let Some(value) = iterator.next(eval) {
+                values.push(value);
+            }
+            return collect(values, kind);
+        }
+        ("collect", _) => fail(EvalError::TypeError(
+            "collect expects an iterator and a kind".to_string(),
+        )),
+        ("indexOf", [iterator, wanted]) => {
+            return find_index(iterable(iterator), eval, |value, _| {
+                matches!(value.compare(wanted), Ok(Ordering::Equal))
+            });
+        }
+        ("findIndex", [predicate, iterator]) => {
+            return find_index(iterable(iterator), eval, |value, eval| {
+                iter::holds(predicate, value, eval)
+            });
+        }
+        ("indexOf", _) => fail(EvalError::TypeError(
+            "indexOf expects an iterator and a value".to_string(),
+        )),
+        ("findIndex", _) => fail(EvalError::TypeError(
+            "findIndex expects a predicate and an iterator".to_string(),
+        )),
+        ("sort", [iterator]) => {
+            let mut iterator = iterable(iterator);
+            let mut values = Vec::new();
+            while let Some(value) = iterator.next(eval) {
+                values.push(value);
+            }
+            let sorted = sort_values(values).unwrap_or_else(|err| fail(err));
+            return ResultValue::Array(sorted.into());
+        }
+        ("sort", _) => fail(EvalError::TypeError("sort expects an iterator".to_string())),
+        ("sortCollated", [iterator]) => {
+            let mut iterator = iterable(iterator);
+            let mut strings = Vec::new();
+            while let Some(value) = iterator.next(eval) {
+                match value {
+                    ResultValue::String(string) => strings.push(string),
+                    value => fail(EvalError::TypeError(format!(
+                        "sortCollated expects strings but got {}",
+                        value.described()
+                    ))),
+                }
+            }
+            strings.sort_by(|left, right| text::collate(left, right));
+            return ResultValue::Array(strings.into_iter().map(ResultValue::String).collect());
+        }
+        ("sortCollated", _) => fail(EvalError::TypeError(
+            "sortCollated expects an iterator".to_string(),
+        )),
+        ("min" | "max", [_, ..]) => {
+            // One argument is something to iterate over, more are the values
+            let mut values = Vec::new();
+            if let [iterator] = arguments.as_slice() {
+                let mut iterator = iterable(iterator);
+                while let Some(value) = iterator.next(eval) {
+                    values.push(value);
+                }
+            } else {
+                values = arguments;
+            }
+            let wanted = if name == "min" {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+            let mut values = values.into_iter();
+            let first = values
+                .next()
+                .unwrap_or_else(|| fail(EvalError::TypeError(format!("{} of no values", name))));
+            let extreme = values.try_fold(first, |extreme, value| {
+                Ok(if value.compare(&extreme)? == wanted {
+                    value
+                } else {
+                    extreme
+                })
+            });
+            return extreme.unwrap_or_else(|err: EvalError| fail(err));
+        }
+        ("min" | "max", _) => fail(EvalError::TypeError(format!(
+            "{} expects values or an iterator",
+            name
+        ))),
+        ("zipWith", [function, left, right]) => {
+            // Stop at the end of the shorter one, without taking a value
+            // from the other
+            let (mut left, mut right) = (iterable(left), iterable(right));
+            let mut values = Vec::new();
+            while let Some(x) = left.next(eval) {
+                let Some(y) = right.next(eval) else {
+                    break;
+                };
+                values.push(apply_function(function.clone(), vec![x, y], eval));
+            }
+            return ResultValue::Array(values.into());
+        }
+        ("zipWith", _) => fail(EvalError::TypeError(
+            "zipWith expects a function and two iterators".to_string(),
+        )),
+        ("cartesian", [left, right]) => {
+            let (mut left, mut right) = (iterable(left), iterable(right));
+            let mut ys = Vec::new();
+            while let Some(y) = right.next(eval) {
+                ys.push(y);
+            }
+            let mut pairs = Vec::new();
+            while let Some(x) = left.next(eval) {
+                for y in &ys {
+                    pairs.push(ResultValue::Array(vec![x.clone(), y.clone()].into()));
+                }
+            }
+            return ResultValue::Array(pairs.into());
+        }
+        ("cartesian", _) => fail(EvalError::TypeError(
+            "cartesian expects two iterators".to_string(),
+        )),
+        ("chunks" | "windows", [iterator, ResultValue::Number(size)]) if *size > 0 => {
+            let mut iterator = iterable(iterator);
+            let mut values = Vec::new();
+            while let Some(value) = iterator.next(eval) {
+                values.push(value);
+            }
+            let size = *size as usize;
+            let groups: Vec<&[ResultValue]> = if name == "chunks" {
+                values.chunks(size).collect()
+            } else {
+                values.windows(size).collect()
+            };
+            return ResultValue::Array(
+                groups
+                    .into_iter()
+                    .map(|group| ResultValue::Array(group.to_vec().into()))
+                    .collect(),
+            );
+        }
+        ("chunks" | "windows", _) => fail(EvalError::TypeError(format!(
+            "{} expects an iterator and a positive size",
+            name
+        ))),
+        _ => unreachable!("Not an iterator builtin: {}", name),
+    };
+    ResultValue::Iterator(Box::new(iterator))
+}
+
+// The position of the first value for which `found` holds, or the unit
+// value
+fn find_index(
+    mut iterator: iter::Iter,
+    eval: &mut Evaluation,
+    found: impl Fn(&ResultValue, &mut Evaluation) -> bool,
+) -> ResultValue {
+    let mut index = 0;
+    while let Some(value) = iterator.next(eval) {
+        if found(&value, eval) {
+            return ResultValue::Number(index);
+        }
+        index += 1;
+    }
+    ResultValue::Unit
+}
+
+// Put values into a collection of the kind "array", "set" or "dict". Values
+// that print the same are the same in a set, and a dict takes its entries
+// from [key, value] arrays, where a later entry replaces an earlier one.
+fn collect(values: Vec<ResultValue>, kind: &str) -> ResultValue {
+    match kind {
+        "array" => ResultValue::Array(values.into()),
+        "set" => {
+            let mut distinct: Vec<ResultValue> = Vec::new();
+            for value in values {
+                if !distinct.iter().any(|seen| seen.quoted() == value.quoted()) {
+                    distinct.push(value);
+                }
+            }
+            ResultValue::Set(distinct.into())
+        }
+        "dict" => {
+            let mut entries: Vec<(String, ResultValue)> = Vec::new();
+            for value in values {
+                let ResultValue::Array(pair) = &value else {
+                    fail(EvalError::TypeError(format!(
+                        "collect into a dict expects [key, value] arrays but got {}",
+                        value
+                    )));
+                };
+                let [ResultValue::String(key), value] = &pair[..] else {
+                    fail(EvalError::TypeError(format!(
+                        "collect into a dict expects [key, value] arrays but got {}",
+                        value
+                    )));
+                };
+                match entries.iter_mut().find(|(existing, _)| existing == key) {
+                    Some(entry) => entry.1 = value.clone(),
+                    None => entries.push((key.clone(), value.clone())),
+                }
+            }
+            ResultValue::Dict(entries)
+        }
+        _ => fail(EvalError::TypeError(format!(
+            "Unknown collection kind: {} (expected array, set or dict)",
+            kind
+        ))),
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::text;
+use std::cmp::Ordering;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    registry.register("normalize", Arity::Exactly(1), apply);
+    for name in [
+        "strCmp",
+        "strEqIgnoreCase",
+        "collate",
+        "editDistance",
+        "diffLines",
+    ] {
+        registry.register(name, Arity::Exactly(2), apply);
+    }
+}
+
+// The string builtins that treat strings as Unicode text, the same on
+// every platform: normalize(s) is the NFC form of `s`, strCmp(a, b) orders
+// by chars after normalizing, collate(a, b) in dictionary order (see
+// `text::collate`), both as -1, 0 or 1, and strEqIgnoreCase(a, b) compares
+// without case. editDistance(a, b) is the Levenshtein distance in chars,
+// and diffLines(a, b) the lines of `a` and `b` as a diff, each starting
+// with " " when it is in both, "-" when only in `a` and "+" when only in
+// `b`.
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    let sign = |ordering: Ordering| ResultValue::Number(ordering as i64);
+    match (name, arguments.as_slice()) {
+        ("normalize", [ResultValue::String(string)]) => {
+            ResultValue::String(text::normalize(string))
+        }
+        ("normalize", _) => fail(EvalError::TypeError(
+            "normalize expects a string".to_string(),
+        )),
+        ("strCmp", [ResultValue::String(left), ResultValue::String(right)]) => {
+            sign(text::compare(left, right))
+        }
+        ("collate", [ResultValue::String(left), ResultValue::String(right)]) => {
+            sign(text::collate(left, right))
+        }
+        ("strEqIgnoreCase", [ResultValue::String(left), ResultValue::String(right)]) => {
+            ResultValue::Bool(text::eq_ignore_case(left, right))
+        }
+        ("editDistance", [ResultValue::String(left), ResultValue::String(right)]) => {
+            ResultValue::Number(text::edit_distance(left, right) as i64)
+        }
+        ("diffLines", [ResultValue::String(old), ResultValue::String(new)]) => {
+            let old: Vec<&str> = old.lines().collect();
+            let new: Vec<&str> = new.lines().collect();
+            let lines = text::diff_lines(&old, &new)
+                .into_iter()
+                .map(|line| {
+                    ResultValue::String(match line {
+                        text::DiffLine::Same(line) => format!(" {}", line),
+                        text::DiffLine::Removed(line) => format!("-{}", line),
+                        text::DiffLine::Added(line) => format!("+{}", line),
+                    })
+                })
+                .collect();
+            ResultValue::Array(lines)
+        }
+        _ => fail(EvalError::TypeError(format!(
+            "{} expects two strings",
+            name
+        ))),
+    }
+}
@@ -0,0 +1,67 @@
+use super::{Arity, BuiltinRegistry};
+use crate::eval::*;
+use crate::world::World;
+
+pub(super) fn register(registry: &mut BuiltinRegistry) {
+    for (name, arity) in [
+        ("readLineW", Arity::Exactly(1)),
+        ("atEndW", Arity::Exactly(1)),
+        ("printW", Arity::Exactly(2)),
+        ("readFileW", Arity::Exactly(2)),
+        ("writeFileW", Arity::Exactly(3)),
+        ("nowW", Arity::Exactly(1)),
+    ] {
+        registry.register(name, arity, apply);
+    }
+}
+
+// The world-passing builtins, pure versions of the I/O builtins that take
+// the world as their first argument and return the next one:
+// readLineW(w) is [line, w'], atEndW(w) whether all input has been read,
+// printW(w, value) is w' with the value printed, readFileW(w, path) is
+// [contents, w'], writeFileW(w, path, text) is w' with the file written and
+// nowW(w) is [date, w'].
+fn apply(name: &str, arguments: Vec<ResultValue>, _: &mut Evaluation) -> ResultValue {
+    let Some(mut world) = arguments.first().and_then(World::from_value) else {
+        fail(EvalError::TypeError(format!(
+            "{} expects a world as its first argument",
+            name
+        )));
+    };
+    let with_world = |value: ResultValue, world: &World| {
+        ResultValue::Array(vec![value, world.to_value()].into())
+    };
+    match (name, &arguments[1..]) {
+        ("readLineW", []) => match world.read_line() {
+            Some(line) => with_world(ResultValue::String(line), &world),
+            None => fail(EvalError::Other(
+                "readLineW: no input left (check atEndW first)".to_string(),
+            )),
+        },
+        ("atEndW", []) => ResultValue::Number(world.at_end() as i64),
+        ("printW", [value]) => {
+            world.print(value.to_string());
+            world.to_value()
+        }
+        ("readFileW", [ResultValue::String(path)]) => match world.read_file(path) {
+            Some(contents) => with_world(ResultValue::String(contents.to_string()), &world),
+            None => fail(EvalError::Other(format!(
+                "readFileW: the world has no file {}",
+                path
+            ))),
+        },
+        ("writeFileW", [ResultValue::String(path), ResultValue::String(text)]) => {
+            world.write_file(path, text.clone());
+            world.to_value()
+        }
+        ("nowW", []) => {
+            let now = world.now();
+            with_world(ResultValue::DateTime(now), &world)
+        }
+        _ => fail(EvalError::TypeError(format!(
+            "{} got the wrong arguments: {}",
+            name,
+            quoted_list(&arguments)
+        ))),
+    }
+}
@@ -0,0 +1,2165 @@
+use crate::analysis::*;
+use crate::ast::*;
+use crate::builtins::*;
+use crate::diagnostic::{ErrorFormat, JsonDiagnostic};
+use crate::env::*;
+use crate::eval::*;
+use crate::optimize::*;
+use crate::parallel::evaluate_parallel;
+use crate::resolver::{locate_lambdas, resolve_locals};
+use crate::stepper::*;
+use crate::vm::Vm;
+use crate::world::World;
+use crate::{cost, properties, rpc, server, sexpr, strategy};
+use clap::builder::PossibleValue;
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::time::{Duration, Instant};
+
+// One difference between two programs, at a path of the old program
+#[derive(Debug)]
+enum Difference {
+    Added(NodePath, String),
+    Removed(NodePath, String),
+    Changed(NodePath, String, String),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Added(path, label) => write!(f, "added   {} {}", path, label),
+            Difference::Removed(path, label) => write!(f, "removed {} {}", path, label),
+            Difference::Changed(path, old, new) => {
+                write!(f, "changed {} {} -> {}", path, old, new)
+            }
+        }
+    }
+}
+
+// Compare two programs modulo renaming of bound variables. Subtrees with
+// the same structural hash are equal; otherwise nodes of the same kind are
+// compared child by child and anything else is reported as changed.
+fn diff_programs(old: &Expr, new: &Expr) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_nodes(
+        old,
+        new,
+        &NodePath::default(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut differences,
+    );
+    differences
+}
+
+fn diff_nodes<'a>(
+    old: &'a Expr,
+    new: &'a Expr,
+    path: &NodePath,
+    old_scope: &mut Vec<&'a str>,
+    new_scope: &mut Vec<&'a str>,
+    differences: &mut Vec<Difference>,
+) {
+    if old.structural_hash_in(old_scope) == new.structural_hash_in(new_scope) {
+        return;
+    }
+    let same_kind = std::mem::discriminant(old) == std::mem::discriminant(new)
+        && old.binds().len() == new.binds().len()
+        && !old.children().is_empty();
+    if !same_kind {
+        differences.push(Difference::Changed(path.clone(), old.label(), new.label()));
+        return;
+    }
+    let (old_depth, new_depth) = (old_scope.len(), new_scope.len());
+    old_scope.extend(old.binds());
+    new_scope.extend(new.binds());
+    let (old_children, new_children) = (old.children(), new.children());
+    for i in 0..old_children.len().max(new_children.len()) {
+        match (old_children.get(i), new_children.get(i)) {
+            (Some(old_child), Some(new_child)) => diff_nodes(
+                old_child,
+                new_child,
+                &path.child(i),
+                old_scope,
+                new_scope,
+                differences,
+            ),
+            (Some(old_child), None) => {
+                differences.push(Difference::Removed(path.child(i), old_child.label()))
+            }
+            (None, Some(new_child)) => {
+                differences.push(Difference::Added(path.child(i), new_child.label()))
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    old_scope.truncate(old_depth);
+    new_scope.truncate(new_depth);
+}
+
+// Parse a JSON document, or say where it is invalid and exit with 1
+fn read_json(text: &str, source: &str, format: ErrorFormat) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|err| {
+        eprintln!("{}", JsonDiagnostic::new(source, text, &err).render(format));
+        std::process::exit(1);
+    })
+}
+
+// Read a program from a JSON file
+fn load_program(path: &str) -> Expr {
+    let input = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    let json = read_json(&input, path, ErrorFormat::Text);
+    Expr::from_json(&json).unwrap_or_else(|err| panic!("{}", err))
+}
+
+// `diff old.json new.json`: exits with 1 when the programs differ
+fn run_diff(args: &ArgMatches) {
+    let (old, new) = (
+        load_program(arg(args, "old")),
+        load_program(arg(args, "new")),
+    );
+    let differences = diff_programs(&old, &new);
+    if differences.is_empty() {
+        println!(
+            "Programs are identical (hash {:016x})",
+            old.structural_hash()
+        );
+        return;
+    }
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    std::process::exit(1);
+}
+
+// `stats program.json`: size and shape metrics of a program
+fn run_stats(args: &ArgMatches) {
+    let program = load_program(arg(args, "program"));
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    let mut nodes = 0;
+    program.walk(&mut |_, _, node| {
+        nodes += 1;
+        match counts.iter_mut().find(|(kind, _)| *kind == node.kind()) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((node.kind(), 1)),
+        }
+    });
+    counts.sort();
+    let lambdas = counts
+        .iter()
+        .find(|(kind, _)| *kind == "Lambda")
+        .map_or(0, |(_, count)| *count);
+    let free: Vec<String> = program
+        .free_variables()
+        .into_iter()
+        .filter(|name| !BUILTINS.contains(&builtin_name(name)))
+        .collect();
+
+    println!("nodes: {}", nodes);
+    for (kind, count) in &counts {
+        println!("  {}: {}", kind, count);
+    }
+    println!("max depth: {}", program.depth());
+    println!("lambdas: {}", lambdas);
+    if free.is_empty() {
+        println!("free variables: none");
+    } else {
+        println!("free variables: {}", free.join(", "));
+    }
+    println!("estimated size: {} bytes", program.estimated_size());
+}
+
+// `cost manifest.json`: check a program's step counts against asymptotic
+// budgets. The manifest names a program that evaluates to a function of the
+// input size, the sizes to run it with and the budgets, e.g.
+// `{"program": "sort.json", "sizes": [10, 20, 40], "bounds": ["50 * n log n"]}`.
+// Exits with 1 when a budget is exceeded.
+fn run_cost(args: &ArgMatches) {
+    let path = arg(args, "manifest");
+    let input = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    let manifest = read_json(&input, path, ErrorFormat::Text);
+    let program = manifest["program"]
+        .as_str()
+        .expect("The manifest needs a program");
+    // The program is found next to the manifest
+    let program = std::path::Path::new(path).with_file_name(program);
+    let function = load_program(&program.to_string_lossy());
+    let sizes: Vec<u64> = manifest["sizes"]
+        .as_array()
+        .expect("The manifest needs sizes")
+        .iter()
+        .map(|size| size.as_u64().expect("Sizes must be natural numbers"))
+        .collect();
+    let bounds: Vec<cost::Bound> = manifest["bounds"]
+        .as_array()
+        .expect("The manifest needs bounds")
+        .iter()
+        .map(|bound| {
+            let bound = bound.as_str().expect("Bounds must be strings");
+            cost::Bound::parse(bound).unwrap_or_else(|err| panic!("{}", err))
+        })
+        .collect();
+
+    let globals = default_globals();
+    let mut samples = Vec::new();
+    for &n in &sizes {
+        let call = Expr::Application(vec![function.clone(), Expr::Number(n as i64)]);
+        let mut eval = Evaluation::new(LangVersion::Cp3);
+        evaluate_expr(&call, &globals, &mut eval);
+        println!("n = {}: {} steps", n, eval.steps);
+        samples.push((n, eval.steps));
+    }
+    let mut failed = false;
+    for bound in &bounds {
+        let fitted = bound.fit(&samples);
+        let verdict = if fitted <= bound.coefficient {
+            "pass"
+        } else {
+            failed = true;
+            "fail"
+        };
+        println!(
+            "{}: steps <= {} (fitted {:.2} * {})",
+            verdict, bound, fitted, bound.complexity
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// `check property.json`: evaluate a property with generated inputs. The file
+// holds the property, an expression over the declared parameters that
+// evaluates to a non-zero number when it holds, the parameters with their
+// generators (see `properties::Parameter`), and optionally the number of
+// cases and the random seed. Exits with 1 on the first counterexample.
+fn run_check(args: &ArgMatches) {
+    let path = arg(args, "property");
+    let input = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    let spec = read_json(&input, path, ErrorFormat::Text);
+    let property = Expr::from_json(&spec["property"]).unwrap_or_else(|err| panic!("{}", err));
+    let parameters: Vec<properties::Parameter> = spec["parameters"]
+        .as_array()
+        .expect("The property needs parameters")
+        .iter()
+        .map(|parameter| {
+            properties::Parameter::from_json(parameter).unwrap_or_else(|err| panic!("{}", err))
+        })
+        .collect();
+    let cases = spec["cases"].as_u64().unwrap_or(100);
+    let mut rng = Rng::new(spec["seed"].as_u64().unwrap_or(0));
+
+    let globals = default_globals();
+    for case in 1..=cases {
+        let inputs: Vec<(String, ResultValue)> = parameters
+            .iter()
+            .map(|parameter| (parameter.name.clone(), parameter.generate(&mut rng)))
+            .collect();
+        let mut eval = Evaluation::new(LangVersion::Cp3);
+        let env = Env::frame(
+            eval.new_frame_id(),
+            inputs.iter().cloned().collect(),
+            Some(globals.clone()),
+        );
+        let evaluated = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            evaluate_expr(&property, &env, &mut eval)
+        }));
+        let outcome = match evaluated {
+            Ok(ResultValue::Number(number)) if number != 0 => continue,
+            Ok(result) => format!("result {}", result),
+            Err(payload) => format!("error: {}", panic_message(&payload)),
+        };
+        let shown: Vec<String> = inputs
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        println!(
+            "Counterexample in case {}: {} ({})",
+            case,
+            shown.join(", "),
+            outcome
+        );
+        std::process::exit(1);
+    }
+    println!("Passed {} cases", cases);
+}
+
+// `conformance suite.json [--engine evaluator|stepper | --fuel]`: run the
+// reference semantics cases with one of the engines. Every case has a name,
+// a category, a program, optionally a language version and options, and
+// either the printed `result` or a pattern the `error` must contain. The
+// stepper can't explain why it is stuck, so for it any error will do.
+// Exits with 1 when a case fails. With `--fuel`, runs every case with both
+// engines and prints the steps each took instead.
+fn run_conformance(args: &ArgMatches) {
+    let path = arg(args, "suite");
+    let engine = if args.get_flag("fuel") {
+        "fuel"
+    } else {
+        arg(args, "engine")
+    };
+    let input = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+    let suite = read_json(&input, path, ErrorFormat::Text);
+    let cases = suite.as_array().expect("The suite must be a list of cases");
+
+    let globals = default_globals();
+    if engine == "fuel" {
+        return compare_fuel(cases, &globals);
+    }
+    let mut failures = 0;
+    for case in cases {
+        let name = case_name(case);
+        let (outcome, _) = run_case(case, engine, &globals);
+        let passed = match (&outcome, case["result"].as_str(), case["error"].as_str()) {
+            (Ok(result), Some(expected), _) => result == expected,
+            (Err(_), _, Some(_)) if engine == "stepper" => true,
+            (Err(err), _, Some(expected)) => err.contains(expected),
+            _ => false,
+        };
+        if !passed {
+            failures += 1;
+            let expected = match case["result"].as_str() {
+                Some(result) => result.to_string(),
+                None => format!("error: {}", case["error"].as_str().unwrap_or("")),
+            };
+            let got = match outcome {
+                Ok(result) => result,
+                Err(err) => format!("error: {}", err),
+            };
+            println!("FAIL {}: expected {}, got {}", name, expected, got);
+        }
+    }
+    println!(
+        "{}: {} of {} cases passed",
+        engine,
+        cases.len() - failures,
+        cases.len()
+    );
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn case_name(case: &Value) -> String {
+    format!(
+        "{}/{}",
+        case["category"].as_str().unwrap_or("-"),
+        case["name"].as_str().unwrap_or("-")
+    )
+}
+
+// Run a conformance case with an engine: the printed result or the error,
+// and the steps taken. The evaluator's steps are the expressions it
+// evaluated, the stepper's the reductions it made.
+fn run_case(case: &Value, engine: &str, globals: &Env) -> (Result<String, String>, u64) {
+    let program = Expr::from_json(&case["program"]).unwrap_or_else(|err| panic!("{}", err));
+    let version = LangVersion::parse(case["version"].as_str().unwrap_or("cp3"))
+        .unwrap_or_else(|err| panic!("{}", err));
+    let loop_limit = case["options"]["loop_limit"]
+        .as_u64()
+        .map(|limit| limit as usize);
+    let max_steps = case["options"]["max_steps"].as_u64();
+    if engine == "stepper" {
+        let mut expr = program;
+        for (name, value) in globals.vars().iter() {
+            if let ResultValue::Number(number) = value {
+                expr = substitute(&expr, &name, &Expr::Number(*number));
+            }
+        }
+        let stepper = Stepper { version };
+        let mut steps = 0;
+        while let Some((next, _)) = stepper.step(&expr) {
+            steps += 1;
+            if steps > max_steps.unwrap_or(10_000) {
+                break;
+            }
+            expr = next;
+        }
+        if is_value(&expr) {
+            (Ok(expr.to_string()), steps)
+        } else {
+            (Err(format!("Stuck at {}", expr)), steps)
+        }
+    } else {
+        let mut eval = Evaluation::new(version);
+        eval.loop_limit = loop_limit;
+        eval.set_max_steps(max_steps);
+        // Only the result counts, and errors are expected, so they aren't
+        // printed as they happen
+        eval.output = Output::Captured(String::new());
+        let outcome = catch_silently(|| evaluate_expr(&program, globals, &mut eval).to_string())
+            .map_err(|payload| panic_message(&payload));
+        (outcome, eval.steps)
+    }
+}
+
+// Steps an imported exercise may take, so that one that doesn't terminate
+// is recorded as running out of fuel instead of hanging the import
+const IMPORT_MAX_STEPS: u64 = 100_000;
+
+// `import DIR OUT [--lang-version cp3|cp4]`: turn a directory of exercises
+// written as s-expressions (`.scm` files, see `sexpr::parse`) into a
+// regression corpus. Every program is written to OUT as NAME.json, and
+// OUT/conformance.json gets a case for each with what the evaluator makes
+// of it now, its result or its error, so that `conformance` replays the
+// corpus with every engine. Files that don't parse are reported and left
+// out.
+fn run_import(args: &ArgMatches) {
+    let (dir, out, version) = (
+        arg(args, "dir"),
+        arg(args, "out"),
+        arg(args, "lang-version"),
+    );
+    LangVersion::parse(version).unwrap_or_else(|err| panic!("{}", err));
+    let mut paths: Vec<std::path::PathBuf> = std::fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", dir, err))
+        .map(|entry| entry.expect("Failed to read the directory").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "scm"))
+        .collect();
+    paths.sort();
+    std::fs::create_dir_all(out).unwrap_or_else(|err| panic!("Failed to create {}: {}", out, err));
+    let category = std::path::Path::new(dir)
+        .file_name()
+        .map_or("imported".to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        });
+
+    let globals = default_globals();
+    let mut cases = Vec::new();
+    let mut skipped = 0;
+    for path in &paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err));
+        let program = match sexpr::parse(&text) {
+            Ok((program, _)) => program,
+            Err(err) => {
+                eprintln!("Skipped {}: {}", path.display(), err);
+                skipped += 1;
+                continue;
+            }
+        };
+        let json = program.to_json();
+        let target = std::path::Path::new(out).join(format!("{}.json", name));
+        std::fs::write(&target, format!("{}\n", json))
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", target.display(), err));
+        let mut case = serde_json::json!({
+            "name": name,
+            "category": category,
+            "program": json,
+            "version": version,
+            "options": { "max_steps": IMPORT_MAX_STEPS },
+        });
+        match run_case(&case, "evaluator", &globals).0 {
+            Ok(result) => case["result"] = Value::String(result),
+            Err(err) => case["error"] = Value::String(err),
+        }
+        cases.push(case);
+    }
+
+    // One case per line, like the hand-written suite
+    let lines: Vec<String> = cases.iter().map(|case| format!("  {}", case)).collect();
+    let suite = std::path::Path::new(out).join("conformance.json");
+    std::fs::write(&suite, format!("[\n{}\n]\n", lines.join(",\n")))
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", suite.display(), err));
+    println!(
+        "Imported {} programs into {}, skipped {}",
+        cases.len(),
+        out,
+        skipped
+    );
+}
+
+// Print the steps the evaluator and the stepper take on every case, and
+// whether they agree on the result. Every failing case agrees with every
+// other, since the stepper can't say why it is stuck. The two count
+// different things, so the ratio shows the stepper's cost relative to the
+// evaluator's rather than which is faster. Exits with 1 when the engines
+// disagree on a case.
+fn compare_fuel(cases: &[Value], globals: &Env) {
+    println!("{:>10} {:>10} {:>7}  case", "evaluator", "stepper", "ratio");
+    let (mut evaluator_total, mut stepper_total) = (0, 0);
+    let mut disagreements = 0;
+    for case in cases {
+        let (evaluated, evaluator_steps) = run_case(case, "evaluator", globals);
+        let (stepped, stepper_steps) = run_case(case, "stepper", globals);
+        evaluator_total += evaluator_steps;
+        stepper_total += stepper_steps;
+        let agree = match (&evaluated, &stepped) {
+            (Ok(evaluated), Ok(stepped)) => evaluated == stepped,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        if !agree {
+            disagreements += 1;
+        }
+        println!(
+            "{:>10} {:>10} {:>7}  {}{}",
+            evaluator_steps,
+            stepper_steps,
+            ratio(stepper_steps, evaluator_steps),
+            case_name(case),
+            if agree { "" } else { "  differs" }
+        );
+    }
+    println!(
+        "{:>10} {:>10} {:>7}  total",
+        evaluator_total,
+        stepper_total,
+        ratio(stepper_total, evaluator_total)
+    );
+    if disagreements > 0 {
+        println!("The engines disagree on {} cases", disagreements);
+        std::process::exit(1);
+    }
+    println!("The engines agree on all {} cases", cases.len());
+}
+
+fn ratio(steps: u64, base: u64) -> String {
+    if base == 0 {
+        return "-".to_string();
+    }
+    format!("{:.2}", steps as f64 / base as f64)
+}
+
+// Random generator of well-formed programs. Generated programs only use
+// what the evaluator supports, so they always evaluate to a number.
+struct Generator {
+    rng: Rng,
+    // Allow immediately applied lambdas
+    lambdas: bool,
+    // Allow cond expressions
+    conds: bool,
+}
+
+impl Generator {
+    // Generate an expression of roughly `size` nodes using the variables in `scope`
+    fn expr(&mut self, size: usize, scope: &[String]) -> Expr {
+        if size <= 1 {
+            return self.leaf(scope);
+        }
+        let mut choices = vec!["arith"];
+        if self.lambdas {
+            choices.push("lambda");
+        }
+        if self.conds && size >= 6 {
+            choices.push("cond");
+        }
+        match choices[self.rng.below(choices.len())] {
+            "lambda" => self.lambda(size, scope),
+            "cond" => self.cond(size, scope),
+            _ => {
+                let operator = ["add", "sub", "mul"][self.rng.below(3)];
+                let count = 2 + self.rng.below(2);
+                let mut items = vec![Expr::Identifier(operator.into())];
+                for _ in 0..count {
+                    items.push(self.expr((size - 1) / count, scope));
+                }
+                Expr::Application(items)
+            }
+        }
+    }
+
+    // A number literal or a variable in scope
+    fn leaf(&mut self, scope: &[String]) -> Expr {
+        if !scope.is_empty() && self.rng.below(2) == 0 {
+            Expr::Identifier(scope[self.rng.below(scope.len())].as_str().into())
+        } else {
+            Expr::Number(self.rng.below(10) as i64)
+        }
+    }
+
+    // `λ(p0, ...) { body }(n0, ...)`; parameters are bound to number
+    // literals because variables can only hold numbers
+    fn lambda(&mut self, size: usize, scope: &[String]) -> Expr {
+        let count = 1 + self.rng.below(3);
+        let mut inner = scope.to_vec();
+        let mut parameters = Vec::new();
+        for _ in 0..count {
+            let name = format!("p{}", inner.len());
+            inner.push(name.clone());
+            parameters.push(name);
+        }
+        let body = self.expr(size.saturating_sub(count + 2), &inner);
+        let mut items = vec![Expr::Lambda(parameters, [body].into(), Site::default())];
+        for _ in 0..count {
+            items.push(Expr::Number(self.rng.below(10) as i64));
+        }
+        Expr::Application(items)
+    }
+
+    // A cond whose last clause is `true`, so it always produces a value
+    fn cond(&mut self, size: usize, scope: &[String]) -> Expr {
+        let count = 1 + self.rng.below(2);
+        let part = size / (2 * count + 1);
+        let mut clauses = Vec::new();
+        for _ in 0..count {
+            let operator = ["=", "<", "<=", ">", ">="][self.rng.below(5)];
+            let test = Expr::Application(vec![
+                Expr::Identifier(operator.into()),
+                self.expr(part / 2, scope),
+                self.expr(part / 2, scope),
+            ]);
+            clauses.push((test, self.expr(part, scope)));
+        }
+        clauses.push((Expr::Identifier("true".into()), self.expr(part, scope)));
+        Expr::Cond(clauses)
+    }
+}
+
+// `generate [--seed N] [--size N] [--features lambda,cond]`: print a random
+// program as JSON
+fn run_generate(args: &ArgMatches) {
+    let seed = *args.get_one::<u64>("seed").expect("--seed has a default");
+    let size = *args.get_one::<usize>("size").expect("--size has a default");
+    let features = arg(args, "features");
+    let mut generator = Generator {
+        rng: Rng::new(seed),
+        lambdas: false,
+        conds: false,
+    };
+    for feature in features.split(',').filter(|feature| !feature.is_empty()) {
+        match feature {
+            "lambda" => generator.lambdas = true,
+            "cond" => generator.conds = true,
+            _ => panic!("Unknown feature: {} (expected lambda or cond)", feature),
+        }
+    }
+    let program = generator.expr(size, &[]);
+    println!("{}", program.to_json());
+}
+
+// A top-level definition: a parameter of a lambda that is applied right
+// away, like `a` in `λ(a) { ... }(1)`, and the argument bound to it.
+// Definitions of the same lambda share a level and can't see each other.
+#[derive(Clone)]
+pub(crate) struct Definition<'a> {
+    pub(crate) name: String,
+    pub(crate) expr: &'a Expr,
+    pub(crate) level: usize,
+}
+
+// Split `λ(a, b) { λ(c) { body }(e3) }(e1, e2)` into the definitions of a,
+// b and c and the block `body`. A program of another shape has no
+// definitions and is the block itself. Both are parts of the program
+// rather than copies, so an error in them is found in it (see
+// `failure_location`).
+pub(crate) fn split_definitions(program: &Expr) -> (Vec<Definition<'_>>, &[Expr]) {
+    let mut definitions = Vec::new();
+    let mut block = std::slice::from_ref(program);
+    let mut level = 0;
+    while let [Expr::Application(items)] = block {
+        let Some(Expr::Lambda(parameters, body, _)) = items.first() else {
+            break;
+        };
+        if parameters.len() != items.len() - 1 {
+            break;
+        }
+        for (name, expr) in parameters.iter().zip(&items[1..]) {
+            definitions.push(Definition {
+                name: name.clone(),
+                expr,
+                level,
+            });
+        }
+        level += 1;
+        block = body;
+    }
+    (definitions, block)
+}
+
+// What the last evaluation knew about a definition: its structural hash,
+// the levels of the definitions its free variables referred to (None for
+// globals and builtins) and its value
+struct CachedDefinition {
+    hash: u64,
+    dependencies: Vec<(String, Option<usize>)>,
+    value: ResultValue,
+}
+
+// The definitions of the last evaluation, by name and level
+#[derive(Default)]
+struct DefinitionCache {
+    entries: HashMap<(String, usize), CachedDefinition>,
+}
+
+// Evaluate the definitions level by level and then the block, like a
+// build system: a definition keeps its cached value unless it changed
+// structurally, one of the definitions it refers to was evaluated again,
+// or one of its variables now refers to another definition. Returns the
+// result, the names of the definitions that were evaluated and the
+// environment the block was evaluated in.
+fn evaluate_definitions(
+    definitions: &[Definition],
+    block: &[Expr],
+    globals: &Env,
+    cache: &mut DefinitionCache,
+    eval: &mut Evaluation,
+) -> (ResultValue, Vec<String>, Env) {
+    let mut entries = HashMap::new();
+    let mut recomputed: Vec<(String, usize)> = Vec::new();
+    // The level of the innermost definition of every name so far
+    let mut visible: HashMap<String, usize> = HashMap::new();
+    let mut env = globals.clone();
+    let mut index = 0;
+    while index < definitions.len() {
+        let level = definitions[index].level;
+        let mut vars = Vars::default();
+        while index < definitions.len() && definitions[index].level == level {
+            let definition = &definitions[index];
+            let hash = definition.expr.structural_hash();
+            let free = definition.expr.free_variables();
+            let dependencies: Vec<(String, Option<usize>)> = free
+                .iter()
+                .map(|name| (name.clone(), visible.get(name).copied()))
+                .collect();
+            let stale = |cached: &CachedDefinition| {
+                cached.hash != hash
+                    || cached.dependencies != dependencies
+                    || dependencies.iter().any(|(name, level)| {
+                        level.is_some_and(|level| recomputed.contains(&(name.clone(), level)))
+                    })
+            };
+            // Clauses live in the evaluation, so declaring them is never
+            // skipped, but their value doesn't change
+            let declares = free.iter().any(|name| name == "fact" || name == "rule");
+            let key = (definition.name.clone(), level);
+            let value = match cache.entries.remove(&key) {
+                Some(cached) if !stale(&cached) => {
+                    if declares {
+                        evaluate_expr(definition.expr, &env, eval);
+                    }
+                    cached.value
+                }
+                _ => {
+                    recomputed.push(key.clone());
+                    evaluate_expr(definition.expr, &env, eval)
+                }
+            };
+            vars.insert(definition.name.clone(), value.clone());
+            entries.insert(
+                key,
+                CachedDefinition {
+                    hash,
+                    dependencies,
+                    value,
+                },
+            );
+            index += 1;
+        }
+        for name in vars.keys() {
+            visible.insert(name.to_string(), level);
+        }
+        env = Env::frame(eval.new_frame_id(), vars, Some(env));
+        // The next level is in the block of this one's lambda
+        if eval.version.block_scoping() && index < definitions.len() {
+            env = block_scope(&env, eval);
+        }
+    }
+    cache.entries = entries;
+    let result = evaluate_block(block, &env, eval);
+    (
+        result,
+        recomputed.into_iter().map(|(name, _)| name).collect(),
+        env,
+    )
+}
+
+// What evaluates the program, from `--engine`: the tree-walking evaluator,
+// the substitution stepper printing every reduction, like `--step`, or the
+// evaluator with independent definitions on threads, like `--parallel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Engine {
+    Evaluator,
+    Stepper,
+    Parallel,
+}
+
+impl Engine {
+    fn parse(name: &str) -> Result<Engine, String> {
+        match name {
+            "evaluator" => Ok(Engine::Evaluator),
+            "stepper" => Ok(Engine::Stepper),
+            "parallel" => Ok(Engine::Parallel),
+            _ => Err(format!(
+                "Unknown engine: {} (expected evaluator, stepper or parallel)",
+                name
+            )),
+        }
+    }
+}
+
+// How `--dump-env` writes the top-level definitions when the program ends,
+// to stdout or a file: as a JSON object from each name to its value on one
+// line, or indented
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EnvDump {
+    Json,
+    Pretty,
+}
+
+impl EnvDump {
+    fn parse(name: &str) -> Result<EnvDump, String> {
+        match name {
+            "json" => Ok(EnvDump::Json),
+            "pretty" => Ok(EnvDump::Pretty),
+            _ => Err(format!(
+                "Unknown env dump format: {} (expected json or pretty)",
+                name
+            )),
+        }
+    }
+
+    // The value every definition has in `env`, where an inner definition
+    // shadows an outer one of the same name. Values are written as
+    // `--display-style json` writes them, so a closure is summarized by its
+    // parameters, captured variables and path rather than its body.
+    fn render(self, definitions: &[Definition], env: &Env) -> String {
+        let bindings: serde_json::Map<String, Value> = definitions
+            .iter()
+            .map(|definition| {
+                let value = env.get(&definition.name).expect("Definition not bound");
+                (definition.name.clone(), value.to_display_json())
+            })
+            .collect();
+        let bindings = Value::Object(bindings);
+        match self {
+            EnvDump::Json => bindings.to_string(),
+            EnvDump::Pretty => serde_json::to_string_pretty(&bindings).unwrap(),
+        }
+    }
+}
+
+// Where `--up-to` keeps the environment after every cell
+const NOTEBOOK_CACHE: &str = ".notebook-cache.json";
+
+impl ResultValue {
+    // JSON for a notebook snapshot. Parsers and documents can't be saved.
+    // `open` holds the frames being written, outermost first, which a
+    // closure bound in one of them refers to by position instead of
+    // writing it again.
+    fn snapshot_in(&self, open: &mut Vec<usize>) -> Option<Value> {
+        Some(match self {
+            ResultValue::Number(number) => serde_json::json!({ "Number": number }),
+            ResultValue::Float(float) => serde_json::json!({ "Float": float }),
+            ResultValue::String(string) => serde_json::json!({ "String": string }),
+            ResultValue::Bool(value) => serde_json::json!({ "Bool": value }),
+            ResultValue::Unit => serde_json::json!("Unit"),
+            ResultValue::Lambda(parameters, body, env, _) => serde_json::json!({
+                "Lambda": {
+                    "parameters": parameters,
+                    "body": body.iter().map(Expr::to_json).collect::<Vec<Value>>(),
+                    "env": env.snapshot_in(open)?,
+                }
+            }),
+            ResultValue::Contract(contract) => {
+                let (pre, post, function) = contract.as_ref();
+                serde_json::json!({
+                    "Contract": [
+                        pre.snapshot_in(open)?,
+                        post.snapshot_in(open)?,
+                        function.snapshot_in(open)?,
+                    ]
+                })
+            }
+            ResultValue::Parser(_)
+            | ResultValue::Doc(_)
+            | ResultValue::Iterator(_)
+            | ResultValue::Array(_)
+            | ResultValue::Set(_)
+            | ResultValue::Dict(_)
+            | ResultValue::Bytes(_)
+            | ResultValue::DateTime(_) => return None,
+        })
+    }
+
+    // `open` holds the frames being read, like in `snapshot_in`
+    fn restore_in(json: &Value, open: &mut Vec<Env>) -> Result<ResultValue, String> {
+        let invalid = || format!("Invalid value in snapshot: {}", json);
+        if let Some(number) = json.get("Number") {
+            return number.as_i64().map(ResultValue::Number).ok_or_else(invalid);
+        }
+        if let Some(float) = json.get("Float") {
+            return float.as_f64().map(ResultValue::Float).ok_or_else(invalid);
+        }
+        if let Some(string) = json.get("String") {
+            let string = string.as_str().ok_or_else(invalid)?;
+            return Ok(ResultValue::String(string.to_string()));
+        }
+        if let Some(value) = json.get("Bool") {
+            return value.as_bool().map(ResultValue::Bool).ok_or_else(invalid);
+        }
+        if json == "Unit" {
+            return Ok(ResultValue::Unit);
+        }
+        if let Some(lambda) = json.get("Lambda") {
+            let parameters =
+                serde_json::from_value(lambda["parameters"].clone()).map_err(|_| invalid())?;
+            let body = lambda["body"]
+                .as_array()
+                .ok_or_else(invalid)?
+                .iter()
+                .map(Expr::from_json)
+                .collect::<Result<Vec<Expr>, String>>()?;
+            let env = Env::restore_in(&lambda["env"], open)?;
+            return Ok(ResultValue::Lambda(parameters, body.into(), env, None));
+        }
+        if let Some(Value::Array(parts)) = json.get("Contract") {
+            let parts = parts
+                .iter()
+                .map(|part| ResultValue::restore_in(part, open))
+                .collect::<Result<Vec<ResultValue>, String>>()?;
+            let [pre, post, function] =
+                <[ResultValue; 3]>::try_from(parts).map_err(|_| invalid())?;
+            return Ok(ResultValue::Contract(Box::new((pre, post, function))));
+        }
+        Err(invalid())
+    }
+}
+
+impl Env {
+    pub(crate) fn to_snapshot(&self) -> Option<Value> {
+        self.snapshot_in(&mut Vec::new())
+    }
+
+    // A frame that is being written already, because a closure bound in it
+    // captured it, like a global function, is written as `{"open": N}`, its
+    // position in `open`. The parent comes first, so only frames it is
+    // inside of are open while it is written.
+    fn snapshot_in(&self, open: &mut Vec<usize>) -> Option<Value> {
+        let key = self.key();
+        if let Some(position) = open.iter().position(|frame| *frame == key) {
+            return Some(serde_json::json!({ "open": position }));
+        }
+        let parent = match &self.parent {
+            Some(parent) => parent.snapshot_in(open)?,
+            None => Value::Null,
+        };
+        open.push(key);
+        let mut vars = serde_json::Map::new();
+        for (name, value) in self.vars().iter() {
+            match value.snapshot_in(open) {
+                Some(value) => vars.insert(name.to_string(), value),
+                None => {
+                    open.pop();
+                    return None;
+                }
+            };
+        }
+        open.pop();
+        Some(serde_json::json!({
+            "id": self.id,
+            "vars": vars,
+            "parent": parent,
+            "recursive": self.recursive,
+        }))
+    }
+
+    pub(crate) fn from_snapshot(json: &Value) -> Result<Env, String> {
+        Env::restore_in(json, &mut Vec::new())
+    }
+
+    fn restore_in(json: &Value, open: &mut Vec<Env>) -> Result<Env, String> {
+        let invalid = || format!("Invalid environment in snapshot: {}", json);
+        if let Some(position) = json.get("open") {
+            let position = position.as_u64().ok_or_else(invalid)? as usize;
+            return open.get(position).cloned().ok_or_else(invalid);
+        }
+        let parent = match &json["parent"] {
+            Value::Null => None,
+            parent => Some(Env::restore_in(parent, open)?),
+        };
+        let id = json["id"].as_u64().ok_or_else(invalid)? as usize;
+        let mut env = Env::frame(id, Vars::default(), parent);
+        env.recursive = json["recursive"].as_bool().unwrap_or(false);
+        // The bindings go in once the frame is open, for closures to refer to
+        open.push(env.clone());
+        let bindings = json["vars"].as_object().ok_or_else(invalid);
+        let restored = bindings.and_then(|bindings| {
+            bindings
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), ResultValue::restore_in(value, open)?)))
+                .collect::<Result<Vec<(String, ResultValue)>, String>>()
+        });
+        open.pop();
+        for (name, value) in restored? {
+            env.vars_mut().insert(name, value);
+        }
+        Ok(env)
+    }
+}
+
+// `--up-to N`: treat the levels of top-level definitions as numbered
+// notebook cells, with the rest of the program as the last cell. Restore
+// the environment after cell N - 1 from the snapshots in NOTEBOOK_CACHE,
+// evaluating the earlier cells only when no snapshot matches them, then
+// evaluate cell N and print its bindings or result.
+fn run_cells(program: &Expr, up_to: usize, globals: &Env, version: LangVersion) {
+    let (definitions, block) = split_definitions(program);
+    let mut cells: Vec<Vec<Definition>> = Vec::new();
+    for definition in definitions {
+        if cells.len() == definition.level {
+            cells.push(Vec::new());
+        }
+        cells[definition.level].push(definition);
+    }
+    let last = cells.len() + 1;
+    if up_to == 0 || up_to > last {
+        panic!("--up-to needs a cell from 1 to {}", last);
+    }
+
+    // A snapshot is valid while the cells up to it are unchanged
+    let mut hashes = vec![0];
+    let mut hasher = Fnv64::new();
+    for cell in &cells {
+        for definition in cell {
+            hasher.write(definition.name.as_bytes());
+            hasher.write_u64(definition.expr.structural_hash());
+        }
+        hashes.push(hasher.0);
+    }
+    let mut snapshots: HashMap<usize, Value> = std::fs::read_to_string(NOTEBOOK_CACHE)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Vec<(usize, Value)>>(&contents).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(cell, snapshot)| {
+            hashes
+                .get(*cell)
+                .is_some_and(|hash| snapshot["hash"].as_u64() == Some(*hash))
+        })
+        .collect();
+
+    let restored = (1..up_to).rev().find_map(|cell| {
+        Some((
+            cell,
+            Env::from_snapshot(&snapshots.get(&cell)?["env"]).ok()?,
+        ))
+    });
+    let (mut cell, mut env) = restored.unwrap_or((0, globals.clone()));
+    if cell > 0 {
+        eprintln!("Restored the environment after cell {}", cell);
+    }
+    let mut eval = Evaluation::new(version);
+    while cell + 1 < last && cell < up_to {
+        let mut vars = Vars::default();
+        for definition in &cells[cell] {
+            let value = evaluate_expr(definition.expr, &env, &mut eval);
+            if cell + 1 == up_to {
+                println!("{} = {}", definition.name, value);
+            }
+            vars.insert(definition.name.clone(), value);
+        }
+        env = Env::frame(eval.new_frame_id(), vars, Some(env));
+        cell += 1;
+        if let Some(snapshot) = env.to_snapshot() {
+            snapshots.insert(
+                cell,
+                serde_json::json!({ "hash": hashes[cell], "env": snapshot }),
+            );
+        }
+    }
+    if up_to == last {
+        let result = evaluate_block(block, &env, &mut eval);
+        if !matches!(result, ResultValue::Unit) {
+            println!("{}", result);
+        }
+    }
+
+    let mut snapshots: Vec<(usize, Value)> = snapshots.into_iter().collect();
+    snapshots.sort_by_key(|(cell, _)| *cell);
+    std::fs::write(NOTEBOOK_CACHE, serde_json::to_string(&snapshots).unwrap())
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", NOTEBOOK_CACHE, err));
+}
+
+// `--watch program.json`: evaluate the program again whenever the file
+// changes, until interrupted. What was recomputed goes to stderr, and the
+// result to stdout unless `quiet`.
+fn run_watch(path: &str, globals: &Env, version: LangVersion, quiet: bool) {
+    let mut cache = DefinitionCache::default();
+    let mut modified = None;
+    loop {
+        let current = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let Ok(current) = current {
+            if modified != Some(current) {
+                modified = Some(current);
+                eprintln!("--- {}", path);
+                // Errors are reported by the panic and watching goes on
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    let program = load_program(path);
+                    let (definitions, block) = split_definitions(&program);
+                    let mut eval = Evaluation::new(version);
+                    let (result, recomputed, _) =
+                        evaluate_definitions(&definitions, block, globals, &mut cache, &mut eval);
+                    eprintln!("recomputed: {}", recomputed.join(", "));
+                    if !quiet && !matches!(result, ResultValue::Unit) {
+                        println!("{}", result);
+                    }
+                }));
+            }
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+// `serve [--port N] [--workers N] [--idle-timeout SECONDS]
+// [--max-bindings N] [--loop-limit N] [--rate-limit N] [--fuel-quota N]
+// [--audit-log FILE] [--audit-max-bytes N] [--io-timeout SECONDS]
+// [--max-body-bytes N] [--lang-version V]`
+fn run_serve(args: &ArgMatches) {
+    let number = |name: &str| -> u64 { *args.get_one::<u64>(name).expect("Limits have defaults") };
+    let options = server::ServerOptions {
+        port: number("port") as u16,
+        workers: number("workers") as usize,
+        idle_timeout: Duration::from_secs(number("idle-timeout")),
+        max_bindings: number("max-bindings") as usize,
+        loop_limit: number("loop-limit") as usize,
+        version: LangVersion::parse(arg(args, "lang-version"))
+            .unwrap_or_else(|err| panic!("{}", err)),
+        rate_limit: number("rate-limit") as usize,
+        fuel_quota: number("fuel-quota"),
+        audit_log: args.get_one::<String>("audit-log").cloned(),
+        audit_max_bytes: number("audit-max-bytes"),
+        io_timeout: Duration::from_secs(number("io-timeout")),
+        max_body_bytes: number("max-body-bytes") as usize,
+    };
+    server::serve(options, default_globals());
+}
+
+// `repl [--backend tree|vm] [--syntax json|sexpr] [--lang-version V]`:
+// evaluate the program on each line of stdin and print its value.
+// `(define name expr)` binds a global for the lines after it instead. A
+// failing line only reports its error. The vm backend compiles every line
+// to bytecode linked against the lines before it (see `vm.rs`).
+fn run_repl(args: &ArgMatches) {
+    let version =
+        LangVersion::parse(arg(args, "lang-version")).unwrap_or_else(|err| panic!("{}", err));
+    let sexpr = arg(args, "syntax") == "sexpr";
+    let mut globals = default_globals();
+    let mut eval = Evaluation::new(version);
+    let mut vm = (arg(args, "backend") == "vm").then(|| Vm::new(&globals, version));
+    let interactive = io::stdin().is_terminal();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            eprint!("> ");
+        }
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+        // Comments too, in s-expressions
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let parsed = if sexpr {
+            sexpr::parse(line).map(|(program, _)| program)
+        } else {
+            serde_json::from_str(line)
+                .map_err(|err| err.to_string())
+                .and_then(|json| Expr::from_json(&json))
+        };
+        let program = match parsed {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let (name, program) = match &program {
+            Expr::Application(items) => match items.as_slice() {
+                [Expr::Identifier(define), Expr::Identifier(name), value] if define == "define" => {
+                    (Some(*name), value)
+                }
+                _ => (None, &program),
+            },
+            _ => (None, &program),
+        };
+        let program = resolve_locals(program, version);
+        let evaluated = catch_silently(|| match &mut vm {
+            Some(vm) => vm.run(&program, &mut eval),
+            None => evaluate_expr(&program, &globals, &mut eval),
+        });
+        match (evaluated, name) {
+            (Err(payload), _) => {
+                eprintln!("{}", panic_message(&payload));
+                eval.recover();
+            }
+            (Ok(value), Some(name)) => globals.define(&name, value),
+            (Ok(ResultValue::Unit), None) => {}
+            (Ok(value), None) => println!("{}", value),
+        }
+    }
+}
+
+// The value of an argument that is required or has a default
+fn arg<'a>(args: &'a ArgMatches, name: &str) -> &'a str {
+    args.get_one::<String>(name)
+        .unwrap_or_else(|| panic!("No value for {}", name))
+}
+
+// The command line. Each subcommand takes the arguments declared for it;
+// `eval`, which runs when no subcommand is given, has the flags declared in
+// `eval_command`.
+fn command() -> Command {
+    let path = |name: &'static str, help: &'static str| Arg::new(name).required(true).help(help);
+    let number = |name: &'static str, default: &'static str, help: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .value_name("N")
+            .value_parser(clap::value_parser!(u64))
+            .default_value(default)
+            .help(help)
+    };
+    let lang_version = Arg::new("lang-version")
+        .long("lang-version")
+        .value_parser(["cp3", "cp4"])
+        .default_value("cp3")
+        .help("The language version programs are read and evaluated as");
+    Command::new("interpreter")
+        .about("Evaluate programs of the course language, and tools around them")
+        .subcommand(eval_command(lang_version.clone()))
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two programs node by node; exits with 1 when they differ")
+                .arg(path("old", "The old program, as JSON"))
+                .arg(path("new", "The new program, as JSON")),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Print size and shape metrics of a program")
+                .arg(path("program", "The program, as JSON")),
+        )
+        .subcommand(
+            Command::new("generate")
+                .about("Print a random program as JSON")
+                .arg(number("seed", "0", "Seed of the random generator"))
+                .arg(
+                    number("size", "20", "Nodes the program has at most")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("features")
+                        .long("features")
+                        .default_value("lambda,cond")
+                        .help("What programs may use, of lambda and cond"),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Evaluate programs sent over HTTP, in sessions")
+                .arg(number("port", "7878", "Port to listen on"))
+                .arg(number("workers", "4", "Connections handled at once"))
+                .arg(number(
+                    "idle-timeout",
+                    "600",
+                    "Seconds before an idle session ends",
+                ))
+                .arg(number(
+                    "max-bindings",
+                    "100",
+                    "Globals a session may define",
+                ))
+                .arg(number(
+                    "loop-limit",
+                    "3",
+                    "Repeats of a call before it is a loop",
+                ))
+                .arg(number(
+                    "rate-limit",
+                    "60",
+                    "Requests per minute by one session",
+                ))
+                .arg(number(
+                    "fuel-quota",
+                    "1000000",
+                    "Steps a session may take in all",
+                ))
+                .arg(
+                    Arg::new("audit-log")
+                        .long("audit-log")
+                        .value_name("FILE")
+                        .help("Append a line for every evaluation to FILE"),
+                )
+                .arg(number(
+                    "audit-max-bytes",
+                    "10000000",
+                    "Size of the audit log before it rotates",
+                ))
+                .arg(number(
+                    "io-timeout",
+                    "5",
+                    "Seconds to wait for a client to send or read",
+                ))
+                .arg(number("max-body-bytes", "1000000", "Largest request body"))
+                .arg(lang_version.clone()),
+        )
+        .subcommand(
+            Command::new("cost")
+                .about("Check the step counts of a program against asymptotic budgets")
+                .arg(path(
+                    "manifest",
+                    "The program, the input sizes and the budgets, as JSON",
+                )),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Check a property with generated inputs")
+                .arg(path("property", "The property and its parameters, as JSON")),
+        )
+        .subcommand(
+            Command::new("conformance")
+                .about("Run a suite of cases with an engine; exits with 1 when one fails")
+                .arg(path("suite", "The cases, as JSON"))
+                .arg(
+                    Arg::new("engine")
+                        .long("engine")
+                        .value_parser(["evaluator", "stepper"])
+                        .default_value("evaluator")
+                        .help("The engine to run the cases with"),
+                )
+                .arg(
+                    Arg::new("fuel")
+                        .long("fuel")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("engine")
+                        .help("Compare the steps both engines take instead"),
+                ),
+        )
+        .subcommand(
+            Command::new("repl")
+                .about("Evaluate a program per line of stdin, with the definitions made so far")
+                .arg(
+                    Arg::new("backend")
+                        .long("backend")
+                        .value_parser(["tree", "vm"])
+                        .default_value("tree")
+                        .help("Evaluate with the tree-walking evaluator or the bytecode VM"),
+                )
+                .arg(
+                    Arg::new("syntax")
+                        .long("syntax")
+                        .value_parser(["json", "sexpr"])
+                        .default_value("json")
+                        .help("How the programs are written"),
+                )
+                .arg(lang_version.clone()),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Turn a directory of s-expression exercises into a conformance corpus")
+                .arg(path("dir", "The directory of .scm files"))
+                .arg(path("out", "Where the programs and conformance.json go"))
+                .arg(lang_version),
+        )
+}
+
+// The flags of `eval`, grouped in its help by what they are for. A flag
+// given again replaces the earlier value, except the ones that change the
+// builtins or set breakpoints, which add up in the order they are given
+// (see `env_builder` and `breakpoints`).
+fn eval_command(lang_version: Arg) -> Command {
+    let flag = |name: &'static str, help: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .action(ArgAction::SetTrue)
+            .help(help)
+    };
+    let value = |name: &'static str, value_name: &'static str, help: &'static str| {
+        Arg::new(name).long(name).value_name(value_name).help(help)
+    };
+    let number = |name: &'static str, value_name: &'static str, help: &'static str| {
+        value(name, value_name, help).value_parser(clap::value_parser!(usize))
+    };
+    let repeated = |name: &'static str, value_name: &'static str, help: &'static str| {
+        value(name, value_name, help).action(ArgAction::Append)
+    };
+    let engines = [
+        PossibleValue::new("evaluator").help("The tree-walking evaluator"),
+        PossibleValue::new("stepper").help("The substitution stepper, printing every reduction"),
+        PossibleValue::new("parallel")
+            .help("The evaluator, with independent definitions on threads"),
+    ];
+    Command::new("eval")
+        .about("Evaluate the program on stdin (the default without a subcommand)")
+        .args_override_self(true)
+        .next_help_heading("Input")
+        .arg(
+            value("syntax", "SYNTAX", "How the program is written")
+                .value_parser(["json", "sexpr"])
+                .default_value("json"),
+        )
+        .arg(lang_version)
+        .arg(
+            value("error-format", "FORMAT", "How invalid JSON input is reported")
+                .value_parser(["text", "json"])
+                .default_value("text"),
+        )
+        .arg(value(
+            "rpc",
+            "FILE",
+            "Evaluate the program in FILE under a debugger driven over stdin and stdout",
+        ))
+        .arg(value(
+            "watch",
+            "FILE",
+            "Evaluate the program in FILE again every time it changes",
+        ))
+        .arg(number(
+            "up-to",
+            "N",
+            "Evaluate the levels of definitions up to N, reusing the ones saved before",
+        ))
+        .next_help_heading("Instead of evaluating")
+        .arg(flag("list-nodes", "Print every node with its id and path"))
+        .arg(value("show-node", "PATH", "Print the node at PATH as JSON").value_parser(NodePath::parse))
+        .arg(flag(
+            "dump-opt",
+            "Print the nodes of the optimized program and where they came from",
+        ))
+        .arg(flag("dump-resolved", "Print how every variable is looked up"))
+        .arg(flag("dump-closure-conv", "Print the program after closure conversion"))
+        .arg(flag("dump-debruijn", "Print the program with de Bruijn indices"))
+        .arg(number("pretty", "WIDTH", "Print the program within WIDTH columns"))
+        .arg(flag("analyze", "Report what an interval analysis finds in the program"))
+        .arg(flag(
+            "compare-strategies",
+            "Evaluate by value, by name and by need, and compare the steps",
+        ))
+        .next_help_heading("Evaluation")
+        .arg(
+            value("engine", "ENGINE", "What evaluates the program")
+                .value_parser(engines)
+                .default_value("evaluator")
+                .overrides_with_all(["step", "parallel"]),
+        )
+        .arg(flag("step", "Like --engine stepper").overrides_with_all(["engine", "parallel"]))
+        .arg(flag("parallel", "Like --engine parallel").overrides_with_all(["engine", "step"]))
+        .arg(flag(
+            "opt",
+            "Fold constants and drop dead cond clauses before evaluating",
+        ))
+        .arg(
+            value("max-steps", "N", "Fail after N steps, or stop the stepper after N")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(number(
+            "loop-limit",
+            "N",
+            "Fail when a call is repeated N times with the same arguments",
+        ))
+        .arg(flag("strict", "Fail on unbound names instead of printing them"))
+        .arg(
+            value("overflow", "MODE", "What arithmetic does with a result out of range")
+                .value_parser(["error", "wrap", "saturate", "promote-float"])
+                .default_value("error"),
+        )
+        .arg(number("precision", "DIGITS", "Digits after the point of printed floats"))
+        .arg(
+            value(
+                "randomize-eval-order",
+                "SEED",
+                "Evaluate arguments in a random order",
+            )
+            .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            value("prelude", "NAME", "Define the functions of a prelude first")
+                .value_parser(["church", "lists"]),
+        )
+        .arg(flag(
+            "pure-lambda",
+            "Allow only lambdas, applications and variables, and print the normal form",
+        ))
+        .arg(flag(
+            "check-purity",
+            "Reject the program when it passes a function with effects where a pure one is expected",
+        ))
+        .next_help_heading("Builtins")
+        .arg(
+            repeated("allow", "CAPABILITY", "Let the program use a capability")
+                .value_parser(["files", "clock", "draw"]),
+        )
+        .arg(flag(
+            "sandbox",
+            "Take away the builtins that reach outside the program",
+        ))
+        .arg(repeated("without-builtin", "NAME", "Remove a builtin"))
+        .arg(
+            repeated(
+                "override-builtin",
+                "NAME=HOST",
+                "Replace a builtin with a host implementation",
+            )
+            .value_parser(parse_override),
+        )
+        .arg(repeated(
+            "world",
+            "FIXTURE",
+            "Replace the I/O builtins with world-passing versions starting from FIXTURE",
+        ))
+        .arg(value("drawing", "FILE", "Where the turtle drawing goes").default_value("drawing.svg"))
+        .next_help_heading("Output")
+        .arg(flag("quiet", "Leave out the result"))
+        .arg(
+            value("display-style", "STYLE", "How the result is printed")
+                .value_parser(["plain", "debug", "json"])
+                .default_value("plain"),
+        )
+        .arg(
+            value(
+                "dump-env",
+                "FORMAT[=FILE]",
+                "Write the top-level definitions as json or pretty JSON, to stdout or FILE",
+            )
+            .value_parser(parse_env_dump),
+        )
+        .arg(value(
+            "env-diagram",
+            "FILE",
+            "Write the environment diagram, as JSON or Graphviz",
+        ))
+        .arg(value(
+            "flamegraph",
+            "FILE",
+            "Write the time spent in each function, folded for flame graphs",
+        ))
+        .arg(flag("heap-stats", "Print how many values of each kind were made"))
+        .arg(flag("time-nodes", "Print how long the evaluation of each node took"))
+        .arg(flag("report", "Print a summary of the run"))
+        .arg(flag("explain-lookups", "Print where every variable was found"))
+        .arg(value("event-log", "FILE", "Write the evaluation to FILE as JSON lines"))
+        .next_help_heading("Debugging")
+        .arg(repeated("break-on", "NAME", "Pause before every call of NAME"))
+        .arg(
+            repeated(
+                "break-when",
+                "EXPR",
+                "Make the preceding --break-on conditional on EXPR, written as JSON",
+            )
+            .value_parser(parse_condition)
+            .requires("break-on"),
+        )
+        .arg(flag(
+            "post-mortem",
+            "Show the frames of a failing evaluation and evaluate in them",
+        ))
+}
+
+// `NAME=HOST` of `--override-builtin`: the builtin to replace and the host
+// implementation that takes its place
+fn parse_override(spec: &str) -> Result<(String, HostBuiltin), String> {
+    let (name, host) = spec
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=HOST".to_string())?;
+    match HOST_BUILTINS.iter().find(|(known, _)| *known == host) {
+        Some((_, host)) => Ok((name.to_string(), *host)),
+        None => {
+            let known: Vec<&str> = HOST_BUILTINS.iter().map(|(known, _)| *known).collect();
+            Err(format!(
+                "Unknown host builtin: {} (expected one of {})",
+                host,
+                known.join(", ")
+            ))
+        }
+    }
+}
+
+// `FORMAT[=FILE]` of `--dump-env`, where FILE gets the dump instead of
+// stdout
+fn parse_env_dump(spec: &str) -> Result<(EnvDump, Option<String>), String> {
+    let (name, file) = match spec.split_once('=') {
+        Some((name, file)) => (name, Some(file.to_string())),
+        None => (spec, None),
+    };
+    Ok((EnvDump::parse(name)?, file))
+}
+
+// The condition of `--break-when`, an expression written as JSON
+fn parse_condition(json: &str) -> Result<Expr, String> {
+    let json: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    Expr::from_json(&json)
+}
+
+pub fn run() {
+    install_panic_hook();
+    let mut args: Vec<String> = std::env::args().collect();
+    // Without a subcommand, the flags are those of `eval`
+    let command = command();
+    let named = args.get(1).is_some_and(|first| {
+        ["help", "-h", "--help"].contains(&first.as_str())
+            || command.get_subcommands().any(|sub| sub.get_name() == first)
+    });
+    if !named {
+        args.insert(1, "eval".to_string());
+    }
+    match command.get_matches_from(args).subcommand() {
+        Some(("diff", args)) => run_diff(args),
+        Some(("stats", args)) => run_stats(args),
+        Some(("generate", args)) => run_generate(args),
+        Some(("serve", args)) => run_serve(args),
+        Some(("cost", args)) => run_cost(args),
+        Some(("check", args)) => run_check(args),
+        Some(("conformance", args)) => run_conformance(args),
+        Some(("import", args)) => run_import(args),
+        Some(("repl", args)) => run_repl(args),
+        Some(("eval", args)) => run_eval(args),
+        _ => unreachable!("Every subcommand is handled"),
+    }
+}
+
+// What the flags of `eval` ask for. The builtins they change are built by
+// `env_builder` instead.
+struct EvalFlags {
+    version: LangVersion,
+    sexpr: bool,
+    error_format: ErrorFormat,
+    rpc_program: Option<String>,
+    watch: Option<String>,
+    up_to: Option<usize>,
+    list_nodes: bool,
+    show_node: Option<NodePath>,
+    dump_opt: bool,
+    dump_resolved: bool,
+    dump_closure_conv: bool,
+    dump_debruijn: bool,
+    pretty_width: Option<usize>,
+    analyze: bool,
+    compare: bool,
+    engine: Engine,
+    opt: bool,
+    max_steps: Option<u64>,
+    loop_limit: Option<usize>,
+    strict: bool,
+    overflow: Overflow,
+    precision: usize,
+    eval_order_seed: Option<u64>,
+    prelude: Option<String>,
+    pure_lambda: bool,
+    check_purity: bool,
+    capabilities: Vec<Capability>,
+    drawing: String,
+    quiet: bool,
+    display_style: DisplayStyle,
+    dump_env: Option<(EnvDump, Option<String>)>,
+    env_diagram: Option<String>,
+    flamegraph: Option<String>,
+    heap_stats: bool,
+    time_nodes: bool,
+    report: bool,
+    explain_lookups: bool,
+    event_log: Option<String>,
+    breakpoints: Vec<Breakpoint>,
+    post_mortem: bool,
+}
+
+impl EvalFlags {
+    // Names with a fixed set of values were checked by clap, so parsing
+    // them again can't fail
+    fn parse(args: &ArgMatches) -> EvalFlags {
+        let flag = |name: &str| args.get_flag(name);
+        let string = |name: &str| args.get_one::<String>(name).cloned();
+        let number = |name: &str| args.get_one::<usize>(name).copied();
+        let engine = if flag("step") {
+            Engine::Stepper
+        } else if flag("parallel") {
+            Engine::Parallel
+        } else {
+            Engine::parse(arg(args, "engine")).unwrap_or_else(|err| panic!("{}", err))
+        };
+        EvalFlags {
+            version: LangVersion::parse(arg(args, "lang-version"))
+                .unwrap_or_else(|err| panic!("{}", err)),
+            sexpr: arg(args, "syntax") == "sexpr",
+            error_format: ErrorFormat::parse(arg(args, "error-format"))
+                .unwrap_or_else(|err| panic!("{}", err)),
+            rpc_program: string("rpc"),
+            watch: string("watch"),
+            up_to: number("up-to"),
+            list_nodes: flag("list-nodes"),
+            show_node: args.get_one::<NodePath>("show-node").cloned(),
+            dump_opt: flag("dump-opt"),
+            dump_resolved: flag("dump-resolved"),
+            dump_closure_conv: flag("dump-closure-conv"),
+            dump_debruijn: flag("dump-debruijn"),
+            pretty_width: number("pretty"),
+            analyze: flag("analyze"),
+            compare: flag("compare-strategies"),
+            engine,
+            opt: flag("opt"),
+            max_steps: args.get_one::<u64>("max-steps").copied(),
+            loop_limit: number("loop-limit"),
+            strict: flag("strict"),
+            overflow: Overflow::parse(arg(args, "overflow"))
+                .unwrap_or_else(|err| panic!("{}", err)),
+            precision: number("precision").unwrap_or(DEFAULT_PRECISION),
+            eval_order_seed: args.get_one::<u64>("randomize-eval-order").copied(),
+            prelude: string("prelude"),
+            pure_lambda: flag("pure-lambda"),
+            check_purity: flag("check-purity"),
+            capabilities: args
+                .get_many::<String>("allow")
+                .into_iter()
+                .flatten()
+                .map(|name| Capability::parse(name).unwrap_or_else(|err| panic!("{}", err)))
+                .collect(),
+            drawing: arg(args, "drawing").to_string(),
+            quiet: flag("quiet"),
+            display_style: DisplayStyle::parse(arg(args, "display-style"))
+                .unwrap_or_else(|err| panic!("{}", err)),
+            dump_env: args
+                .get_one::<(EnvDump, Option<String>)>("dump-env")
+                .cloned(),
+            env_diagram: string("env-diagram"),
+            flamegraph: string("flamegraph"),
+            heap_stats: flag("heap-stats"),
+            time_nodes: flag("time-nodes"),
+            report: flag("report"),
+            explain_lookups: flag("explain-lookups"),
+            event_log: string("event-log"),
+            breakpoints: breakpoints(args),
+            post_mortem: flag("post-mortem"),
+        }
+    }
+}
+
+// Every value of an argument that can be given more than once, with its
+// position on the command line
+fn occurrences<'a, T: Clone + Send + Sync + 'static>(
+    args: &'a ArgMatches,
+    name: &str,
+) -> Vec<(usize, &'a T)> {
+    match (args.indices_of(name), args.get_many::<T>(name)) {
+        (Some(indices), Some(values)) => indices.zip(values).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The globals and builtins with the changes of `--sandbox`,
+// `--without-builtin`, `--override-builtin` and `--world`, made in the order
+// they are given, so that a later one can undo an earlier one
+fn env_builder(args: &ArgMatches) -> EnvBuilder {
+    type Change<'a> = Box<dyn FnOnce(EnvBuilder) -> EnvBuilder + 'a>;
+    let mut changes: Vec<(usize, Change)> = Vec::new();
+    if args.get_flag("sandbox") {
+        let index = args.index_of("sandbox").expect("A given flag has an index");
+        changes.push((index, Box::new(EnvBuilder::sandboxed)));
+    }
+    for (index, name) in occurrences::<String>(args, "without-builtin") {
+        changes.push((index, Box::new(move |builder| builder.remove_builtin(name))));
+    }
+    for (index, (name, host)) in occurrences::<(String, HostBuiltin)>(args, "override-builtin") {
+        changes.push((
+            index,
+            Box::new(move |builder| builder.override_builtin(name, *host)),
+        ));
+    }
+    for (index, path) in occurrences::<String>(args, "world") {
+        // Replace the I/O builtins with their world-passing versions
+        let world = World::load(path).unwrap_or_else(|err| panic!("{}", err));
+        changes.push((
+            index,
+            Box::new(move |builder| {
+                let builder = builder.define("world", world.to_value());
+                ["print", "readBytes", "writeBytes", "nowUtc"]
+                    .into_iter()
+                    .fold(builder, |builder, name| builder.remove_builtin(name))
+            }),
+        ));
+    }
+    changes.sort_by_key(|(index, _)| *index);
+    changes
+        .into_iter()
+        .fold(EnvBuilder::new(), |builder, (_, change)| change(builder))
+}
+
+// The breakpoints of `--break-on`, each with the condition of the last
+// `--break-when` between it and the next one
+fn breakpoints(args: &ArgMatches) -> Vec<Breakpoint> {
+    let mut breakpoints: Vec<(usize, Breakpoint)> = occurrences::<String>(args, "break-on")
+        .into_iter()
+        .map(|(index, name)| {
+            let breakpoint = Breakpoint {
+                name: name.clone(),
+                condition: None,
+            };
+            (index, breakpoint)
+        })
+        .collect();
+    for (index, condition) in occurrences::<Expr>(args, "break-when") {
+        let Some((_, breakpoint)) = breakpoints.iter_mut().rev().find(|(on, _)| *on < index) else {
+            clap::Error::raw(
+                ErrorKind::ArgumentConflict,
+                "--break-when must follow --break-on\n",
+            )
+            .exit();
+        };
+        breakpoint.condition = Some(condition.clone());
+    }
+    breakpoints
+        .into_iter()
+        .map(|(_, breakpoint)| breakpoint)
+        .collect()
+}
+
+// Evaluate the program on stdin, or do what the flags ask with it instead
+fn run_eval(args: &ArgMatches) {
+    let flags = EvalFlags::parse(args);
+    let (mut globals, builtins) = env_builder(args).build();
+    if let Some(path) = &flags.watch {
+        return run_watch(path, &globals, flags.version, flags.quiet);
+    }
+    let (mut program, source_map) = read_program(&flags);
+
+    // Optimize, remembering where every rewritten node came from
+    let mut provenance = Provenance::default();
+    if flags.opt || flags.dump_opt {
+        let vars = globals.vars();
+        let bound: Vec<&str> = vars.keys().map(|name| name.as_str()).collect();
+        let root = NodePath::default();
+        program = optimize(&program, &root, &root, &bound, &mut provenance);
+    }
+    if run_instead(&flags, &program, &provenance, &globals) {
+        return;
+    }
+
+    let mut eval = evaluation(&flags, &mut globals, builtins);
+    check_program(&flags, &program, &globals);
+    // Parallel evaluation binds the definitions in frames of its own.
+    // `--dump-env` makes a frame per level of definitions, like the lambdas
+    // they are the parameters of, so resolved identifiers find them.
+    program = if flags.engine != Engine::Parallel {
+        resolve_locals(&program, flags.version)
+    } else {
+        locate_lambdas(&program)
+    };
+    let start = Instant::now();
+    if let Some(path) = &flags.event_log {
+        eval.event_log = Some(EventLog::new(path, &program));
+    }
+    if flags.time_nodes {
+        eval.node_times = Some(NodeTimes::new(&program));
+    }
+    if flags.rpc_program.is_some() {
+        // Paused before the program until the client continues
+        let mut debugger = rpc::Debugger::new(&program, &globals);
+        eval.output = Output::Sink(Box::new(rpc::RpcOutput));
+        eval.call_stack = Some(Vec::new());
+        debugger.serve(None, &eval);
+        eval.debugger = Some(debugger);
+    }
+    // A failed evaluation is reported with what went wrong and where, then
+    // logged and opened in the post-mortem REPL, and exits with 1. Other
+    // panics are bugs of the interpreter and passed on.
+    let mut dumped = None;
+    let evaluated = catch_silently(|| {
+        if let Some((format, _)) = flags.dump_env {
+            // One definition after another, to keep the frame they end up in
+            let (definitions, block) = split_definitions(&program);
+            let mut cache = DefinitionCache::default();
+            let (result, _, env) =
+                evaluate_definitions(&definitions, block, &globals, &mut cache, &mut eval);
+            dumped = Some(format.render(&definitions, &env));
+            result
+        } else if flags.engine == Engine::Parallel {
+            evaluate_parallel(&program, &globals, &mut eval)
+        } else {
+            evaluate_expr(&program, &globals, &mut eval)
+        }
+    });
+    if flags.rpc_program.is_some() {
+        let end = match &evaluated {
+            Ok(result) => serde_json::json!({"event": "terminated", "result": result.to_string()}),
+            Err(payload) => {
+                serde_json::json!({"event": "terminated", "error": panic_message(payload)})
+            }
+        };
+        return rpc::send(end);
+    }
+    let result = match evaluated {
+        Ok(result) => result,
+        Err(payload) => {
+            let Some(error) = payload.downcast_ref::<EvalError>() else {
+                std::panic::resume_unwind(payload);
+            };
+            let path = failure_location(&program)
+                .map(|path| provenance.original(&path).cloned().unwrap_or(path));
+            report_failure(error, path, &source_map, &mut eval, flags.version);
+        }
+    };
+    if !flags.quiet {
+        print_result(&flags, &result);
+    }
+    write_reports(&flags, eval, start, dumped);
+}
+
+// The program, from stdin or the file of `--rpc`, whose protocol is on
+// stdin then. It is JSON, or an s-expression with `--syntax sexpr`.
+fn read_program(flags: &EvalFlags) -> (Expr, SourceMap) {
+    let input = match &flags.rpc_program {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err)),
+        None => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .expect("Failed to read input");
+            input
+        }
+    };
+    if flags.sexpr {
+        sexpr::parse(&input).unwrap_or_else(|err| panic!("{}", err))
+    } else {
+        let json_input = read_json(&input, "stdin", flags.error_format);
+        let program = Expr::from_json(&json_input).unwrap_or_else(|err| panic!("{}", err));
+        (program, SourceMap::default())
+    }
+}
+
+// Do what the flags ask with the program instead of evaluating it, if
+// anything: print something about it, or run it with the stepper. False
+// when the program is to be evaluated.
+fn run_instead(flags: &EvalFlags, program: &Expr, provenance: &Provenance, globals: &Env) -> bool {
+    if flags.dump_opt {
+        program.walk(&mut |id, path, node| {
+            let original = provenance.original(path).expect("Node without provenance");
+            println!("{} {} (from {}) {}", id, path, original, node.label());
+        });
+    } else if flags.list_nodes {
+        program.walk(&mut |id, path, node| println!("{} {} {}", id, path, node.label()));
+    } else if flags.dump_resolved {
+        print_resolved(program, flags.version);
+    } else if flags.engine == Engine::Stepper {
+        run_stepper(program, globals, flags.version, flags.max_steps);
+    } else if flags.analyze {
+        run_analysis(program, globals);
+    } else if flags.compare {
+        strategy::compare_strategies(program, globals, flags.version);
+    } else if flags.dump_debruijn {
+        let indexed = DeBruijn::from_expr(program);
+        println!("indices: {}", indexed);
+        println!("renamed: {}", indexed.to_expr());
+    } else if let Some(cell) = flags.up_to {
+        run_cells(program, cell, globals, flags.version);
+    } else if let Some(width) = flags.pretty_width {
+        println!("{}", program.to_doc().render(width));
+    } else if flags.dump_closure_conv {
+        println!("{}", ClosureConversion::new(program));
+    } else if let Some(path) = &flags.show_node {
+        match program.node_at(path) {
+            Some(node) => println!("{}", node.to_json()),
+            None => panic!("No node at path {}", path),
+        }
+    } else {
+        return false;
+    }
+    true
+}
+
+// Print how every variable of the program is looked up once resolved
+fn print_resolved(program: &Expr, version: LangVersion) {
+    resolve_locals(program, version).walk(&mut |_, path, node| match node {
+        Expr::Local(name, depth, slot) => {
+            println!("{} {} frame {} out, slot {}", path, name, depth, slot)
+        }
+        Expr::Identifier(name) => println!("{} {} by name", path, name),
+        _ => {}
+    });
+}
+
+// Substitute the numeric globals, then print every reduction
+fn run_stepper(program: &Expr, globals: &Env, version: LangVersion, max_steps: Option<u64>) {
+    let mut expr = program.clone();
+    for (name, value) in globals.vars().iter() {
+        if let ResultValue::Number(number) = value {
+            expr = substitute(&expr, &name, &Expr::Number(*number));
+        }
+    }
+    let stepper = Stepper { version };
+    println!("   {}", expr);
+    let limit = max_steps.unwrap_or(10_000);
+    let mut steps = 0;
+    while let Some((next, rule)) = stepper.step(&expr) {
+        steps += 1;
+        if steps > limit {
+            println!("Stopped after {} steps", limit);
+            return;
+        }
+        println!("→  {}    [{}]", next, rule);
+        expr = next;
+    }
+}
+
+// Print what the analyses find, and exit with 1 when one of the findings
+// is an error
+fn run_analysis(program: &Expr, globals: &Env) {
+    let (result, mut findings) = IntervalAnalysis::analyze(program, globals);
+    findings.extend(termination_warnings(program));
+    findings.extend(order_warnings(program));
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    println!("result: {} ({})", result, result.sign());
+    if findings.iter().any(|finding| finding.error) {
+        std::process::exit(1);
+    }
+}
+
+// The evaluation the flags ask for, with the limits, the builtins and what
+// to record. The globals get the prelude, or lose the numeric variables
+// with `--pure-lambda`.
+fn evaluation(flags: &EvalFlags, globals: &mut Env, builtins: BuiltinRegistry) -> Evaluation {
+    let mut eval = Evaluation::new(flags.version);
+    eval.loop_limit = flags.loop_limit;
+    eval.breakpoints = flags.breakpoints.clone();
+    eval.set_strict(flags.strict);
+    eval.set_max_steps(flags.max_steps);
+    if flags.pure_lambda {
+        // No numbers, so no pre-defined numeric variables either
+        globals.vars_mut().clear();
+    }
+    if let Some(name) = &flags.prelude {
+        load_prelude(name, globals, &mut eval);
+    }
+    if flags.env_diagram.is_some() {
+        eval.diagram = Some(EnvDiagram::new(globals));
+    }
+    if flags.flamegraph.is_some() {
+        eval.profile = Some(Profile::new());
+    }
+    if flags.heap_stats {
+        eval.heap_stats = Some(HeapStats::default());
+    }
+    if flags.report {
+        eval.report = Some(Report::default());
+    }
+    if flags.explain_lookups {
+        eval.lookups = Some(LookupReport::default());
+    }
+    eval.eval_order = flags.eval_order_seed.map(Rng::new);
+    eval.builtins = builtins;
+    eval.capabilities = flags.capabilities.clone();
+    eval.precision = flags.precision;
+    eval.overflow = flags.overflow;
+    if flags.post_mortem {
+        eval.call_stack = Some(vec![globals.clone()]);
+    }
+    eval
+}
+
+// Reject the program before it runs when `--pure-lambda` or
+// `--check-purity` doesn't allow it
+fn check_program(flags: &EvalFlags, program: &Expr, globals: &Env) {
+    if flags.pure_lambda {
+        check_pure_lambda(program, globals).unwrap_or_else(|err| panic!("{}", err));
+    }
+    if flags.check_purity {
+        let findings = purity_errors(program, globals);
+        for finding in &findings {
+            eprintln!("{}", finding);
+        }
+        if !findings.is_empty() {
+            std::process::exit(1);
+        }
+    }
+}
+
+// Report a failed evaluation with what went wrong and where, log it, open
+// the post-mortem REPL if asked for, and exit with 1
+fn report_failure(
+    error: &EvalError,
+    path: Option<NodePath>,
+    source_map: &SourceMap,
+    eval: &mut Evaluation,
+    version: LangVersion,
+) -> ! {
+    eprintln!("{}", error);
+    if let Some(path) = path {
+        let span = source_map.span(&path);
+        eprintln!("at {}", Location { path, span });
+    }
+    eval.log("error", serde_json::json!({ "message": error.to_string() }));
+    if let Some(log) = &mut eval.event_log {
+        log.writer.flush().expect("Failed to write event log");
+    }
+    if let Some(frames) = &eval.call_stack {
+        post_mortem(frames, version);
+    }
+    std::process::exit(1);
+}
+
+// The result goes to stdout after what the program printed. Everything the
+// interpreter reports about the evaluation goes to stderr.
+fn print_result(flags: &EvalFlags, result: &ResultValue) {
+    if flags.pure_lambda {
+        // Print the normal form of the result instead of a closure
+        let mut term = read_back(result);
+        for _ in 0..10_000 {
+            match normal_order_step(&term, flags.version) {
+                Some(next) => term = next,
+                None => break,
+            }
+        }
+        match church_meaning(&term) {
+            Some(meaning) => println!("{}    = {}", term, meaning),
+            None => println!("{}", term),
+        }
+    } else if let Some(text) = flags.display_style.render(result) {
+        println!("{}", text);
+    }
+}
+
+// Write the files and print the reports the flags ask for once the
+// program has been evaluated
+fn write_reports(flags: &EvalFlags, mut eval: Evaluation, start: Instant, dumped: Option<String>) {
+    // Write the environment diagram, as JSON or Graphviz depending on the extension
+    if let (Some(path), Some(diagram)) = (&flags.env_diagram, eval.diagram) {
+        let contents = if path.ends_with(".json") {
+            serde_json::to_string_pretty(&diagram.to_json()).unwrap()
+        } else {
+            diagram.to_dot()
+        };
+        std::fs::write(path, contents)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+    }
+    if let Some(log) = &mut eval.event_log {
+        log.writer.flush().expect("Failed to write event log");
+    }
+    if let Some(turtle) = &eval.turtle {
+        std::fs::write(&flags.drawing, turtle.to_svg())
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", flags.drawing, err));
+    }
+    if let Some(dumped) = dumped {
+        match flags.dump_env.as_ref().and_then(|(_, file)| file.as_ref()) {
+            Some(file) => std::fs::write(file, dumped + "\n")
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", file, err)),
+            None => println!("{}", dumped),
+        }
+    }
+    if let Some(stats) = eval.heap_stats {
+        eprint!("{}", stats);
+    }
+    if let Some(mut report) = eval.report {
+        report.steps = eval.steps;
+        report.wall_time = start.elapsed();
+        eprint!("{}", report);
+    }
+    if let Some(lookups) = eval.lookups {
+        eprint!("{}", lookups);
+    }
+    if let Some(times) = eval.node_times {
+        eprint!("{}", times);
+    }
+    if let (Some(path), Some(profile)) = (&flags.flamegraph, eval.profile) {
+        std::fs::write(path, profile.into_folded())
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+    }
+}
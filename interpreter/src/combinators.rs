@@ -0,0 +1,58 @@
+use std::fmt;
+
+// A parser built from the `pChar`, `pSeq`, `pAlt` and `pMany` builtins.
+// Choice is ordered and repetition is greedy, as in parsing expression
+// grammars: once an alternative or a repetition has matched, later failures
+// don't make it try again.
+#[derive(Debug, Clone)]
+pub enum Parser {
+    Char(char),
+    Seq(Vec<Parser>),
+    Alt(Vec<Parser>),
+    Many(Box<Parser>),
+}
+
+impl Parser {
+    // Match a prefix of `input`, returning the number of chars consumed
+    pub fn parse(&self, input: &[char]) -> Option<usize> {
+        match self {
+            Parser::Char(expected) => (input.first() == Some(expected)).then_some(1),
+            Parser::Seq(parsers) => {
+                let mut consumed = 0;
+                for parser in parsers {
+                    consumed += parser.parse(&input[consumed..])?;
+                }
+                Some(consumed)
+            }
+            Parser::Alt(parsers) => parsers.iter().find_map(|parser| parser.parse(input)),
+            Parser::Many(parser) => {
+                let mut consumed = 0;
+                // Stop on an empty match too, or pMany(pMany(p)) would loop
+                while let Some(count @ 1..) = parser.parse(&input[consumed..]) {
+                    consumed += count;
+                }
+                Some(consumed)
+            }
+        }
+    }
+
+    // The prefix of `input` the parser matches
+    pub fn run(&self, input: &str) -> Option<String> {
+        let chars: Vec<char> = input.chars().collect();
+        let consumed = self.parse(&chars)?;
+        Some(chars[..consumed].iter().collect())
+    }
+}
+
+impl fmt::Display for Parser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, parsers) = match self {
+            Parser::Char(c) => return write!(f, "pChar({:?})", c.to_string()),
+            Parser::Many(parser) => return write!(f, "pMany({})", parser),
+            Parser::Seq(parsers) => ("pSeq", parsers),
+            Parser::Alt(parsers) => ("pAlt", parsers),
+        };
+        let shown: Vec<String> = parsers.iter().map(|parser| parser.to_string()).collect();
+        write!(f, "{}({})", name, shown.join(", "))
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+// Growth rates that step budgets can be given in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Complexity {
+    Constant,
+    Log,
+    Linear,
+    Linearithmic,
+    Quadratic,
+    Cubic,
+    Exponential,
+}
+
+const COMPLEXITIES: [(&str, Complexity); 7] = [
+    ("1", Complexity::Constant),
+    ("log n", Complexity::Log),
+    ("n", Complexity::Linear),
+    ("n log n", Complexity::Linearithmic),
+    ("n^2", Complexity::Quadratic),
+    ("n^3", Complexity::Cubic),
+    ("2^n", Complexity::Exponential),
+];
+
+impl Complexity {
+    // The growth rate at input size n. Logarithms are base 2 and at least 1,
+    // so that small sizes don't make a budget zero.
+    pub fn at(self, n: u64) -> f64 {
+        let n = n as f64;
+        let log = n.log2().max(1.0);
+        match self {
+            Complexity::Constant => 1.0,
+            Complexity::Log => log,
+            Complexity::Linear => n,
+            Complexity::Linearithmic => n * log,
+            Complexity::Quadratic => n * n,
+            Complexity::Cubic => n * n * n,
+            Complexity::Exponential => n.exp2(),
+        }
+    }
+}
+
+impl fmt::Display for Complexity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (name, _) = COMPLEXITIES
+            .iter()
+            .find(|(_, complexity)| complexity == self)
+            .unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+// A budget of `coefficient` times the growth rate, written `50 * n log n`
+#[derive(Debug, Clone, Copy)]
+pub struct Bound {
+    pub coefficient: f64,
+    pub complexity: Complexity,
+}
+
+impl Bound {
+    pub fn parse(text: &str) -> Result<Bound, String> {
+        let (coefficient, complexity) = match text.split_once('*') {
+            Some((coefficient, complexity)) => (
+                coefficient
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Bad coefficient in bound: {}", text))?,
+                complexity.trim(),
+            ),
+            None => (1.0, text.trim()),
+        };
+        let complexity = COMPLEXITIES
+            .iter()
+            .find(|(name, _)| *name == complexity)
+            .map(|(_, complexity)| *complexity)
+            .ok_or_else(|| {
+                let names: Vec<&str> = COMPLEXITIES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Unknown growth rate {}, expected one of: {}",
+                    complexity,
+                    names.join(", ")
+                )
+            })?;
+        Ok(Bound {
+            coefficient,
+            complexity,
+        })
+    }
+
+    // The smallest coefficient that bounds every (size, steps) sample
+    pub fn fit(&self, samples: &[(u64, u64)]) -> f64 {
+        samples
+            .iter()
+            .map(|&(n, steps)| steps as f64 / self.complexity.at(n))
+            .fold(0.0, f64::max)
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} * {}", self.coefficient, self.complexity)
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// A moment in UTC, as seconds since 1970-01-01 00:00:00
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime(pub i64);
+
+// The calendar fields of a moment
+struct Fields {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The date of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    // The current time from the system clock
+    pub fn now() -> DateTime {
+        let elapsed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("The system clock is before 1970");
+        DateTime(elapsed.as_secs() as i64)
+    }
+
+    fn fields(self) -> Fields {
+        let (year, month, day) = civil_from_days(self.0.div_euclid(SECONDS_PER_DAY));
+        let seconds = self.0.rem_euclid(SECONDS_PER_DAY);
+        Fields {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+
+    pub fn add_days(self, days: i64) -> Result<DateTime, String> {
+        days.checked_mul(SECONDS_PER_DAY)
+            .and_then(|seconds| self.0.checked_add(seconds))
+            .map(DateTime)
+            .ok_or_else(|| format!("Date out of range: {} days after {}", days, self))
+    }
+
+    // Read a date written in `format`, where `%Y` is the year, `%m`, `%d`,
+    // `%H`, `%M` and `%S` are two-digit fields, `%%` is a percent sign and
+    // every other char must appear as is. Missing fields are the start of
+    // their range.
+    pub fn parse(text: &str, format: &str) -> Result<DateTime, String> {
+        let fail = || format!("{:?} doesn't match the date format {:?}", text, format);
+        let mut fields = Fields {
+            year: 1970,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let mut rest = text;
+        let mut directives = format.chars();
+        while let Some(char) = directives.next() {
+            if char != '%' {
+                rest = rest.strip_prefix(char).ok_or_else(fail)?;
+                continue;
+            }
+            let directive = directives
+                .next()
+                .ok_or_else(|| format!("Date format ends with %: {:?}", format))?;
+            let (field, width) = match directive {
+                'Y' => (&mut fields.year, 4),
+                'm' => (&mut fields.month, 2),
+                'd' => (&mut fields.day, 2),
+                'H' => (&mut fields.hour, 2),
+                'M' => (&mut fields.minute, 2),
+                'S' => (&mut fields.second, 2),
+                '%' => {
+                    rest = rest.strip_prefix('%').ok_or_else(fail)?;
+                    continue;
+                }
+                _ => return Err(format!("Unknown date directive %{}", directive)),
+            };
+            let digits = rest.get(..width).ok_or_else(fail)?;
+            if !digits.chars().all(|digit| digit.is_ascii_digit()) {
+                return Err(fail());
+            }
+            *field = digits.parse().map_err(|_| fail())?;
+            rest = &rest[width..];
+        }
+        if !rest.is_empty() {
+            return Err(fail());
+        }
+        let Fields {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        } = fields;
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(format!("{:?} is not a valid date", text));
+        }
+        let days = days_from_civil(year, month, day);
+        Ok(DateTime(
+            days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second,
+        ))
+    }
+
+    // Write the date in `format`, with the directives of `parse`
+    pub fn format(self, format: &str) -> Result<String, String> {
+        let fields = self.fields();
+        let mut text = String::new();
+        let mut directives = format.chars();
+        while let Some(char) = directives.next() {
+            if char != '%' {
+                text.push(char);
+                continue;
+            }
+            match directives.next() {
+                Some('Y') => text += &format!("{:04}", fields.year),
+                Some('m') => text += &format!("{:02}", fields.month),
+                Some('d') => text += &format!("{:02}", fields.day),
+                Some('H') => text += &format!("{:02}", fields.hour),
+                Some('M') => text += &format!("{:02}", fields.minute),
+                Some('S') => text += &format!("{:02}", fields.second),
+                Some('%') => text.push('%'),
+                Some(directive) => return Err(format!("Unknown date directive %{}", directive)),
+                None => return Err(format!("Date format ends with %: {:?}", format)),
+            }
+        }
+        Ok(text)
+    }
+}
+
+pub const ISO_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+// Written as the builtin call that makes the date
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = self.format(ISO_FORMAT).unwrap();
+        write!(f, "parseDate({:?}, {:?})", text, ISO_FORMAT)
+    }
+}
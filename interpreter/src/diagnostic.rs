@@ -0,0 +1,175 @@
+use serde_json::Value;
+use std::fmt;
+
+// How errors in the input are reported, from `--error-format`: as text for
+// people or as a JSON object for tools
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn parse(name: &str) -> Result<ErrorFormat, String> {
+        match name {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "Unknown error format: {} (expected text or json)",
+                name
+            )),
+        }
+    }
+}
+
+// Lines shown before the one with the error
+const CONTEXT_LINES: usize = 2;
+
+// Why a JSON document couldn't be read, where, and the lines around it
+pub struct JsonDiagnostic {
+    // Where the document came from, e.g. a file name or "stdin"
+    source: String,
+    message: String,
+    line: usize,
+    column: usize,
+    // The JSON Pointer (RFC 6901) of the value being read, "" at the top
+    pointer: String,
+    // The numbered lines up to the one with the error
+    snippet: Vec<(usize, String)>,
+}
+
+impl JsonDiagnostic {
+    pub fn new(source: &str, text: &str, err: &serde_json::Error) -> JsonDiagnostic {
+        // The message without the position serde adds to it
+        let message = err.to_string();
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message
+            .strip_suffix(&suffix)
+            .unwrap_or(&message)
+            .to_string();
+        let lines: Vec<&str> = text.split('\n').collect();
+        let line = err.line().clamp(1, lines.len());
+        let line_start: usize = lines[..line - 1].iter().map(|line| line.len() + 1).sum();
+        let mut offset = (line_start + err.column().saturating_sub(1)).min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let snippet = (line.saturating_sub(CONTEXT_LINES).max(1)..=line)
+            .map(|number| (number, lines[number - 1].trim_end_matches('\r').to_string()))
+            .collect();
+        JsonDiagnostic {
+            source: source.to_string(),
+            message,
+            line,
+            column: err.column().max(1),
+            pointer: pointer_at(text, offset),
+            snippet,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "error": "invalid JSON",
+            "source": self.source,
+            "message": self.message,
+            "line": self.line,
+            "column": self.column,
+            "pointer": self.pointer,
+            "snippet": self.snippet.iter().map(|(_, text)| text).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn render(&self, format: ErrorFormat) -> String {
+        match format {
+            ErrorFormat::Text => self.to_string(),
+            ErrorFormat::Json => self.to_json().to_string(),
+        }
+    }
+}
+
+//   error: expected `,` or `]`
+//    --> stdin, line 3, column 5, in /Application/1
+//     |
+//   2 |   {"Identifier": "add"},
+//   3 |   1 2]}
+//     |     ^
+impl fmt::Display for JsonDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+        let within = match self.pointer.as_str() {
+            "" => "at the top level".to_string(),
+            pointer => format!("in {}", pointer),
+        };
+        let width = self.line.to_string().len();
+        writeln!(
+            f,
+            "{:width$}--> {}, line {}, column {}, {}",
+            "", self.source, self.line, self.column, within
+        )?;
+        writeln!(f, "{:width$} |", "")?;
+        for (number, text) in &self.snippet {
+            writeln!(f, "{:>width$} | {}", number, text)?;
+        }
+        write!(f, "{:width$} | {:>column$}", "", "^", column = self.column)
+    }
+}
+
+// A container the reader is inside of at some point of a JSON document
+enum Container {
+    // The index of the element being read
+    Array(usize),
+    // The key of the value being read, None while reading a key
+    Object(Option<String>),
+}
+
+// The JSON Pointer of the innermost value that contains the byte offset
+// of a document, as far as it can be read up to there
+fn pointer_at(text: &str, offset: usize) -> String {
+    let mut containers: Vec<Container> = Vec::new();
+    let mut chars = text[..offset].chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => {
+                let mut string = String::new();
+                let mut closed = false;
+                while let Some(char) = chars.next() {
+                    match char {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => string.extend(chars.next()),
+                        _ => string.push(char),
+                    }
+                }
+                if let (true, Some(Container::Object(key @ None))) = (closed, containers.last_mut())
+                {
+                    *key = Some(string);
+                }
+            }
+            '[' => containers.push(Container::Array(0)),
+            '{' => containers.push(Container::Object(None)),
+            ']' | '}' => {
+                containers.pop();
+            }
+            ',' => match containers.last_mut() {
+                Some(Container::Array(index)) => *index += 1,
+                Some(Container::Object(key)) => *key = None,
+                None => {}
+            },
+            _ => {}
+        }
+    }
+    let mut pointer = String::new();
+    for container in &containers {
+        match container {
+            Container::Array(index) => pointer.push_str(&format!("/{}", index)),
+            Container::Object(Some(key)) => {
+                pointer.push('/');
+                pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+            }
+            Container::Object(None) => break,
+        }
+    }
+    pointer
+}
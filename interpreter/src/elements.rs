@@ -0,0 +1,141 @@
+use crate::eval::ResultValue;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// The elements of an array or a set. Copies of the value share them, so
+// looking one up or passing it to a call doesn't copy them, and each copy
+// sees the first `len` elements of a buffer that can have room for more.
+// `push` puts the new element in the free slot after the last one when no
+// other copy has taken it yet, and copies into a buffer twice as large
+// otherwise, so a loop that keeps appending to its latest array takes
+// amortized constant time per element. Elements already in the buffer are
+// never written again, so every copy keeps the values it had.
+pub struct Elements {
+    buffer: Arc<Buffer>,
+    len: usize,
+}
+
+struct Buffer {
+    slots: Box<[UnsafeCell<MaybeUninit<ResultValue>>]>,
+    // How many slots from the start hold a value. It only grows, and a slot
+    // is claimed by moving it from `len` to `len + 1` before the value is
+    // written, so two copies of the same length can't both write there.
+    filled: AtomicUsize,
+}
+
+// Slots below `filled` are only read, and a slot is written by the one
+// thread that claimed it, before any copy that can read it exists
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    fn new(values: Vec<ResultValue>, capacity: usize) -> Buffer {
+        let filled = values.len();
+        let free = capacity.saturating_sub(filled);
+        let slots = values
+            .into_iter()
+            .map(MaybeUninit::new)
+            .chain(std::iter::repeat_with(MaybeUninit::uninit).take(free))
+            .map(UnsafeCell::new)
+            .collect();
+        Buffer {
+            slots,
+            filled: AtomicUsize::new(filled),
+        }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        let filled = *self.filled.get_mut();
+        for slot in &mut self.slots[..filled] {
+            // Safety: the slots below `filled` were written
+            unsafe { slot.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl Elements {
+    // Whether other values hold these elements too, or elements of the same
+    // buffer that `push` added to them
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.buffer) > 1
+    }
+
+    // A copy of these elements with `value` added at the end
+    pub fn push(&self, value: ResultValue) -> Elements {
+        let claimed = self.len < self.buffer.slots.len()
+            && self
+                .buffer
+                .filled
+                .compare_exchange(self.len, self.len + 1, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok();
+        if !claimed {
+            let mut values = Vec::with_capacity(self.len + 1);
+            values.extend_from_slice(self);
+            values.push(value);
+            let capacity = 2 * values.len();
+            return Elements {
+                len: values.len(),
+                buffer: Arc::new(Buffer::new(values, capacity)),
+            };
+        }
+        // Safety: the slot was free, and claiming it made it ours alone
+        unsafe { (*self.buffer.slots[self.len].get()).write(value) };
+        Elements {
+            buffer: Arc::clone(&self.buffer),
+            len: self.len + 1,
+        }
+    }
+}
+
+impl Deref for Elements {
+    type Target = [ResultValue];
+
+    fn deref(&self) -> &[ResultValue] {
+        // Safety: the first `len` slots were written before this copy was
+        // made and aren't written again, and a slot has the layout of the
+        // value in it
+        unsafe { std::slice::from_raw_parts(self.buffer.slots.as_ptr().cast(), self.len) }
+    }
+}
+
+impl Clone for Elements {
+    fn clone(&self) -> Elements {
+        Elements {
+            buffer: Arc::clone(&self.buffer),
+            len: self.len,
+        }
+    }
+}
+
+impl From<Vec<ResultValue>> for Elements {
+    fn from(values: Vec<ResultValue>) -> Elements {
+        let len = values.len();
+        Elements {
+            buffer: Arc::new(Buffer::new(values, len)),
+            len,
+        }
+    }
+}
+
+impl From<&[ResultValue]> for Elements {
+    fn from(values: &[ResultValue]) -> Elements {
+        values.to_vec().into()
+    }
+}
+
+impl FromIterator<ResultValue> for Elements {
+    fn from_iter<I: IntoIterator<Item = ResultValue>>(values: I) -> Elements {
+        values.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl fmt::Debug for Elements {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,252 @@
+use crate::eval::*;
+use crate::symbol::Symbol;
+use std::fmt;
+use std::ops::Index;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// The variables bound by one frame, and the frame around it. Copies of an
+// Env are handles to the same frame: a closure captures its environment
+// without copying any bindings, and a binding added later is seen through
+// every handle. Values cross threads in `--parallel` and the server, hence
+// `Arc` rather than `Rc`.
+#[derive(Clone, Default)]
+pub struct Env {
+    // Frame number, unique within an evaluation; the global frame is 0
+    pub(crate) id: usize,
+    vars: Arc<RwLock<Vars>>,
+    pub(crate) parent: Option<Arc<Env>>,
+    // Whether the frame was made by `letrec`. Its closures don't hold the
+    // frame itself, which can't contain them, so it is put back when one
+    // of them is called (see `tie`).
+    pub(crate) recursive: bool,
+}
+
+impl Env {
+    // An empty global frame
+    pub fn new() -> Env {
+        Env::default()
+    }
+
+    // A new frame with these bindings inside `parent`
+    pub(crate) fn frame(id: usize, vars: Vars, parent: Option<Env>) -> Env {
+        Env {
+            id,
+            vars: Arc::new(RwLock::new(vars)),
+            parent: parent.map(Arc::new),
+            recursive: false,
+        }
+    }
+
+    // The bindings of this frame. Evaluation errors are panics, so a lock
+    // can be poisoned by one that was caught; the bindings are still whole.
+    pub(crate) fn vars(&self) -> RwLockReadGuard<'_, Vars> {
+        self.vars.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn vars_mut(&self) -> RwLockWriteGuard<'_, Vars> {
+        self.vars.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // What tells frames apart: the frames that share their bindings, clones
+    // of each other, have the same key
+    pub(crate) fn key(&self) -> usize {
+        Arc::as_ptr(&self.vars) as usize
+    }
+
+    // The same bindings in a frame of their own, which bindings added to
+    // this one later don't show up in
+    pub(crate) fn copy(&self) -> Env {
+        Env {
+            vars: Arc::new(RwLock::new(self.vars().clone())),
+            ..self.clone()
+        }
+    }
+
+    // Bind a variable in this frame, replacing any earlier binding
+    pub fn define(&mut self, name: &str, value: ResultValue) {
+        self.vars_mut().insert(name, value);
+    }
+
+    // Number of bindings in this frame and all frames around it
+    pub(crate) fn binding_count(&self) -> usize {
+        self.vars().len()
+            + self
+                .parent
+                .as_ref()
+                .map_or(0, |parent| parent.binding_count())
+    }
+
+    // Approximate number of bytes of this frame and all frames around it
+    pub(crate) fn estimated_size(&self) -> usize {
+        let bindings: usize = self
+            .vars()
+            .iter()
+            .map(|(name, value)| {
+                std::mem::size_of::<String>() + name.len() + value.estimated_size()
+            })
+            .sum();
+        let parent = self
+            .parent
+            .as_ref()
+            .map_or(0, |parent| parent.estimated_size());
+        std::mem::size_of::<Env>() + bindings + parent
+    }
+
+    // The environment a closure that captured this frame is called in. In
+    // a letrec frame, every closure it binds gets the frame back as its
+    // environment, so they can call each other however deep the recursion
+    // goes. The closures hold a copy of the frame from before they were
+    // bound, so no frame ever holds itself and dropping it frees it.
+    pub(crate) fn tie(self) -> Env {
+        if !self.recursive {
+            return self;
+        }
+        let tied = self.copy();
+        for value in tied.vars_mut().values_mut() {
+            if let ResultValue::Lambda(_, _, env, _) = value {
+                *env = self.clone();
+            }
+        }
+        tied
+    }
+
+    // The value in a slot of the frame `depth` frames out from this one,
+    // where the resolver found the binder of a variable
+    pub(crate) fn slot(&self, depth: usize, slot: usize) -> ResultValue {
+        let mut frame = self;
+        for _ in 0..depth {
+            frame = frame.parent.as_deref().expect("Resolved past the globals");
+        }
+        let vars = frame.vars();
+        vars.slot(slot).expect("Resolved to a missing slot").clone()
+    }
+
+    // Look a variable up in this frame, then in the enclosing ones
+    pub fn get(&self, name: &str) -> Option<ResultValue> {
+        self.get_symbol(Symbol::lookup(name)?)
+    }
+
+    pub(crate) fn get_symbol(&self, name: Symbol) -> Option<ResultValue> {
+        match self.vars().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.get_symbol(name),
+        }
+    }
+}
+
+// Frames are shown by id, since a closure in a frame can lead back to it
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<Symbol> = self.vars().keys().collect();
+        f.debug_struct("Env")
+            .field("id", &self.id)
+            .field("vars", &names)
+            .field("parent", &self.parent.as_ref().map(|parent| parent.id))
+            .finish()
+    }
+}
+
+// The bindings of a frame, in the order they were made. A name bound
+// again keeps its place, so the slot of each name is known before the
+// frame exists: a lambda's parameters, or a letrec's names, in order,
+// each repeated name counted once. The resolver (see `resolver.rs`)
+// relies on that to turn names into slot numbers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Vars(Vec<(Symbol, ResultValue)>);
+
+impl Vars {
+    pub(crate) fn get(&self, name: Symbol) -> Option<&ResultValue> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == name)
+            .map(|(_, value)| value)
+    }
+
+    // The slot of a name, if the frame binds it
+    pub(crate) fn position(&self, name: Symbol) -> Option<usize> {
+        self.0.iter().position(|(bound, _)| *bound == name)
+    }
+
+    // The value in a slot, if the frame has that many
+    pub(crate) fn slot(&self, slot: usize) -> Option<&ResultValue> {
+        self.0.get(slot).map(|(_, value)| value)
+    }
+
+    // Bind a name, in its old slot if it has one, else in a new one last
+    pub(crate) fn insert(&mut self, name: impl Into<Symbol>, value: ResultValue) {
+        let name = name.into();
+        match self.0.iter_mut().find(|(bound, _)| *bound == name) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((name, value)),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Symbol, &ResultValue)> {
+        self.0.iter().map(|(name, value)| (*name, value))
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.0.iter().map(|(name, _)| *name)
+    }
+
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut ResultValue> {
+        self.0.iter_mut().map(|(_, value)| value)
+    }
+}
+
+impl<S: Into<Symbol>> FromIterator<(S, ResultValue)> for Vars {
+    fn from_iter<I: IntoIterator<Item = (S, ResultValue)>>(bindings: I) -> Vars {
+        let mut vars = Vars::default();
+        for (name, value) in bindings {
+            vars.insert(name, value);
+        }
+        vars
+    }
+}
+
+impl Index<&str> for Vars {
+    type Output = ResultValue;
+
+    fn index(&self, name: &str) -> &ResultValue {
+        Symbol::lookup(name)
+            .and_then(|name| self.get(name))
+            .expect("Name not bound in the frame")
+    }
+}
+
+// Names are resolved the same way by every engine: the innermost scope
+// first, then each enclosing scope out to the globals (the root of every
+// environment), and only then the builtins, so a variable shadows a builtin
+// of the same name. `builtin:NAME` skips the scopes and always means the
+// builtin.
+pub(crate) const BUILTIN_PREFIX: &str = "builtin:";
+
+// What a name refers to
+pub(crate) enum Resolution {
+    Variable(ResultValue),
+    // No scope binds the name: the builtin of that name, if there is one
+    Builtin(&'static str),
+}
+
+pub(crate) fn resolve(name: Symbol, env: &Env) -> Resolution {
+    if let Some(builtin) = name.as_str().strip_prefix(BUILTIN_PREFIX) {
+        return Resolution::Builtin(builtin);
+    }
+    match env.get_symbol(name) {
+        Some(value) => Resolution::Variable(value),
+        None => Resolution::Builtin(name.as_str()),
+    }
+}
+
+// The builtin a name refers to when no scope binds it
+pub(crate) fn builtin_name(name: &str) -> &str {
+    name.strip_prefix(BUILTIN_PREFIX).unwrap_or(name)
+}
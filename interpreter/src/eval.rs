@@ -0,0 +1,2305 @@
+use crate::ast::*;
+use crate::builtins::*;
+use crate::elements::Elements;
+use crate::env::*;
+use crate::{combinators, date, iter, logic, pretty, rpc, turtle};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Small deterministic random number generator (xorshift64*), so generated
+// programs can be reproduced from their seed
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // Zero is a fixed point of xorshift
+        Rng(seed ^ 0x9e3779b97f4a7c15)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // A number in `0..bound`
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+// A value produced by evaluating an expression
+#[derive(Debug, Clone)]
+pub enum ResultValue {
+    Number(i64),
+    // Only made by arithmetic with `--overflow promote-float`
+    Float(f64),
+    String(String),
+    Bool(bool),
+    // The result of evaluating only for the side effects, like `for` or an
+    // empty block
+    Unit,
+    // Parameters and body of a lambda, with the environment it was created in
+    // and the path of the lambda in the program, when known. The body is
+    // shared by the copies of the closure that calls and builtins like
+    // `map` make.
+    Lambda(Vec<String>, Arc<[Expr]>, Env, Option<NodePath>),
+    // A function wrapped by `contract(pre, post, function)`
+    Contract(Box<(ResultValue, ResultValue, ResultValue)>),
+    // A parser built with the parser combinator builtins
+    Parser(combinators::Parser),
+    // A document built with the pretty-printing builtins
+    Doc(pretty::Doc),
+    // A lazy sequence built with the iterator builtins
+    Iterator(Box<iter::Iter>),
+    // Collections made with `collect`: arrays, sets of distinct values and
+    // dicts from strings to values, all in insertion order. Arrays and sets
+    // share their elements (see `Elements`), so that looking one up or
+    // passing it to a call doesn't copy them.
+    Array(Elements),
+    Set(Elements),
+    Dict(Vec<(String, ResultValue)>),
+    // Binary data
+    Bytes(Vec<u8>),
+    DateTime(date::DateTime),
+}
+
+impl fmt::Display for ResultValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResultValue::Number(number) => write!(f, "{}", number),
+            // Always with a point or an exponent, unlike numbers
+            ResultValue::Float(float) => write!(f, "{:?}", float),
+            ResultValue::String(string) => write!(f, "{}", string),
+            ResultValue::Bool(value) => write!(f, "{}", value),
+            ResultValue::Unit => write!(f, "()"),
+            ResultValue::Lambda(parameters, body, env, path) => {
+                write!(f, "<closure ({})", parameters.join(", "))?;
+                let captured: Vec<String> =
+                    Expr::Lambda(parameters.clone(), body.clone(), Site::default())
+                        .free_variables()
+                        .into_iter()
+                        .filter(|name| env.get(name).is_some())
+                        .collect();
+                if !captured.is_empty() {
+                    write!(f, " captures: {}", captured.join(", "))?;
+                }
+                if let Some(path) = path {
+                    write!(f, " @ {}", path)?;
+                }
+                write!(f, ">")
+            }
+            ResultValue::Contract(contract) => {
+                let (pre, post, function) = contract.as_ref();
+                write!(f, "contract({}, {}, {})", pre, post, function)
+            }
+            ResultValue::Parser(parser) => write!(f, "{}", parser),
+            ResultValue::Doc(doc) => write!(f, "{}", doc.render(80)),
+            ResultValue::Iterator(iterator) => write!(f, "{}", iterator),
+            ResultValue::Array(values) => write!(f, "[{}]", quoted_list(values)),
+            ResultValue::Set(values) => write!(f, "#{{{}}}", quoted_list(values)),
+            ResultValue::Dict(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{:?}: {}", key, value.quoted()))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            ResultValue::Bytes(bytes) => write!(f, "hexDecode({:?})", hex_encode(bytes)),
+            ResultValue::DateTime(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+impl ResultValue {
+    // A number or float as a float, for arithmetic that involves a float
+    pub(crate) fn as_float(&self) -> f64 {
+        match self {
+            ResultValue::Number(number) => *number as f64,
+            ResultValue::Float(float) => *float,
+            value => fail(EvalError::TypeError(format!("Not a number: {}", value))),
+        }
+    }
+
+    // The value as written inside a collection, where strings are quoted
+    pub(crate) fn quoted(&self) -> String {
+        match self {
+            ResultValue::String(string) => format!("{:?}", string),
+            value => value.to_string(),
+        }
+    }
+}
+
+// Conversions between values and Rust types, for host functions: `From`
+// makes a value of an i64, f64, bool, string, `()` (the unit value) or a
+// Vec of any of these (an array), and `TryFrom` takes one apart again,
+// failing with a TypeError when the value is of another kind. Numbers
+// convert to f64 too, and sets to Vecs. `IntoValue` and `FromValue` are
+// the same conversions as traits to be generic over.
+pub trait IntoValue {
+    fn into_value(self) -> ResultValue;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: ResultValue) -> Result<Self, EvalError>;
+
+    // The argument at `index` of a host function's arguments, failing like
+    // a call with too few arguments when there is none
+    fn from_argument(arguments: &[ResultValue], index: usize) -> Result<Self, EvalError> {
+        match arguments.get(index) {
+            Some(argument) => Self::from_value(argument.clone()),
+            None => Err(EvalError::ArityMismatch {
+                expected: index + 1,
+                got: arguments.len(),
+            }),
+        }
+    }
+}
+
+impl<T: Into<ResultValue>> IntoValue for T {
+    fn into_value(self) -> ResultValue {
+        self.into()
+    }
+}
+
+impl<T: TryFrom<ResultValue, Error = EvalError>> FromValue for T {
+    fn from_value(value: ResultValue) -> Result<T, EvalError> {
+        T::try_from(value)
+    }
+}
+
+impl From<i64> for ResultValue {
+    fn from(number: i64) -> ResultValue {
+        ResultValue::Number(number)
+    }
+}
+
+impl From<f64> for ResultValue {
+    fn from(float: f64) -> ResultValue {
+        ResultValue::Float(float)
+    }
+}
+
+impl From<bool> for ResultValue {
+    fn from(value: bool) -> ResultValue {
+        ResultValue::Bool(value)
+    }
+}
+
+impl From<String> for ResultValue {
+    fn from(string: String) -> ResultValue {
+        ResultValue::String(string)
+    }
+}
+
+impl From<&str> for ResultValue {
+    fn from(string: &str) -> ResultValue {
+        ResultValue::String(string.to_string())
+    }
+}
+
+impl From<()> for ResultValue {
+    fn from(_: ()) -> ResultValue {
+        ResultValue::Unit
+    }
+}
+
+impl<T: IntoValue> From<Vec<T>> for ResultValue {
+    fn from(values: Vec<T>) -> ResultValue {
+        ResultValue::Array(values.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+// The error of converting a value of the wrong kind
+fn expected(what: &str, value: &ResultValue) -> EvalError {
+    EvalError::TypeError(format!("Expected {} but got {}", what, value.described()))
+}
+
+impl TryFrom<ResultValue> for i64 {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<i64, EvalError> {
+        match value {
+            ResultValue::Number(number) => Ok(number),
+            value => Err(expected("a number", &value)),
+        }
+    }
+}
+
+impl TryFrom<ResultValue> for f64 {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<f64, EvalError> {
+        match value {
+            ResultValue::Number(_) | ResultValue::Float(_) => Ok(value.as_float()),
+            value => Err(expected("a number", &value)),
+        }
+    }
+}
+
+impl TryFrom<ResultValue> for bool {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<bool, EvalError> {
+        match value {
+            ResultValue::Bool(value) => Ok(value),
+            value => Err(expected("a boolean", &value)),
+        }
+    }
+}
+
+impl TryFrom<ResultValue> for String {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<String, EvalError> {
+        match value {
+            ResultValue::String(string) => Ok(string),
+            value => Err(expected("a string", &value)),
+        }
+    }
+}
+
+impl TryFrom<ResultValue> for () {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<(), EvalError> {
+        match value {
+            ResultValue::Unit => Ok(()),
+            value => Err(expected("the unit value", &value)),
+        }
+    }
+}
+
+impl<T: FromValue> TryFrom<ResultValue> for Vec<T> {
+    type Error = EvalError;
+
+    fn try_from(value: ResultValue) -> Result<Vec<T>, EvalError> {
+        match value {
+            ResultValue::Array(values) | ResultValue::Set(values) => {
+                values.iter().cloned().map(T::from_value).collect()
+            }
+            value => Err(expected("an array", &value)),
+        }
+    }
+}
+
+impl ResultValue {
+    // The name of the value's type, as in error messages
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ResultValue::Number(_) => "number",
+            ResultValue::Float(_) => "float",
+            ResultValue::String(_) => "string",
+            ResultValue::Bool(_) => "boolean",
+            ResultValue::Unit => "unit",
+            ResultValue::Lambda(..) => "closure",
+            ResultValue::Contract(_) => "contract",
+            ResultValue::Parser(_) => "parser",
+            ResultValue::Doc(_) => "document",
+            ResultValue::Iterator(_) => "iterator",
+            ResultValue::Array(_) => "array",
+            ResultValue::Set(_) => "set",
+            ResultValue::Dict(_) => "dict",
+            ResultValue::Bytes(_) => "bytes",
+            ResultValue::DateTime(_) => "date",
+        }
+    }
+
+    // Order two values of the same comparable type: numbers, strings and
+    // dates in their natural order, false before true, bytes and arrays
+    // lexicographically. Numbers and floats compare by value, except NaN.
+    // Values of other types, or of different types, can't be ordered.
+    pub(crate) fn compare(&self, other: &ResultValue) -> Result<Ordering, EvalError> {
+        match (self, other) {
+            (ResultValue::Number(left), ResultValue::Number(right)) => Ok(left.cmp(right)),
+            (ResultValue::Float(_), ResultValue::Number(_) | ResultValue::Float(_))
+            | (ResultValue::Number(_), ResultValue::Float(_)) => {
+                let (left, right) = (self.as_float(), other.as_float());
+                left.partial_cmp(&right)
+                    .ok_or_else(|| EvalError::Incomparable {
+                        left: self.described(),
+                        right: other.described(),
+                    })
+            }
+            (ResultValue::String(left), ResultValue::String(right)) => Ok(left.cmp(right)),
+            (ResultValue::Bool(left), ResultValue::Bool(right)) => Ok(left.cmp(right)),
+            (ResultValue::Unit, ResultValue::Unit) => Ok(Ordering::Equal),
+            (ResultValue::DateTime(left), ResultValue::DateTime(right)) => Ok(left.cmp(right)),
+            (ResultValue::Bytes(left), ResultValue::Bytes(right)) => Ok(left.cmp(right)),
+            (ResultValue::Array(left), ResultValue::Array(right)) => {
+                for (left, right) in left.iter().zip(right.iter()) {
+                    match left.compare(right)? {
+                        Ordering::Equal => continue,
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Ok(left.len().cmp(&right.len()))
+            }
+            _ => Err(EvalError::Incomparable {
+                left: self.described(),
+                right: other.described(),
+            }),
+        }
+    }
+}
+
+// How the result of a program is printed, from `--display-style`:
+//
+// - plain: as `Display` writes it, with strings bare at the top level
+// - debug: the kind and the value with strings quoted, `string "hi"`
+// - json: numbers, strings, arrays and sets (as arrays) and dicts (as
+//   objects, with keys in sorted order) as JSON, any other value as `{"KIND": "TEXT"}` with its plain
+//   text, and the unit value as null
+//
+// Plain and debug print nothing for the unit value.
+//
+// None of them depend on the locale or platform: numbers and floats are
+// written in ASCII digits and every collection keeps insertion order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DisplayStyle {
+    Plain,
+    Debug,
+    Json,
+}
+
+impl DisplayStyle {
+    pub(crate) fn parse(name: &str) -> Result<DisplayStyle, String> {
+        match name {
+            "plain" => Ok(DisplayStyle::Plain),
+            "debug" => Ok(DisplayStyle::Debug),
+            "json" => Ok(DisplayStyle::Json),
+            _ => Err(format!(
+                "Unknown display style: {} (expected plain, debug or json)",
+                name
+            )),
+        }
+    }
+
+    // The text to print for a result, or None to print nothing
+    pub(crate) fn render(self, value: &ResultValue) -> Option<String> {
+        let no_value = matches!(value, ResultValue::Unit);
+        match self {
+            DisplayStyle::Plain if no_value => None,
+            DisplayStyle::Plain => Some(value.to_string()),
+            DisplayStyle::Debug if no_value => None,
+            DisplayStyle::Debug => Some(value.described()),
+            DisplayStyle::Json if no_value => Some(Value::Null.to_string()),
+            DisplayStyle::Json => Some(value.to_display_json().to_string()),
+        }
+    }
+}
+
+impl ResultValue {
+    // The kind and the value, with strings quoted
+    pub(crate) fn described(&self) -> String {
+        format!("{} {}", self.kind(), self.quoted())
+    }
+
+    // The value as JSON for `--display-style json` and `--dump-env`
+    pub(crate) fn to_display_json(&self) -> Value {
+        match self {
+            ResultValue::Number(number) => serde_json::json!(number),
+            ResultValue::Float(float) => serde_json::json!(float),
+            ResultValue::String(string) => serde_json::json!(string),
+            ResultValue::Bool(value) => serde_json::json!(value),
+            ResultValue::Unit => Value::Null,
+            ResultValue::Array(values) | ResultValue::Set(values) => {
+                Value::Array(values.iter().map(ResultValue::to_display_json).collect())
+            }
+            ResultValue::Dict(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.to_display_json()))
+                    .collect(),
+            ),
+            value => serde_json::json!({ value.kind(): value.to_string() }),
+        }
+    }
+}
+
+// Sort values stably by `compare`, failing on the first pair that can't be
+// ordered
+pub(crate) fn sort_values(mut values: Vec<ResultValue>) -> Result<Vec<ResultValue>, EvalError> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let mut left = sort_values(values)?.into_iter().peekable();
+    let mut right = sort_values(right)?.into_iter().peekable();
+    let mut sorted = Vec::new();
+    while let (Some(first), Some(second)) = (left.peek(), right.peek()) {
+        if second.compare(first)? == Ordering::Less {
+            sorted.extend(right.next());
+        } else {
+            sorted.extend(left.next());
+        }
+    }
+    sorted.extend(left.chain(right));
+    Ok(sorted)
+}
+
+// The values separated by commas, with strings quoted
+pub(crate) fn quoted_list(values: &[ResultValue]) -> String {
+    let values: Vec<String> = values.iter().map(ResultValue::quoted).collect();
+    values.join(", ")
+}
+
+impl ResultValue {
+    // Approximate number of bytes the value occupies in memory, including
+    // the environment a closure captured
+    pub(crate) fn estimated_size(&self) -> usize {
+        let own = match self {
+            ResultValue::Number(_)
+            | ResultValue::Float(_)
+            | ResultValue::Bool(_)
+            | ResultValue::Unit
+            | ResultValue::Parser(_) => 0,
+            ResultValue::Doc(doc) => doc.estimated_size(),
+            ResultValue::Iterator(iterator) => iterator.estimated_size(),
+            ResultValue::Array(values) | ResultValue::Set(values) => {
+                values.iter().map(ResultValue::estimated_size).sum()
+            }
+            ResultValue::Bytes(bytes) => bytes.len(),
+            ResultValue::DateTime(_) => std::mem::size_of::<date::DateTime>(),
+            ResultValue::Dict(entries) => entries
+                .iter()
+                .map(|(key, value)| {
+                    std::mem::size_of::<String>() + key.len() + value.estimated_size()
+                })
+                .sum(),
+            ResultValue::String(string) => string.len(),
+            ResultValue::Lambda(parameters, body, env, _) => {
+                parameters.iter().map(|name| name.len()).sum::<usize>()
+                    + parameters.len() * std::mem::size_of::<String>()
+                    + body.iter().map(Expr::estimated_size).sum::<usize>()
+                    + std::mem::size_of_val(env)
+            }
+            ResultValue::Contract(contract) => {
+                let (pre, post, function) = contract.as_ref();
+                pre.estimated_size() + post.estimated_size() + function.estimated_size()
+            }
+        };
+        std::mem::size_of::<ResultValue>() + own
+    }
+
+    // The estimated size of what a builtin's result adds: arrays and sets
+    // that other values hold too were counted when they were made
+    pub(crate) fn fresh_size(&self) -> usize {
+        match self {
+            ResultValue::Array(values) | ResultValue::Set(values) if !values.is_shared() => {
+                std::mem::size_of::<ResultValue>()
+                    + values.iter().map(ResultValue::fresh_size).sum::<usize>()
+            }
+            ResultValue::Array(_) | ResultValue::Set(_) => std::mem::size_of::<ResultValue>(),
+            ResultValue::Iterator(iterator) => {
+                std::mem::size_of::<ResultValue>() + iterator.fresh_size()
+            }
+            value => value.estimated_size(),
+        }
+    }
+}
+
+// Settings a host evaluates programs with, for `Evaluation::with_options`
+// and `Interpreter::with_options`; the default is what `Evaluation::new`
+// starts with
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    // How many expressions may be evaluated before evaluation fails with
+    // `FuelExhausted`, like `--max-steps`. None doesn't limit the steps.
+    pub max_steps: Option<u64>,
+    // Whether an unbound name is an error, like `--strict`
+    pub strict: bool,
+    // Whether what programs print is kept for `Evaluation::take_output`
+    // instead of written to stdout
+    pub capture_output: bool,
+}
+
+// State shared by a whole evaluation
+pub struct Evaluation {
+    pub(crate) version: LangVersion,
+    // Number of frames created so far
+    frame_count: usize,
+    // Frames recorded for `--env-diagram`, if requested
+    pub(crate) diagram: Option<EnvDiagram>,
+    // With `--loop-limit N`, how often a call may be in progress with the
+    // same function and arguments before evaluation is aborted
+    pub(crate) loop_limit: Option<usize>,
+    // Calls in progress, by the address of the lambda's body, which its
+    // closures share, and the printed arguments
+    active_calls: HashMap<(usize, String), usize>,
+    // Facts and rules declared with `fact` and `rule`
+    pub(crate) knowledge_base: logic::KnowledgeBase,
+    // What the drawing builtins drew, once the program uses one
+    pub(crate) turtle: Option<turtle::Turtle>,
+    // Time spent in each call stack, for `--flamegraph`
+    pub(crate) profile: Option<Profile>,
+    // Values created, for `--heap-stats`
+    pub(crate) heap_stats: Option<HeapStats>,
+    // Resources used, for `--report`
+    pub(crate) report: Option<Report>,
+    // Identifier resolutions, for `--explain-lookups`
+    pub(crate) lookups: Option<LookupReport>,
+    // Events written for `--event-log`
+    pub(crate) event_log: Option<EventLog>,
+    // Time per node, for `--time-nodes`
+    pub(crate) node_times: Option<NodeTimes>,
+    // Calls to pause at, from `--break-on`
+    pub(crate) breakpoints: Vec<Breakpoint>,
+    // Frames of the lambda calls in progress, innermost last, for
+    // `--post-mortem`
+    pub(crate) call_stack: Option<Vec<Env>>,
+    // The client of `--rpc`, with the nodes to pause at
+    pub(crate) debugger: Option<rpc::Debugger>,
+    // Expressions evaluated so far
+    pub(crate) steps: u64,
+    // How many expressions may be evaluated before evaluation is aborted
+    pub(crate) fuel: Option<u64>,
+    // Expressions in progress, and how many may be before evaluation is
+    // aborted instead of overflowing the stack
+    depth: usize,
+    pub(crate) max_depth: usize,
+    // Whether an unbound name is an error rather than printed, for
+    // `--strict`
+    pub(crate) strict: bool,
+    // The largest value a builtin may return, in estimated bytes
+    max_value_bytes: usize,
+    // With `--randomize-eval-order SEED`, shuffles the order in which
+    // arguments are evaluated
+    pub(crate) eval_order: Option<Rng>,
+    // The builtins programs can call, the standard ones unless the host
+    // changed them
+    pub(crate) builtins: BuiltinRegistry,
+    // Where `print` writes to
+    pub(crate) output: Output,
+    // What the program may do besides computing, from `--allow`
+    pub(crate) capabilities: Vec<Capability>,
+    // Digits after the decimal point when formatting numbers without saying
+    // how many, from `--precision`
+    pub(crate) precision: usize,
+    // What add, sub, mul and div do with results that don't fit in a
+    // number, from `--overflow`
+    pub(crate) overflow: Overflow,
+}
+
+// Access to the world outside the program, which programs only get when it
+// is allowed with `--allow NAME`. The server never allows any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Capability {
+    Files,
+    Clock,
+    Draw,
+}
+
+impl Capability {
+    pub(crate) fn parse(name: &str) -> Result<Capability, String> {
+        match name {
+            "files" => Ok(Capability::Files),
+            "clock" => Ok(Capability::Clock),
+            "draw" => Ok(Capability::Draw),
+            _ => Err(format!(
+                "Unknown capability: {} (expected files, clock or draw)",
+                name
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Capability::Files => "files",
+            Capability::Clock => "clock",
+            Capability::Draw => "draw",
+        }
+    }
+}
+
+// What arithmetic does when a result doesn't fit in a number: fail with
+// `Overflow`, wrap around, stay at the largest or smallest number, or
+// become a float
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Overflow {
+    Error,
+    Wrap,
+    Saturate,
+    PromoteFloat,
+}
+
+impl Overflow {
+    pub(crate) fn parse(name: &str) -> Result<Overflow, String> {
+        match name {
+            "error" => Ok(Overflow::Error),
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            "promote-float" => Ok(Overflow::PromoteFloat),
+            _ => Err(format!(
+                "Unknown overflow mode: {} (expected error, wrap, saturate or promote-float)",
+                name
+            )),
+        }
+    }
+}
+
+// Where a program's output goes: the process's stdout, a buffer that is
+// returned with the result, or a sink that receives every line as it is
+// printed, as in the server
+pub(crate) enum Output {
+    Stdout,
+    Captured(String),
+    Sink(Box<dyn OutputSink>),
+}
+
+// Receives a program's output as it is printed, e.g. to stream it to a
+// client while the program is still running
+pub(crate) trait OutputSink {
+    fn write_line(&mut self, line: &str);
+}
+
+// Default limits of every evaluation. Evaluations run on threads with
+// EVALUATION_STACK_SIZE bytes of stack, which is enough for
+// DEFAULT_MAX_DEPTH nested expressions in a debug build.
+const DEFAULT_MAX_DEPTH: usize = 10_000;
+const DEFAULT_MAX_VALUE_BYTES: usize = 256 << 20;
+pub const EVALUATION_STACK_SIZE: usize = 256 << 20;
+pub(crate) const DEFAULT_PRECISION: usize = 6;
+
+impl Evaluation {
+    pub fn new(version: LangVersion) -> Evaluation {
+        Evaluation {
+            version,
+            frame_count: 1,
+            diagram: None,
+            loop_limit: None,
+            active_calls: HashMap::new(),
+            knowledge_base: logic::KnowledgeBase::default(),
+            turtle: None,
+            profile: None,
+            heap_stats: None,
+            report: None,
+            lookups: None,
+            event_log: None,
+            node_times: None,
+            breakpoints: Vec::new(),
+            call_stack: None,
+            debugger: None,
+            steps: 0,
+            fuel: None,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict: false,
+            max_value_bytes: DEFAULT_MAX_VALUE_BYTES,
+            eval_order: None,
+            builtins: BuiltinRegistry::standard(),
+            output: Output::Stdout,
+            capabilities: Vec::new(),
+            precision: DEFAULT_PRECISION,
+            overflow: Overflow::Error,
+        }
+    }
+
+    pub fn with_options(version: LangVersion, options: &EvalOptions) -> Evaluation {
+        let mut evaluation = Evaluation::new(version);
+        evaluation.set_max_steps(options.max_steps);
+        evaluation.set_strict(options.strict);
+        if options.capture_output {
+            evaluation.capture_output();
+        }
+        evaluation
+    }
+
+    // Make names that no scope binds and that aren't builtins an
+    // `UnboundVariable` error. By default they print their name and
+    // evaluate to the unit value, like in checkpoint 3.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    // Let programs call only the builtins of `registry`
+    pub fn set_builtins(&mut self, registry: BuiltinRegistry) {
+        self.builtins = registry;
+    }
+
+    // Forget everything a failed evaluation left in progress, the
+    // expressions, calls and their frames and the fuel it used, so that the
+    // evaluation can go on with another program
+    pub(crate) fn recover(&mut self) {
+        self.depth = 0;
+        self.steps = 0;
+        self.active_calls.clear();
+        if let Some(stack) = &mut self.call_stack {
+            stack.truncate(1);
+        }
+        if let Some(report) = &mut self.report {
+            report.calls = 0;
+        }
+        if let Some(lookups) = &mut self.lookups {
+            lookups.dynamic.clear();
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.stack.truncate(1);
+        }
+        if let Some(node_times) = &mut self.node_times {
+            node_times.stack.clear();
+            node_times.active.clear();
+        }
+    }
+
+    // Abort with `FuelExhausted` once more than `steps` expressions have been
+    // evaluated, so that a program that never terminates can't hang its
+    // caller. None, the default, doesn't limit the steps.
+    pub fn set_max_steps(&mut self, steps: Option<u64>) {
+        self.fuel = steps;
+    }
+
+    // Makes a fresh evaluation with the same language, limits, builtins and
+    // permissions, for evaluating part of the program on another thread.
+    // Its output is captured, and nothing is instrumented. The fuel left is
+    // split evenly between `workers` workers, and this is the one numbered
+    // `worker` of them, so that together they can't use more than this
+    // evaluation has left.
+    pub(crate) fn worker(&self, worker: u64, workers: u64) -> impl FnOnce() -> Evaluation + Send {
+        let version = self.version;
+        let fuel = self.fuel.map(|fuel| {
+            let left = fuel.saturating_sub(self.steps);
+            left / workers + u64::from(worker < left % workers)
+        });
+        let (loop_limit, max_depth) = (self.loop_limit, self.max_depth);
+        let (strict, max_value_bytes) = (self.strict, self.max_value_bytes);
+        let builtins = self.builtins.clone();
+        let capabilities = self.capabilities.clone();
+        let (precision, overflow) = (self.precision, self.overflow);
+        move || Evaluation {
+            loop_limit,
+            fuel,
+            max_depth,
+            strict,
+            max_value_bytes,
+            builtins,
+            output: Output::Captured(String::new()),
+            capabilities,
+            precision,
+            overflow,
+            ..Evaluation::new(version)
+        }
+    }
+
+    pub(crate) fn new_frame_id(&mut self) -> usize {
+        self.frame_count += 1;
+        self.frame_count - 1
+    }
+
+    // Fail unless the program may use the capability
+    pub(crate) fn require(&self, capability: Capability, builtin: &str) {
+        if !self.capabilities.contains(&capability) {
+            fail(EvalError::CapabilityDenied {
+                builtin: builtin.to_string(),
+                capability: capability.name().to_string(),
+            });
+        }
+    }
+
+    // Write a line of the program's output
+    pub(crate) fn print(&mut self, line: &str) {
+        match &mut self.output {
+            Output::Stdout => println!("{}", line),
+            Output::Captured(buffer) => {
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+            Output::Sink(sink) => sink.write_line(line),
+        }
+    }
+
+    // Keep what programs print from now on, for `take_output`, instead of
+    // writing it to stdout
+    pub fn capture_output(&mut self) {
+        self.output = Output::Captured(String::new());
+    }
+
+    // The output captured so far, leaving the buffer empty
+    pub fn take_output(&mut self) -> String {
+        match &mut self.output {
+            Output::Captured(buffer) => std::mem::take(buffer),
+            Output::Stdout | Output::Sink(_) => String::new(),
+        }
+    }
+
+    // Record an event if there is an event log
+    pub(crate) fn log(&mut self, event: &str, fields: serde_json::Value) {
+        if let Some(log) = &mut self.event_log {
+            log.write(event, fields);
+        }
+    }
+
+    // Record a call if there is an event log. The arguments are only
+    // printed then, since printing a large array takes as long as copying
+    // it.
+    fn log_call(&mut self, function: &str, arguments: &[ResultValue]) {
+        if self.event_log.is_some() {
+            let shown: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+            self.log(
+                "call",
+                serde_json::json!({"function": function, "arguments": shown}),
+            );
+        }
+    }
+
+    // Count a value that was just created or copied
+    fn allocated(&mut self, value: ResultValue) -> ResultValue {
+        if let Some(stats) = &mut self.heap_stats {
+            stats.record(&value);
+        }
+        if let Some(report) = &mut self.report {
+            report.allocations += 1;
+        }
+        value
+    }
+
+    // Call `apply` as a call of `name` in the profile, if there is one
+    fn profiled(
+        &mut self,
+        name: &str,
+        apply: impl FnOnce(&mut Evaluation) -> ResultValue,
+    ) -> ResultValue {
+        if let Some(profile) = &mut self.profile {
+            profile.enter(name);
+        }
+        let result = apply(self);
+        if let Some(profile) = &mut self.profile {
+            profile.exit();
+        }
+        result
+    }
+
+    // Whether a call in tail position may take the place of the call it is
+    // in instead of nesting inside it. Instrumentation that pairs every call
+    // with its return needs the calls nested.
+    fn tail_calls(&self) -> bool {
+        self.loop_limit.is_none()
+            && self.profile.is_none()
+            && self.report.is_none()
+            && self.lookups.is_none()
+            && self.event_log.is_none()
+            && self.node_times.is_none()
+            && self.call_stack.is_none()
+    }
+}
+
+// A call to pause at, from `--break-on NAME`: builtins by name, lambdas by
+// the variable they are called through. A condition from `--break-when`
+// restricts it to calls where the condition holds in the frame of the call.
+#[derive(Clone)]
+pub(crate) struct Breakpoint {
+    pub(crate) name: String,
+    pub(crate) condition: Option<Expr>,
+}
+
+// Whether a breakpoint stops the call of `name` in `frame`: the new frame
+// for a lambda, the caller's environment for a builtin
+fn breakpoint_hit(eval: &Evaluation, name: &str, frame: &Env) -> bool {
+    eval.breakpoints
+        .iter()
+        .filter(|breakpoint| breakpoint.name == name)
+        .any(|breakpoint| match &breakpoint.condition {
+            None => true,
+            Some(condition) => {
+                // In an evaluation of its own, so the condition isn't
+                // logged, profiled or stopped at, but with the builtins,
+                // modes and permissions of the program it inspects
+                let mut auxiliary = eval.worker(0, 1)();
+                evaluate_bool(condition, frame, &mut auxiliary)
+            }
+        })
+}
+
+// Pause before a call with a breakpoint: show the call and let
+// the user replace the arguments on the terminal. The input is a JSON list
+// without the brackets, where null keeps an argument, so `null, 7` changes
+// only the second one; an empty line continues unchanged. Without a
+// terminal the call is only shown.
+fn pause_at_call(name: &str, arguments: &mut Vec<ResultValue>) {
+    let shown: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+    eprintln!("Breakpoint: {}({})", name, shown.join(", "));
+    let Ok(terminal) = std::fs::File::open("/dev/tty") else {
+        return;
+    };
+    let mut terminal = io::BufReader::new(terminal);
+    loop {
+        eprint!("arguments> ");
+        let mut line = String::new();
+        if io::BufRead::read_line(&mut terminal, &mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        if line.trim().is_empty() {
+            return;
+        }
+        let edited: Vec<Value> = match serde_json::from_str(&format!("[{}]", line)) {
+            Ok(edited) => edited,
+            Err(err) => {
+                eprintln!("Not a list of numbers and strings: {}", err);
+                continue;
+            }
+        };
+        let mut replaced = Vec::new();
+        for (index, value) in edited.iter().enumerate() {
+            match value {
+                Value::Null if index < arguments.len() => replaced.push(arguments[index].clone()),
+                Value::String(string) => replaced.push(ResultValue::String(string.clone())),
+                value if value.is_i64() => {
+                    replaced.push(ResultValue::Number(value.as_i64().unwrap()))
+                }
+                value => {
+                    eprintln!("Can't use {} as an argument", value);
+                    break;
+                }
+            }
+        }
+        if replaced.len() != edited.len() {
+            continue;
+        }
+        *arguments = replaced;
+        let shown: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        eprintln!("Continuing with {}({})", name, shown.join(", "));
+        return;
+    }
+}
+
+// The REPL of `--post-mortem`, in the frames that were active when
+// evaluation failed. Reads commands from the terminal, since the program
+// came in on stdin; without a terminal it only prints the call chain.
+pub(crate) fn post_mortem(frames: &[Env], version: LangVersion) {
+    let print_chain = |selected: usize| {
+        for (index, frame) in frames.iter().enumerate().rev() {
+            let marker = if index == selected { ">" } else { " " };
+            let mut bindings: Vec<String> = frame
+                .vars()
+                .iter()
+                .filter(|(_, value)| !matches!(value, ResultValue::Lambda(..)))
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect();
+            bindings.sort();
+            eprintln!("{} f{}  {}", marker, frame.id, bindings.join(", "));
+        }
+    };
+    let mut selected = frames.len() - 1;
+    eprintln!("Post-mortem, in the frame where evaluation failed:");
+    print_chain(selected);
+    let Ok(terminal) = std::fs::File::open("/dev/tty") else {
+        return;
+    };
+    eprintln!("Commands: where, up, down, vars, quit, or a JSON expression to evaluate");
+    let mut terminal = io::BufReader::new(terminal);
+    loop {
+        eprint!("post-mortem> ");
+        let mut line = String::new();
+        if io::BufRead::read_line(&mut terminal, &mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        match line.trim() {
+            "" => {}
+            "quit" => return,
+            "where" => print_chain(selected),
+            "up" if selected > 0 => selected -= 1,
+            "down" if selected + 1 < frames.len() => selected += 1,
+            "up" | "down" => eprintln!("No frame there"),
+            "vars" => {
+                let vars = frames[selected].vars();
+                let mut bindings: Vec<_> = vars.iter().collect();
+                bindings.sort_by_key(|(name, _)| name.as_str());
+                for (name, value) in bindings {
+                    eprintln!("{} = {}", name, value);
+                }
+            }
+            json => {
+                let expr = serde_json::from_str::<Value>(json)
+                    .map_err(|err| err.to_string())
+                    .and_then(|json| Expr::from_json(&json));
+                let expr = match expr {
+                    Ok(expr) => expr,
+                    Err(err) => {
+                        eprintln!("Not an expression: {}", err);
+                        continue;
+                    }
+                };
+                // A failing expression only reports its error
+                let frame = &frames[selected];
+                let evaluated = std::panic::catch_unwind(|| {
+                    evaluate_expr(&expr, frame, &mut Evaluation::new(version))
+                });
+                match evaluated {
+                    // Unbound variables were already printed
+                    Ok(ResultValue::Unit) | Err(_) => {}
+                    Ok(value) => eprintln!("{}", value),
+                }
+            }
+        }
+    }
+}
+
+// The id and path of every node that may be evaluated, by address, as
+// `--list-nodes` prints them. Closures share their body with the program,
+// so the nodes of a body have the same address in every call.
+pub(crate) struct NodeIndex {
+    nodes: HashMap<*const Expr, (NodeId, NodePath)>,
+}
+
+impl NodeIndex {
+    pub(crate) fn new(program: &Expr) -> NodeIndex {
+        let mut index = NodeIndex {
+            nodes: HashMap::new(),
+        };
+        program.walk(&mut |id, path, node| {
+            index.nodes.insert(node, (id, path.clone()));
+        });
+        index
+    }
+
+    pub(crate) fn get(&self, expr: &Expr) -> Option<&(NodeId, NodePath)> {
+        self.nodes.get(&(expr as *const Expr))
+    }
+}
+
+// Writes what the evaluator does as one JSON object per line, so other tools
+// can replay an evaluation. Nodes are identified by the ids and paths of
+// `--list-nodes`.
+pub(crate) struct EventLog {
+    pub(crate) writer: BufWriter<std::fs::File>,
+    start: Instant,
+    nodes: NodeIndex,
+}
+
+impl EventLog {
+    pub(crate) fn new(path: &str, program: &Expr) -> EventLog {
+        let file = std::fs::File::create(path)
+            .unwrap_or_else(|err| panic!("Failed to create {}: {}", path, err));
+        EventLog {
+            writer: BufWriter::new(file),
+            start: Instant::now(),
+            nodes: NodeIndex::new(program),
+        }
+    }
+
+    fn write(&mut self, event: &str, mut fields: serde_json::Value) {
+        fields["event"] = event.into();
+        fields["time_us"] = (self.start.elapsed().as_micros() as u64).into();
+        writeln!(self.writer, "{}", fields).expect("Failed to write event log");
+    }
+
+    fn node(&mut self, event: &str, expr: &Expr, value: Option<&ResultValue>) {
+        let (id, path) = match self.nodes.get(expr) {
+            Some((id, path)) => (Some(id.0), Some(path.to_string())),
+            None => (None, None),
+        };
+        let mut fields = serde_json::json!({"node": id, "path": path, "kind": expr.kind()});
+        if let Some(value) = value {
+            fields["value"] = value.to_string().into();
+        }
+        self.write(event, fields);
+    }
+}
+
+// Time spent evaluating each node, for `--time-nodes`. The clock is read
+// once when a node is entered and once when it exits, and the time since
+// the previous reading goes to the node on top of the stack as its own
+// time, so a node's self time is what is left of its time after its
+// children's. A node that is in progress more than once, inside a
+// recursive call, counts its time once, for the outermost evaluation.
+pub(crate) struct NodeTimes {
+    nodes: NodeIndex,
+    // Nodes in progress, innermost last, with when they were entered
+    stack: Vec<(Option<NodeId>, Instant)>,
+    // How often each node is in progress
+    active: HashMap<NodeId, usize>,
+    last: Instant,
+    rows: HashMap<NodeId, NodeTime>,
+}
+
+struct NodeTime {
+    path: NodePath,
+    label: String,
+    count: u64,
+    total: Duration,
+    own: Duration,
+}
+
+impl NodeTimes {
+    pub(crate) fn new(program: &Expr) -> NodeTimes {
+        NodeTimes {
+            nodes: NodeIndex::new(program),
+            stack: Vec::new(),
+            active: HashMap::new(),
+            last: Instant::now(),
+            rows: HashMap::new(),
+        }
+    }
+
+    // Charge the time since the previous reading to the innermost node
+    fn tick(&mut self) -> Instant {
+        let now = Instant::now();
+        if let Some((Some(id), _)) = self.stack.last() {
+            if let Some(row) = self.rows.get_mut(id) {
+                row.own += now - self.last;
+            }
+        }
+        self.last = now;
+        now
+    }
+
+    fn enter(&mut self, expr: &Expr) {
+        let now = self.tick();
+        let id = self.nodes.get(expr).map(|(id, path)| {
+            self.rows.entry(*id).or_insert_with(|| NodeTime {
+                path: path.clone(),
+                label: expr.label(),
+                count: 0,
+                total: Duration::ZERO,
+                own: Duration::ZERO,
+            });
+            *self.active.entry(*id).or_insert(0) += 1;
+            *id
+        });
+        self.stack.push((id, now));
+    }
+
+    fn exit(&mut self) {
+        let now = self.tick();
+        let Some((Some(id), start)) = self.stack.pop() else {
+            return;
+        };
+        let active = self.active.get_mut(&id).expect("Node entered");
+        *active -= 1;
+        let outermost = *active == 0;
+        let row = self.rows.get_mut(&id).expect("Node entered");
+        row.count += 1;
+        if outermost {
+            row.total += now - start;
+        }
+    }
+}
+
+impl fmt::Display for NodeTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rows: Vec<(&NodeId, &NodeTime)> = self.rows.iter().collect();
+        rows.sort_by_key(|(id, row)| (std::cmp::Reverse(row.total), id.0));
+        writeln!(
+            f,
+            "{:<6} {:<12} {:>10} {:>12} {:>12}  node",
+            "id", "path", "count", "total ms", "self ms"
+        )?;
+        for (id, row) in rows {
+            writeln!(
+                f,
+                "{:<6} {:<12} {:>10} {:>12.3} {:>12.3}  {}",
+                id.to_string(),
+                row.path.to_string(),
+                row.count,
+                row.total.as_secs_f64() * 1000.0,
+                row.own.as_secs_f64() * 1000.0,
+                row.label
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Counts and approximate bytes of the values created during an evaluation,
+// by kind. Looking up a variable copies its value, but closures share the
+// environment they are created in, so a closure's bytes are its code and
+// not what it captured.
+#[derive(Default)]
+pub(crate) struct HeapStats {
+    kinds: BTreeMap<&'static str, (usize, usize)>,
+    // How many closures captured environments with that many bindings
+    captured_bindings: BTreeMap<usize, usize>,
+}
+
+impl HeapStats {
+    fn record(&mut self, value: &ResultValue) {
+        if let ResultValue::Lambda(_, _, env, _) = value {
+            *self
+                .captured_bindings
+                .entry(env.binding_count())
+                .or_insert(0) += 1;
+        }
+        let entry = self.kinds.entry(value.kind()).or_insert((0, 0));
+        entry.0 += 1;
+        entry.1 += value.estimated_size();
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<10} {:>10} {:>12}", "kind", "count", "bytes")?;
+        for (kind, (count, bytes)) in &self.kinds {
+            writeln!(f, "{:<10} {:>10} {:>12}", kind, count, bytes)?;
+        }
+        writeln!(f, "closures by captured bindings:")?;
+        for (bindings, count) in &self.captured_bindings {
+            writeln!(f, "{:>10} {:>10}", bindings, count)?;
+        }
+        Ok(())
+    }
+}
+
+// Resources used by an evaluation, for `--report`. Memory is estimated like
+// in `--heap-stats`; the peak is the largest environment of a lambda call,
+// which holds every value the call can reach.
+#[derive(Default)]
+pub(crate) struct Report {
+    pub(crate) steps: u64,
+    peak_memory: usize,
+    pub(crate) wall_time: Duration,
+    allocations: usize,
+    // Lambda calls in progress, and the most there were at once
+    calls: usize,
+    deepest_stack: usize,
+}
+
+impl Report {
+    fn enter(&mut self, frame: &Env) {
+        self.calls += 1;
+        self.deepest_stack = self.deepest_stack.max(self.calls);
+        self.peak_memory = self.peak_memory.max(frame.estimated_size());
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14} {:>12}", "steps", self.steps)?;
+        writeln!(f, "{:<14} {:>12} bytes", "peak memory", self.peak_memory)?;
+        writeln!(
+            f,
+            "{:<14} {:>12.3} ms",
+            "wall time",
+            self.wall_time.as_secs_f64() * 1000.0
+        )?;
+        writeln!(f, "{:<14} {:>12}", "allocations", self.allocations)?;
+        writeln!(f, "{:<14} {:>12}", "deepest stack", self.deepest_stack)
+    }
+}
+
+// Every identifier resolution, for `--explain-lookups`: where lexical
+// scope found the value, next to what dynamic scope would have found by
+// searching the frames of the calls in progress, innermost first, and then
+// the globals. Names neither view binds, like most builtin calls, are left
+// out.
+#[derive(Default)]
+pub(crate) struct LookupReport {
+    // The id and bindings of the frames of the calls in progress, innermost
+    // last
+    dynamic: Vec<(usize, Vars)>,
+    // Distinct resolutions in the order they first happened, and how often
+    rows: Vec<(Lookup, usize)>,
+}
+
+// A value and the scope it came from under each discipline
+#[derive(PartialEq)]
+struct Lookup {
+    name: String,
+    lexical: String,
+    dynamic: String,
+    differs: bool,
+}
+
+impl LookupReport {
+    fn resolve(&mut self, name: Symbol, env: &Env) {
+        if name.starts_with(BUILTIN_PREFIX) {
+            return;
+        }
+        let mut lexical = None;
+        let mut frame = Some(env);
+        let mut out = 0;
+        while let Some(current) = frame {
+            if let Some(value) = current.vars().get(name).cloned() {
+                let scope = match (&current.parent, out) {
+                    (None, _) => "global".to_string(),
+                    (Some(_), 0) => format!("frame {}, local", current.id),
+                    (Some(_), out) => format!("frame {}, {} out", current.id, out),
+                };
+                lexical = Some((value, scope));
+                break;
+            }
+            frame = current.parent.as_deref();
+            out += 1;
+        }
+        let mut globals = env;
+        while let Some(parent) = &globals.parent {
+            globals = parent;
+        }
+        let dynamic = self
+            .dynamic
+            .iter()
+            .rev()
+            .find_map(|(id, vars)| Some((vars.get(name)?.clone(), format!("frame {}", id))))
+            .or_else(|| Some((globals.vars().get(name)?.clone(), "global".to_string())));
+        if lexical.is_none() && dynamic.is_none() {
+            return;
+        }
+        let describe = |found: &Option<(ResultValue, String)>| match found {
+            Some((value, scope)) => format!("{} ({})", value.quoted(), scope),
+            None if BUILTINS.contains(&name.as_str()) => "builtin".to_string(),
+            None => "unbound".to_string(),
+        };
+        let differs = match (&lexical, &dynamic) {
+            (Some((lexical, _)), Some((dynamic, _))) => lexical.quoted() != dynamic.quoted(),
+            _ => true,
+        };
+        let lookup = Lookup {
+            name: name.to_string(),
+            lexical: describe(&lexical),
+            dynamic: describe(&dynamic),
+            differs,
+        };
+        match self.rows.iter_mut().find(|(row, _)| *row == lookup) {
+            Some((_, count)) => *count += 1,
+            None => self.rows.push((lookup, 1)),
+        }
+    }
+}
+
+// A table of the resolutions, with the ones where the two disciplines
+// disagree marked
+impl fmt::Display for LookupReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = |column: fn(&Lookup) -> &str, title: &str| {
+            self.rows
+                .iter()
+                .map(|(row, _)| column(row).chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or(0)
+        };
+        let name = width(|row| &row.name, "name");
+        let lexical = width(|row| &row.lexical, "lexical");
+        let dynamic = width(|row| &row.dynamic, "dynamic");
+        writeln!(
+            f,
+            "{:<name$}  {:<lexical$}  {:<dynamic$}  count",
+            "name", "lexical", "dynamic"
+        )?;
+        for (row, count) in &self.rows {
+            let marker = if row.differs { "  differs" } else { "" };
+            writeln!(
+                f,
+                "{:<name$}  {:<lexical$}  {:<dynamic$}  {:>5}{}",
+                row.name, row.lexical, row.dynamic, count, marker
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A call in progress: its name, when it started and how long its callees took
+struct ProfileCall {
+    name: String,
+    start: Instant,
+    callees: Duration,
+}
+
+// Self time of every call stack, written in the folded-stack format that
+// `inferno-flamegraph` and `flamegraph.pl` read: the names of the stack from
+// the outside in separated by `;`, a space and the nanoseconds spent in the
+// innermost call itself
+pub(crate) struct Profile {
+    stack: Vec<ProfileCall>,
+    folded: HashMap<String, u128>,
+}
+
+impl Profile {
+    pub(crate) fn new() -> Profile {
+        Profile {
+            stack: vec![ProfileCall {
+                name: "main".to_string(),
+                start: Instant::now(),
+                callees: Duration::ZERO,
+            }],
+            folded: HashMap::new(),
+        }
+    }
+
+    fn enter(&mut self, name: &str) {
+        self.stack.push(ProfileCall {
+            name: name.to_string(),
+            start: Instant::now(),
+            callees: Duration::ZERO,
+        });
+    }
+
+    fn exit(&mut self) {
+        let names: Vec<&str> = self.stack.iter().map(|call| call.name.as_str()).collect();
+        let key = names.join(";");
+        let call = self.stack.pop().expect("Profile exit without enter");
+        let total = call.start.elapsed();
+        *self.folded.entry(key).or_insert(0) += (total - call.callees).as_nanos();
+        if let Some(caller) = self.stack.last_mut() {
+            caller.callees += total;
+        }
+    }
+
+    // End the main call and list the stacks, sorted so the output is stable
+    pub(crate) fn into_folded(mut self) -> String {
+        self.exit();
+        let mut lines: Vec<String> = self
+            .folded
+            .iter()
+            .map(|(stack, nanos)| format!("{} {}\n", stack, nanos))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+// A frame created by applying a lambda, for environment diagrams
+struct Frame {
+    // The frame the lambda was created in; only the global frame has none
+    parent: Option<usize>,
+    name: String,
+    bindings: Vec<(String, ResultValue)>,
+}
+
+// Record of every frame created during an evaluation, drawn like the
+// box-and-pointer environment diagrams of the scoping lectures
+pub(crate) struct EnvDiagram {
+    // Indexed by frame id
+    frames: Vec<Frame>,
+}
+
+impl EnvDiagram {
+    pub(crate) fn new(globals: &Env) -> EnvDiagram {
+        let mut diagram = EnvDiagram { frames: Vec::new() };
+        diagram.enter(globals, &[]);
+        diagram.frames[0].name = "global".to_string();
+        diagram
+    }
+
+    // A frame was created; `parameters` gives the order of its bindings
+    fn enter(&mut self, frame: &Env, parameters: &[String]) {
+        let mut bindings: Vec<(String, ResultValue)> = frame
+            .vars()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        bindings.sort_by_key(|(name, _)| {
+            let position = parameters.iter().position(|parameter| parameter == name);
+            (position, name.clone())
+        });
+        self.frames.push(Frame {
+            parent: frame.parent.as_ref().map(|parent| parent.id),
+            name: format!("f{}: λ({})", frame.id, parameters.join(", ")),
+            bindings,
+        });
+    }
+
+    // Graphviz rendering: frames are boxes pointing at their parent, lambdas
+    // bound to a name are closures pointing at the frame they came from
+    pub(crate) fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph environments {\n    node [shape=record];\n");
+        for (id, frame) in self.frames.iter().enumerate() {
+            let mut label = escape_dot(&frame.name);
+            for (name, value) in &frame.bindings {
+                match value {
+                    ResultValue::Lambda(..) => label.push_str(&format!("|<{}> {} = ●", name, name)),
+                    _ => label.push_str(&format!("|{} = {}", name, escape_dot(&value.to_string()))),
+                }
+            }
+            dot.push_str(&format!("    frame{} [label=\"{{{}}}\"];\n", id, label));
+            if let Some(parent) = frame.parent {
+                dot.push_str(&format!("    frame{} -> frame{};\n", id, parent));
+            }
+            for (name, value) in &frame.bindings {
+                if let ResultValue::Lambda(_, _, env, _) = value {
+                    let closure = format!("closure{}_{}", id, name);
+                    dot.push_str(&format!(
+                        "    {} [shape=ellipse, label=\"{}\"];\n",
+                        closure,
+                        escape_dot(&value.to_string())
+                    ));
+                    dot.push_str(&format!("    frame{}:{} -> {};\n", id, name, closure));
+                    dot.push_str(&format!(
+                        "    {} -> frame{} [style=dashed];\n",
+                        closure, env.id
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        let frames: Vec<Value> = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                let bindings: Vec<Value> = frame
+                    .bindings
+                    .iter()
+                    .map(|(name, value)| match value {
+                        ResultValue::Lambda(_, _, env, _) => serde_json::json!({
+                            "name": name,
+                            "closure": value.to_string(),
+                            "env": env.id,
+                        }),
+                        _ => serde_json::json!({ "name": name, "value": value.to_string() }),
+                    })
+                    .collect();
+                serde_json::json!({
+                    "id": id,
+                    "name": frame.name,
+                    "parent": frame.parent,
+                    "bindings": bindings,
+                })
+            })
+            .collect();
+        serde_json::json!({ "frames": frames })
+    }
+}
+
+// Escape the characters Graphviz treats specially in record labels
+fn escape_dot(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "{}|<>\"\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Mutation testing hooks. With the `mutations` feature, the mutation named
+// by `INTERPRETER_MUTATION` changes one detail of the evaluator, and
+// `mutationtest.sh` checks that the golden tests notice.
+#[cfg(feature = "mutations")]
+fn mutated(name: &str) -> bool {
+    std::env::var("INTERPRETER_MUTATION").is_ok_and(|mutation| mutation == name)
+}
+
+#[cfg(not(feature = "mutations"))]
+fn mutated(_name: &str) -> bool {
+    false
+}
+
+// Function to evaluate a boolean expression
+fn evaluate_bool(expr: &Expr, env: &Env, eval: &mut Evaluation) -> bool {
+    match expr {
+        Expr::Identifier(identifier) | Expr::Local(identifier, _, _) => match identifier.as_str() {
+            "true" => true,
+            "false" => false,
+            _ => fail(EvalError::InvalidCondition(expr.to_string())),
+        },
+        Expr::Bool(value) => *value,
+        Expr::Application(application) => {
+            if let Some(Expr::Identifier(operator) | Expr::Local(operator, _, _)) =
+                application.first()
+            {
+                if operator == "zero?" {
+                    let zero = match evaluate_expr(application.get(1).unwrap(), env, eval) {
+                        ResultValue::Number(number) => number == 0,
+                        ResultValue::Float(float) => float == 0.0,
+                        value => fail(EvalError::TypeError(format!(
+                            "Expected a number but got {}",
+                            value
+                        ))),
+                    };
+                    if mutated("zero-is-nonzero") {
+                        return !zero;
+                    }
+                    return zero;
+                }
+                if !["=", "<", "<=", ">", ">="].contains(&operator.as_str()) {
+                    // From checkpoint 4 on, any call that returns a boolean
+                    // is a test
+                    if !eval.version.boolean_calls() {
+                        fail(EvalError::UnknownOperator(operator.to_string()));
+                    }
+                    return match evaluate_expr(expr, env, eval) {
+                        ResultValue::Bool(value) => value,
+                        value => fail(EvalError::TypeError(format!(
+                            "Expected a boolean but got {}",
+                            value
+                        ))),
+                    };
+                }
+                let left = evaluate_expr(application.get(1).unwrap(), env, eval);
+                let right = evaluate_expr(application.get(2).unwrap(), env, eval);
+                // Numbers, strings, dates, bytes and arrays can be compared
+                let ordering = left.compare(&right).unwrap_or_else(|err| fail(err));
+                match operator.as_str() {
+                    "=" if mutated("equal-is-unequal") => ordering != Ordering::Equal,
+                    "=" => ordering == Ordering::Equal,
+                    "<" if mutated("less-is-greater-equal") => ordering != Ordering::Less,
+                    "<" => ordering == Ordering::Less,
+                    "<=" => ordering != Ordering::Greater,
+                    ">" => ordering == Ordering::Greater,
+                    ">=" => ordering != Ordering::Less,
+                    _ => fail(EvalError::InvalidCondition(expr.to_string())),
+                }
+            } else {
+                fail(EvalError::InvalidCondition(expr.to_string()));
+            }
+        }
+        _ => fail(EvalError::InvalidCondition(expr.to_string())),
+    }
+}
+
+// What is left of evaluating an expression in tail position: its value, or
+// a call of a lambda whose result is the value, for the caller to make so
+// that recursion in tail position doesn't nest. Only calls that can't fail
+// before the lambda's body is evaluated are left to the caller, since the
+// node of the call is gone by then.
+enum Tail {
+    Value(ResultValue),
+    Call(ResultValue, Vec<ResultValue>),
+}
+
+impl Tail {
+    fn finish(self, eval: &mut Evaluation) -> ResultValue {
+        match self {
+            Tail::Value(value) => value,
+            Tail::Call(function, arguments) => apply_function(function, arguments, eval),
+        }
+    }
+}
+
+// Function to evaluate an expression
+pub fn evaluate_expr(expr: &Expr, env: &Env, eval: &mut Evaluation) -> ResultValue {
+    evaluate_position(expr, env, eval, false).finish(eval)
+}
+
+// Evaluate an expression, leaving a call to the caller if it is in `tail`
+// position and tail calls are possible
+fn evaluate_position(expr: &Expr, env: &Env, eval: &mut Evaluation, tail: bool) -> Tail {
+    let tail = tail && eval.tail_calls();
+    let _guard = NodeGuard(expr);
+    eval.steps += 1;
+    if eval.fuel.is_some_and(|fuel| eval.steps > fuel) {
+        fail(EvalError::FuelExhausted(eval.steps - 1));
+    }
+    if eval.debugger.is_some() {
+        rpc::pause_at_node(expr, env, eval);
+    }
+    if eval.depth >= eval.max_depth {
+        fail(EvalError::LimitExceeded(format!(
+            "Too deeply nested: over {} expressions in progress",
+            eval.max_depth
+        )));
+    }
+    eval.depth += 1;
+    if let Some(times) = &mut eval.node_times {
+        times.enter(expr);
+    }
+    let value = if let Some(log) = &mut eval.event_log {
+        log.node("enter", expr, None);
+        let value = evaluate_node(expr, env, eval, tail);
+        if let (Some(log), Tail::Value(value)) = (&mut eval.event_log, &value) {
+            log.node("exit", expr, Some(value));
+        }
+        value
+    } else {
+        evaluate_node(expr, env, eval, tail)
+    };
+    if let Some(times) = &mut eval.node_times {
+        times.exit();
+    }
+    eval.depth -= 1;
+    value
+}
+
+// Evaluate an expression without logging it
+fn evaluate_node(expr: &Expr, env: &Env, eval: &mut Evaluation, tail: bool) -> Tail {
+    match expr {
+        Expr::Application(application) => {
+            if let Some(Expr::Identifier(name)) = application.first() {
+                // Handle procedures like "add", "sub", etc., unless a
+                // variable of the same name shadows them
+                if let Resolution::Builtin(identifier) = resolve(*name, env) {
+                    if let Some(lookups) = &mut eval.lookups {
+                        lookups.resolve(*name, env);
+                    }
+                    let mut arguments = evaluate_arguments(&application[1..], env, eval);
+                    if breakpoint_hit(eval, identifier, env) {
+                        pause_at_call(identifier, &mut arguments);
+                    }
+                    eval.log_call(identifier, &arguments);
+                    let result = eval.profiled(identifier, |eval| {
+                        apply_builtin(identifier, arguments, eval)
+                    });
+                    if result.fresh_size() > eval.max_value_bytes {
+                        fail(EvalError::LimitExceeded(format!(
+                            "Out of memory: {} returned more than {} bytes",
+                            identifier, eval.max_value_bytes
+                        )));
+                    }
+                    return Tail::Value(eval.allocated(result));
+                }
+            }
+            let function = evaluate_expr(&application[0], env, eval);
+            if let ResultValue::Number(_) | ResultValue::String(_) = function {
+                // Checkpoint 3 returned the value of a variable applied like a function
+                return Tail::Value(function);
+            }
+            let mut arguments = evaluate_arguments(&application[1..], env, eval);
+            // Lambdas are named after the variable they are called through
+            let name = match &application[0] {
+                Expr::Identifier(name) | Expr::Local(name, _, _) => name.as_str(),
+                _ => "λ",
+            };
+            if let ResultValue::Lambda(parameters, _, closure_env, _) = &function {
+                if eval
+                    .breakpoints
+                    .iter()
+                    .any(|breakpoint| breakpoint.name == name)
+                {
+                    // The frame the call is about to create
+                    let frame = Env::frame(
+                        eval.frame_count,
+                        parameters.iter().cloned().zip(arguments.clone()).collect(),
+                        Some(closure_env.clone().tie()),
+                    );
+                    if breakpoint_hit(eval, name, &frame) {
+                        pause_at_call(name, &mut arguments);
+                    }
+                }
+            }
+            eval.log_call(name, &arguments);
+            if let ResultValue::Lambda(parameters, _, _, _) = &function {
+                if tail && arguments.len() >= parameters.len() {
+                    return Tail::Call(function, arguments);
+                }
+            }
+            return Tail::Value(
+                eval.profiled(name, |eval| apply_function(function, arguments, eval)),
+            );
+        }
+        // Handle conditional expressions
+        Expr::Cond(clauses) => {
+            let mut clauses: Vec<&(Expr, Expr)> = clauses.iter().collect();
+            if mutated("cond-last-clause-first") {
+                clauses.reverse();
+            }
+            for (test, result) in clauses {
+                if evaluate_bool(test, env, eval) {
+                    return evaluate_position(result, env, eval, tail);
+                }
+            }
+        }
+        // A lambda captures the environment it is created in
+        Expr::Lambda(parameters, block, site) => {
+            let closure = ResultValue::Lambda(
+                parameters.clone(),
+                block.clone(),
+                env.clone(),
+                site.0.clone(),
+            );
+            return Tail::Value(eval.allocated(closure));
+        }
+        // Treat an identifier as a variable reference
+        Expr::Identifier(identifier) => {
+            if let Some(lookups) = &mut eval.lookups {
+                lookups.resolve(*identifier, env);
+            }
+            return Tail::Value(match resolve(*identifier, env) {
+                Resolution::Variable(value) => eval.allocated(value),
+                Resolution::Builtin(_) => unbound_value(*identifier, eval),
+            });
+        }
+        // A resolved variable is in a known slot of a known frame
+        Expr::Local(identifier, depth, slot) => {
+            if let Some(lookups) = &mut eval.lookups {
+                lookups.resolve(*identifier, env);
+            }
+            return Tail::Value(eval.allocated(env.slot(*depth, *slot)));
+        }
+        // Literals evaluate their elements left to right; a later entry of a
+        // dict replaces an earlier one with the same key
+        Expr::Array(elements) => {
+            let values = elements
+                .iter()
+                .map(|element| evaluate_expr(element, env, eval))
+                .collect();
+            return Tail::Value(eval.allocated(ResultValue::Array(values)));
+        }
+        Expr::Dict(entries) => {
+            let mut values: Vec<(String, ResultValue)> = Vec::new();
+            for (key, value) in entries {
+                let value = evaluate_expr(value, env, eval);
+                match values.iter_mut().find(|(existing, _)| existing == key) {
+                    Some(entry) => entry.1 = value,
+                    None => values.push((key.clone(), value)),
+                }
+            }
+            return Tail::Value(eval.allocated(ResultValue::Dict(values)));
+        }
+        // The lambdas are created in a frame of their own that is tied to
+        // itself (see `Env::tie`) before the body is evaluated in it
+        Expr::Letrec(bindings, body) => {
+            if !eval.version.letrec() {
+                fail(EvalError::Other(
+                    "letrec needs --lang-version cp4".to_string(),
+                ));
+            }
+            let mut frame = Env::frame(eval.new_frame_id(), Vars::default(), Some(env.clone()));
+            frame.recursive = true;
+            let mut vars = Vars::default();
+            for (name, value) in bindings {
+                if !matches!(value, Expr::Lambda(_, _, _)) {
+                    fail(EvalError::TypeError(format!(
+                        "letrec can only bind lambdas, but {} is bound to {}",
+                        name, value
+                    )));
+                }
+                vars.insert(name.clone(), evaluate_expr(value, &frame, eval));
+            }
+            // In a frame of their own, which the closures don't hold
+            let mut frame = Env::frame(frame.id, vars, Some(env.clone()));
+            frame.recursive = true;
+            return evaluate_body(body, &frame.tie(), eval, tail);
+        }
+        // If it's a direct number or string, return it
+        Expr::Number(number) => return Tail::Value(eval.allocated(ResultValue::Number(*number))),
+        Expr::String(string) => {
+            return Tail::Value(eval.allocated(ResultValue::String(string.clone())))
+        }
+        Expr::Bool(value) => return Tail::Value(eval.allocated(ResultValue::Bool(*value))),
+    }
+    fail(EvalError::NoMatchingClause(expr.to_string()));
+}
+
+// Call a lambda or a function wrapped in a contract
+pub fn apply_function(
+    mut function: ResultValue,
+    mut arguments: Vec<ResultValue>,
+    eval: &mut Evaluation,
+) -> ResultValue {
+    loop {
+        match function {
+            ResultValue::Lambda(parameters, block, closure_env, _) => {
+                // Bind the arguments in a new frame inside the lambda's environment
+                if arguments.len() < parameters.len() {
+                    fail(EvalError::ArityMismatch {
+                        expected: parameters.len(),
+                        got: arguments.len(),
+                    });
+                }
+                let frame = Env::frame(
+                    eval.new_frame_id(),
+                    parameters.iter().cloned().zip(arguments).collect(),
+                    Some(closure_env.tie()),
+                );
+                if let Some(diagram) = &mut eval.diagram {
+                    diagram.enter(&frame, &parameters);
+                }
+                if let Some(log) = &mut eval.event_log {
+                    for parameter in &parameters {
+                        let value = frame.vars()[parameter.as_str()].to_string();
+                        log.write(
+                        "bind",
+                        serde_json::json!({"frame": frame.id, "name": parameter, "value": value}),
+                    );
+                    }
+                }
+                if let Some(stack) = &mut eval.call_stack {
+                    stack.push(frame.clone());
+                }
+                if let Some(report) = &mut eval.report {
+                    report.enter(&frame);
+                }
+                if let Some(lookups) = &mut eval.lookups {
+                    lookups.dynamic.push((frame.id, frame.vars().clone()));
+                }
+                let result = match eval.loop_limit {
+                    None => evaluate_body(&block, &frame, eval, true),
+                    Some(limit) => {
+                        // Programs are pure, so a call that is still in progress
+                        // with the same arguments will never return
+                        let arguments: Vec<String> = parameters
+                            .iter()
+                            .map(|parameter| frame.vars()[parameter.as_str()].to_string())
+                            .collect();
+                        let key = (block.as_ptr() as usize, arguments.join(", "));
+                        let count = eval.active_calls.entry(key.clone()).or_insert(0);
+                        *count += 1;
+                        if *count >= limit {
+                            let function =
+                                Expr::Lambda(parameters.clone(), block.clone(), Site::default());
+                            fail(EvalError::LimitExceeded(format!(
+                            "Possible infinite loop: same (function, arguments) seen {} times: {}({})",
+                            limit, function, key.1
+                        )));
+                        }
+                        let result = evaluate_block(&block, &frame, eval);
+                        *eval.active_calls.get_mut(&key).unwrap() -= 1;
+                        Tail::Value(result)
+                    }
+                };
+                // Frames stay on the stack when evaluation fails, for
+                // `--post-mortem`
+                if let Some(stack) = &mut eval.call_stack {
+                    stack.pop();
+                }
+                if let Some(report) = &mut eval.report {
+                    report.calls -= 1;
+                }
+                if let Some(lookups) = &mut eval.lookups {
+                    lookups.dynamic.pop();
+                }
+                match result {
+                    Tail::Value(value) => return value,
+                    // The call in tail position takes the place of this one
+                    Tail::Call(next, next_arguments) => {
+                        function = next;
+                        arguments = next_arguments;
+                    }
+                }
+            }
+            // The caller is to blame when the arguments break the precondition,
+            // the function when its result breaks the postcondition
+            ResultValue::Contract(contract) => {
+                let (pre, post, function) = *contract;
+                let shown: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                if !holds(pre.clone(), arguments.clone(), eval) {
+                    fail(EvalError::ContractViolation(format!(
+                    "Contract violation: precondition {} does not hold for arguments ({}) of {}. Blaming: the caller",
+                    pre,
+                    shown.join(", "),
+                    function
+                )));
+                }
+                let result = apply_function(function.clone(), arguments, eval);
+                if !holds(post.clone(), vec![result.clone()], eval) {
+                    fail(EvalError::ContractViolation(format!(
+                    "Contract violation: postcondition {} does not hold for result {} of {} applied to ({}). Blaming: {}",
+                    post,
+                    result,
+                    function,
+                    shown.join(", "),
+                    function
+                )));
+                }
+                return result;
+            }
+            value => fail(EvalError::TypeError(format!("Can't apply {}", value))),
+        }
+    }
+}
+
+// Evaluate the body of a lambda
+pub(crate) fn evaluate_block(block: &[Expr], env: &Env, eval: &mut Evaluation) -> ResultValue {
+    evaluate_body(block, env, eval, false).finish(eval)
+}
+
+// The frame a block is evaluated in from checkpoint 4 on: an empty one of
+// its own inside `env`, which the resolver counts too
+pub(crate) fn block_scope(env: &Env, eval: &mut Evaluation) -> Env {
+    Env::frame(eval.new_frame_id(), Vars::default(), Some(env.clone()))
+}
+
+// Evaluate a body whose last expression is in `tail` position
+fn evaluate_body(block: &[Expr], env: &Env, eval: &mut Evaluation, tail: bool) -> Tail {
+    if eval.version.block_scoping() {
+        // Evaluate every expression in order in the block's own scope, the
+        // last one is the result
+        let Some((last, rest)) = block.split_last() else {
+            return Tail::Value(ResultValue::Unit);
+        };
+        let scope = block_scope(env, eval);
+        for item in rest {
+            evaluate_expr(item, &scope, eval);
+        }
+        return evaluate_position(last, &scope, eval, tail);
+    }
+    match block.first() {
+        Some(expr) => evaluate_position(expr, env, eval, tail),
+        None => Tail::Value(ResultValue::Unit),
+    }
+}
+
+// The value of a name that no scope binds. Builtins can only be called, so
+// passing one to `map` or `zipWith` takes a lambda around it; other names
+// print themselves and are the unit value, unless `--strict`.
+pub(crate) fn unbound_value(name: Symbol, eval: &mut Evaluation) -> ResultValue {
+    if name.starts_with(BUILTIN_PREFIX) || eval.builtins.contains(&name) {
+        fail(EvalError::TypeError(format!(
+            "{} is a builtin and can only be called, wrap it in a lambda to pass it as a value",
+            builtin_name(&name)
+        )))
+    }
+    if eval.strict {
+        fail(EvalError::UnboundVariable(name.to_string()))
+    }
+    eval.print(&name);
+    ResultValue::Unit
+}
+
+// Evaluate the arguments of a call. They are evaluated left to right, after
+// the function position; `--randomize-eval-order` shuffles them instead to
+// find programs that depend on the order.
+fn evaluate_arguments(arguments: &[Expr], env: &Env, eval: &mut Evaluation) -> Vec<ResultValue> {
+    if let Some(rng) = &mut eval.eval_order {
+        let mut order: Vec<usize> = (0..arguments.len()).collect();
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.below(i + 1));
+        }
+        let mut values = vec![None; arguments.len()];
+        for i in order {
+            values[i] = Some(evaluate_expr(&arguments[i], env, eval));
+        }
+        return values.into_iter().flatten().collect();
+    }
+    if mutated("arguments-right-to-left") {
+        let mut values: Vec<ResultValue> = arguments
+            .iter()
+            .rev()
+            .map(|argument| evaluate_expr(argument, env, eval))
+            .collect();
+        values.reverse();
+        return values;
+    }
+    arguments
+        .iter()
+        .map(|argument| evaluate_expr(argument, env, eval))
+        .collect()
+}
+
+// The value of a number literal
+pub(crate) fn as_number(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Number(number) => Some(*number),
+        _ => None,
+    }
+}
+
+// Why an evaluation failed. Errors are raised as panics carrying the
+// EvalError, like every failure of the evaluator, so embedders that want to
+// match on them call `interpreter::try_eval`; other failures come out as
+// `Other` with their message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    // A lambda called with fewer arguments than it has parameters
+    ArityMismatch { expected: usize, got: usize },
+    // A value of the wrong kind, like calling a number or adding strings
+    TypeError(String),
+    // A cond test that isn't a boolean expression, as written
+    InvalidCondition(String),
+    // A cond test calling something other than a comparison or `zero?`,
+    // before checkpoint 4, as the name called
+    UnknownOperator(String),
+    // A cond none of whose tests hold, as written
+    NoMatchingClause(String),
+    // A name that no scope binds and that isn't a builtin, looked up in
+    // strict mode
+    UnboundVariable(String),
+    // A call of a name that no scope binds and that isn't a builtin
+    UnknownProcedure(String),
+    DivisionByZero,
+    // An arithmetic result too large for a number, with `--overflow error`,
+    // as the call that computed it
+    Overflow(String),
+    IndexOutOfBounds { index: i64, len: usize },
+    // Two values `compare` can't order, as their kind and value
+    Incomparable { left: String, right: String },
+    // A builtin that needs a capability the evaluation doesn't have
+    CapabilityDenied { builtin: String, capability: String },
+    // A builtin put out of use with `--override-builtin NAME=disabled`
+    DisabledBuiltin(String),
+    // A file a builtin couldn't read or write, and why
+    IoError(String),
+    ContractViolation(String),
+    // More expressions evaluated than the step budget allows, as the budget
+    FuelExhausted(u64),
+    // Depth, value size or loop limit reached
+    LimitExceeded(String),
+    Other(String),
+    // Another error, with the node of the program it happened at
+    At(Box<EvalError>, Location),
+}
+
+impl EvalError {
+    // The error without its location
+    pub fn kind(&self) -> &EvalError {
+        match self {
+            EvalError::At(error, _) => error.kind(),
+            error => error,
+        }
+    }
+}
+
+// A node of a program: its path, as accepted by `--show-node`, and where
+// it is written when the front end recorded that
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: NodePath,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}", self.path)?;
+        if let Some(span) = self.span {
+            write!(f, " ({})", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::ArityMismatch { expected, got } => {
+                write!(f, "Expected {} arguments but got {}", expected, got)
+            }
+            EvalError::InvalidCondition(test) => {
+                write!(f, "Not a known boolean expression: {}", test)
+            }
+            EvalError::UnknownOperator(operator) => {
+                write!(f, "Unknown boolean operator: {}", operator)
+            }
+            EvalError::NoMatchingClause(cond) => write!(f, "No clause matches in {}", cond),
+            EvalError::UnboundVariable(name) => write!(f, "Unbound variable: {}", name),
+            EvalError::UnknownProcedure(name) => write!(f, "Unknown procedure: {}", name),
+            EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::Overflow(call) => write!(f, "Overflow: {}", call),
+            EvalError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index out of bounds: {} for length {}", index, len)
+            }
+            EvalError::Incomparable { left, right } => {
+                write!(f, "Can't compare {} with {}", left, right)
+            }
+            EvalError::CapabilityDenied {
+                builtin,
+                capability,
+            } => write!(
+                f,
+                "Capability denied: {} needs {} (run with --allow {})",
+                builtin, capability, capability
+            ),
+            EvalError::DisabledBuiltin(name) => write!(f, "This builtin is disabled: {}", name),
+            EvalError::FuelExhausted(steps) => write!(f, "Fuel exhausted after {} steps", steps),
+            EvalError::TypeError(message)
+            | EvalError::ContractViolation(message)
+            | EvalError::LimitExceeded(message)
+            | EvalError::IoError(message)
+            | EvalError::Other(message) => write!(f, "{}", message),
+            EvalError::At(error, location) => write!(f, "{} at {}", error, location),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+// Abort the evaluation with an error
+#[track_caller]
+pub(crate) fn fail(error: EvalError) -> ! {
+    std::panic::panic_any(error)
+}
+
+// The error a panic was raised with
+pub(crate) fn panic_error(payload: &Box<dyn std::any::Any + Send>) -> EvalError {
+    match payload.downcast_ref::<EvalError>() {
+        Some(error) => error.clone(),
+        None => EvalError::Other(panic_message(payload)),
+    }
+}
+
+// The message a panic was raised with
+pub(crate) fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    payload
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+        .or_else(|| {
+            payload
+                .downcast_ref::<EvalError>()
+                .map(EvalError::to_string)
+        })
+        .unwrap_or_default()
+}
+
+thread_local! {
+    // The address of the innermost node whose evaluation a panic unwound,
+    // filled in by the guard of `evaluate_expr` as the stack unwinds. The
+    // panic hook clears it when a panic starts.
+    static FAILURE: std::cell::Cell<Option<*const Expr>> = const { std::cell::Cell::new(None) };
+    // Whether the panic hook keeps quiet, while `catch_silently` runs
+    static SILENT: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+// Run `f` and catch its panic, without the panic hook printing it: the
+// caller gets the error back and decides what to show
+pub(crate) fn catch_silently<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    install_panic_hook();
+    let silent = SILENT.replace(true);
+    let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    SILENT.set(silent);
+    caught
+}
+
+struct NodeGuard<'a>(&'a Expr);
+
+impl Drop for NodeGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() && FAILURE.get().is_none() {
+            FAILURE.set(Some(self.0));
+        }
+    }
+}
+
+// Where the last failed evaluation of `program` failed. `program` is the
+// tree that was evaluated, like the copy `resolver.rs` makes, which has
+// the same paths as the original. The node is found by its address, so of
+// two equal subtrees the one that failed is reported. Closures share their
+// bodies with the program, so this holds inside calls too. None when the
+// node isn't part of the program, like a prelude definition.
+pub(crate) fn failure_location(program: &Expr) -> Option<NodePath> {
+    let node = FAILURE.take()?;
+    let mut found = None;
+    program.walk(&mut |_, path, candidate| {
+        if found.is_none() && std::ptr::eq(candidate, node) {
+            found = Some(path.clone());
+        }
+    });
+    found
+}
+
+// The standard panic hook only prints string messages, so print failed
+// evaluations the same way it does and leave other panics to it
+pub(crate) fn install_panic_hook() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let standard = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            FAILURE.take();
+            if SILENT.get() {
+                return;
+            }
+            let Some(error) = info.payload().downcast_ref::<EvalError>() else {
+                return standard(info);
+            };
+            let thread = std::thread::current();
+            let location = info
+                .location()
+                .map_or_else(String::new, |location| format!(" at {}", location));
+            eprintln!(
+                "\nthread '{}' panicked{}:\n{}",
+                thread.name().unwrap_or("<unnamed>"),
+                location,
+                error
+            );
+            let backtrace = std::backtrace::Backtrace::capture();
+            match backtrace.status() {
+                std::backtrace::BacktraceStatus::Captured => {
+                    eprintln!("stack backtrace:\n{}", backtrace)
+                }
+                _ => eprintln!(
+                    "note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"
+                ),
+            }
+        }));
+    });
+}
@@ -0,0 +1,153 @@
+use std::collections::{HashMap, VecDeque};
+
+// A directed graph as adjacency lists. Nodes are names, kept in the order
+// they were first seen, and so are the neighbors of each node, so every
+// traversal is deterministic.
+#[derive(Debug, Default)]
+pub struct Graph {
+    nodes: Vec<String>,
+    indices: HashMap<String, usize>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    // The graph of a list of (from, to) edges
+    pub fn from_edges(edges: &[(String, String)]) -> Graph {
+        let mut graph = Graph::default();
+        for (from, to) in edges {
+            let (from, to) = (graph.add_node(from), graph.add_node(to));
+            graph.edges[from].push(to);
+        }
+        graph
+    }
+
+    // The graph of the neighbors of every node. Neighbors without a list of
+    // their own have no edges.
+    pub fn from_adjacency(lists: &[(String, Vec<String>)]) -> Graph {
+        let mut graph = Graph::default();
+        for (node, _) in lists {
+            graph.add_node(node);
+        }
+        for (node, neighbors) in lists {
+            let from = graph.indices[node];
+            for neighbor in neighbors {
+                let to = graph.add_node(neighbor);
+                graph.edges[from].push(to);
+            }
+        }
+        graph
+    }
+
+    pub fn to_adjacency(&self) -> Vec<(String, Vec<String>)> {
+        self.nodes
+            .iter()
+            .zip(&self.edges)
+            .map(|(node, edges)| (node.clone(), self.names(edges)))
+            .collect()
+    }
+
+    fn add_node(&mut self, node: &str) -> usize {
+        if let Some(&index) = self.indices.get(node) {
+            return index;
+        }
+        self.nodes.push(node.to_string());
+        self.edges.push(Vec::new());
+        self.indices.insert(node.to_string(), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    fn names(&self, indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|&index| self.nodes[index].clone())
+            .collect()
+    }
+
+    // The nodes an edge leads to from `node`, none for unknown nodes
+    pub fn neighbors(&self, node: &str) -> Vec<String> {
+        match self.indices.get(node) {
+            Some(&index) => self.names(&self.edges[index]),
+            None => Vec::new(),
+        }
+    }
+
+    // The nodes reachable from `start` in breadth-first order
+    pub fn bfs(&self, start: &str) -> Vec<String> {
+        let Some(&start) = self.indices.get(start) else {
+            return vec![start.to_string()];
+        };
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for &next in &self.edges[node] {
+                if !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        self.names(&order)
+    }
+
+    // The nodes reachable from `start` in depth-first preorder, visiting
+    // neighbors in order. The stack is explicit, so long paths don't
+    // overflow the evaluator's.
+    pub fn dfs(&self, start: &str) -> Vec<String> {
+        let Some(&start) = self.indices.get(start) else {
+            return vec![start.to_string()];
+        };
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![start];
+        while let Some(node) = stack.pop() {
+            if visited[node] {
+                continue;
+            }
+            visited[node] = true;
+            order.push(node);
+            stack.extend(
+                self.edges[node]
+                    .iter()
+                    .rev()
+                    .filter(|&&next| !visited[next]),
+            );
+        }
+        self.names(&order)
+    }
+
+    // Every node, each before the nodes its edges lead to (Kahn's
+    // algorithm). Nodes come in the order they become ready, starting with
+    // those no edge leads to in the order they were seen.
+    pub fn topo_sort(&self) -> Result<Vec<String>, String> {
+        let mut incoming = vec![0; self.nodes.len()];
+        for &to in self.edges.iter().flatten() {
+            incoming[to] += 1;
+        }
+        let mut ready: VecDeque<usize> = (0..self.nodes.len())
+            .filter(|&node| incoming[node] == 0)
+            .collect();
+        let mut order = Vec::new();
+        while let Some(node) = ready.pop_front() {
+            order.push(node);
+            for &next in &self.edges[node] {
+                incoming[next] -= 1;
+                if incoming[next] == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+        if order.len() < self.nodes.len() {
+            let stuck: Vec<usize> = (0..self.nodes.len())
+                .filter(|&node| incoming[node] > 0)
+                .collect();
+            return Err(format!(
+                "The graph has a cycle, so these nodes have no order: {}",
+                self.names(&stuck).join(", ")
+            ));
+        }
+        Ok(self.names(&order))
+    }
+}
@@ -0,0 +1,167 @@
+use crate::elements::Elements;
+use crate::eval::{apply_function, fail, quoted_list, EvalError, Evaluation, ResultValue};
+use std::fmt;
+
+// A lazy sequence of values. Like every value, an iterator is copied when a
+// variable is looked up, so consuming one copy leaves the others as they
+// were and programs stay pure.
+#[derive(Debug, Clone)]
+pub enum Iter {
+    // The numbers from the first up to, but not including, the second
+    Range(i64, i64),
+    // The chars of a string from a position on, as one-character strings
+    Chars(Vec<char>, usize),
+    // The values of a collection from a position on
+    Values(Elements, usize),
+    Iterate(Box<Iterate>),
+    Map(ResultValue, Box<Iter>),
+    Filter(ResultValue, Box<Iter>),
+    Take(usize, Box<Iter>),
+}
+
+// `value`, `next(value)`, `next(next(value))`, ... as long as `test`
+// returns a non-zero number; `started` once `value` has been produced
+#[derive(Debug, Clone)]
+pub struct Iterate {
+    pub value: ResultValue,
+    pub next: ResultValue,
+    pub test: ResultValue,
+    pub started: bool,
+}
+
+impl Iter {
+    // Produce the next value, or None at the end
+    pub fn next(&mut self, eval: &mut Evaluation) -> Option<ResultValue> {
+        match self {
+            Iter::Range(next, end) => {
+                if next >= end {
+                    return None;
+                }
+                *next += 1;
+                Some(ResultValue::Number(*next - 1))
+            }
+            Iter::Chars(chars, position) => {
+                let char = chars.get(*position)?;
+                *position += 1;
+                Some(ResultValue::String(char.to_string()))
+            }
+            Iter::Values(values, position) => {
+                let value = values.get(*position)?;
+                *position += 1;
+                Some(value.clone())
+            }
+            Iter::Iterate(state) => {
+                if state.started {
+                    state.value =
+                        apply_function(state.next.clone(), vec![state.value.clone()], eval);
+                }
+                state.started = true;
+                holds(&state.test, &state.value, eval).then(|| state.value.clone())
+            }
+            Iter::Map(function, inner) => {
+                let value = inner.next(eval)?;
+                Some(apply_function(function.clone(), vec![value], eval))
+            }
+            Iter::Filter(predicate, inner) => loop {
+                let value = inner.next(eval)?;
+                if holds(predicate, &value, eval) {
+                    return Some(value);
+                }
+            },
+            Iter::Take(count, inner) => {
+                if *count == 0 {
+                    return None;
+                }
+                *count -= 1;
+                inner.next(eval)
+            }
+        }
+    }
+
+    // Approximate number of bytes the iterator occupies
+    pub fn estimated_size(&self) -> usize {
+        let own = std::mem::size_of::<Iter>();
+        match self {
+            Iter::Range(_, _) => own,
+            Iter::Chars(chars, _) => own + chars.len() * std::mem::size_of::<char>(),
+            Iter::Values(values, _) => {
+                own + values
+                    .iter()
+                    .map(ResultValue::estimated_size)
+                    .sum::<usize>()
+            }
+            Iter::Iterate(state) => {
+                own + state.value.estimated_size()
+                    + state.next.estimated_size()
+                    + state.test.estimated_size()
+            }
+            Iter::Map(function, inner) | Iter::Filter(function, inner) => {
+                own + function.estimated_size() + inner.estimated_size()
+            }
+            Iter::Take(_, inner) => own + inner.estimated_size(),
+        }
+    }
+
+    // The estimated size of what the iterator adds to the values it was
+    // made from (see `ResultValue::fresh_size`)
+    pub(crate) fn fresh_size(&self) -> usize {
+        let own = std::mem::size_of::<Iter>();
+        match self {
+            Iter::Values(values, _) if values.is_shared() => own,
+            Iter::Values(values, _) => {
+                own + values.iter().map(ResultValue::fresh_size).sum::<usize>()
+            }
+            Iter::Iterate(state) => {
+                own + state.value.fresh_size() + state.next.fresh_size() + state.test.fresh_size()
+            }
+            Iter::Map(function, inner) | Iter::Filter(function, inner) => {
+                own + function.fresh_size() + inner.fresh_size()
+            }
+            Iter::Take(_, inner) => own + inner.fresh_size(),
+            Iter::Range(_, _) | Iter::Chars(_, _) => self.estimated_size(),
+        }
+    }
+}
+
+// Whether a predicate returns true or a non-zero number for the value
+pub fn holds(predicate: &ResultValue, value: &ResultValue, eval: &mut Evaluation) -> bool {
+    match apply_function(predicate.clone(), vec![value.clone()], eval) {
+        ResultValue::Number(number) => number != 0,
+        ResultValue::Bool(value) => value,
+        result => fail(EvalError::TypeError(format!(
+            "{} returned {}, not a number",
+            predicate, result
+        ))),
+    }
+}
+
+// Written as the builtin call that makes the iterator
+impl fmt::Display for Iter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Iter::Range(next, end) => write!(f, "iterRange({}, {})", next, end),
+            Iter::Chars(chars, position) => {
+                let rest: String = chars[*position..].iter().collect();
+                write!(f, "iterChars({:?})", rest)
+            }
+            Iter::Values(values, position) => {
+                write!(f, "toIter([{}])", quoted_list(&values[*position..]))
+            }
+            Iter::Iterate(state) if !state.started => {
+                write!(
+                    f,
+                    "iterate({}, {}, {})",
+                    state.value, state.next, state.test
+                )
+            }
+            Iter::Iterate(state) => write!(
+                f,
+                "iterate({}({}), {}, {})",
+                state.next, state.value, state.next, state.test
+            ),
+            Iter::Map(function, inner) => write!(f, "map({}, {})", function, inner),
+            Iter::Filter(predicate, inner) => write!(f, "filter({}, {})", predicate, inner),
+            Iter::Take(count, inner) => write!(f, "take({}, {})", count, inner),
+        }
+    }
+}
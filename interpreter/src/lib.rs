@@ -0,0 +1,173 @@
+// An interpreter for the course language, as a library so that other
+// programs can embed the evaluator; the `interpreter` binary is its command
+// line interface.
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod ast;
+pub mod builtins;
+pub mod env;
+pub mod eval;
+pub mod prelude;
+pub mod symbol;
+
+mod analysis;
+mod cli;
+mod combinators;
+mod cost;
+mod date;
+mod diagnostic;
+mod elements;
+mod graph;
+mod iter;
+mod logic;
+mod optimize;
+mod parallel;
+mod pretty;
+mod properties;
+mod resolver;
+mod rpc;
+mod server;
+mod sexpr;
+mod stepper;
+mod strategy;
+mod text;
+mod turtle;
+mod unify;
+mod vm;
+mod world;
+
+pub use ast::Expr;
+pub use cli::run;
+pub use env::Env;
+pub use eval::{EvalError, EvalOptions, FromValue, IntoValue, Location, ResultValue};
+pub use symbol::Symbol;
+
+// Evaluate a program in an environment, with the default language version
+// and limits. Errors are panics, like everywhere in the evaluator, and deep
+// recursion needs a stack of EVALUATION_STACK_SIZE bytes.
+pub fn eval(expr: &Expr, env: &Env) -> ResultValue {
+    eval::install_panic_hook();
+    evaluate_located(&resolver::locate_lambdas(expr), env)
+}
+
+fn evaluate_located(located: &Expr, env: &Env) -> ResultValue {
+    let mut evaluation = eval::Evaluation::new(ast::LangVersion::Cp3);
+    eval::evaluate_expr(located, env, &mut evaluation)
+}
+
+// Like `eval`, but return what went wrong instead of passing the panic on,
+// as `EvalError::At` the node that failed when it is part of `expr`.
+// Nothing is printed, showing the error is up to the caller.
+pub fn try_eval(expr: &Expr, env: &Env) -> Result<ResultValue, EvalError> {
+    let located = resolver::locate_lambdas(expr);
+    eval::catch_silently(|| evaluate_located(&located, env))
+        .map_err(|payload| caught(&located, &payload))
+}
+
+// The error of a failed evaluation of `expr`, the tree that was evaluated,
+// at the node that failed
+fn caught(expr: &Expr, payload: &Box<dyn std::any::Any + Send>) -> EvalError {
+    let error = eval::panic_error(payload);
+    match eval::failure_location(expr) {
+        Some(path) => EvalError::At(Box::new(error), eval::Location { path, span: None }),
+        None => error,
+    }
+}
+
+// An evaluator for hosts that evaluate several programs: the globals, the
+// builtins and the settings of its evaluation carry over from one program
+// to the next. Like `try_eval`, it returns errors instead of panicking.
+pub struct Interpreter {
+    globals: Env,
+    evaluation: eval::Evaluation,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new(ast::LangVersion::Cp3)
+    }
+}
+
+impl Interpreter {
+    // No globals and the standard builtins
+    pub fn new(version: ast::LangVersion) -> Interpreter {
+        eval::install_panic_hook();
+        Interpreter {
+            globals: Env::new(),
+            evaluation: eval::Evaluation::new(version),
+        }
+    }
+
+    // No globals, the standard builtins and the settings of `options`
+    pub fn with_options(version: ast::LangVersion, options: &EvalOptions) -> Interpreter {
+        eval::install_panic_hook();
+        Interpreter {
+            globals: Env::new(),
+            evaluation: eval::Evaluation::with_options(version, options),
+        }
+    }
+
+    // The evaluation programs run in, to change its settings, e.g. with
+    // `set_strict`, `set_max_steps` or `set_builtins`
+    pub fn evaluation(&mut self) -> &mut eval::Evaluation {
+        &mut self.evaluation
+    }
+
+    // Bind a global variable, replacing any earlier binding of the name
+    pub fn set_global(&mut self, name: &str, value: ResultValue) {
+        self.globals.define(name, value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<ResultValue> {
+        self.globals.get(name)
+    }
+
+    // The globals as JSON, like the snapshots of `--up-to`, to `restore`
+    // them in this or another interpreter later. None when one of them
+    // can't be saved, like a parser or an array.
+    pub fn snapshot(&self) -> Option<serde_json::Value> {
+        self.globals.to_snapshot()
+    }
+
+    // Replace the globals with those of a snapshot. One that isn't fails
+    // with `EvalError::Other` and leaves the globals as they were.
+    pub fn restore(&mut self, snapshot: &serde_json::Value) -> Result<(), EvalError> {
+        self.globals = Env::from_snapshot(snapshot).map_err(EvalError::Other)?;
+        Ok(())
+    }
+
+    // Let programs call `name`, with any number of arguments, as a host
+    // function. The error it returns fails the evaluation like a builtin's.
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[ResultValue]) -> Result<ResultValue, EvalError> + Send + Sync + 'static,
+    ) {
+        self.evaluation.builtins.register(
+            name,
+            builtins::Arity::AtLeast(0),
+            move |_, arguments, _| function(&arguments).unwrap_or_else(|err| eval::fail(err)),
+        );
+    }
+
+    // Evaluate a program with the globals in scope. Deep recursion needs a
+    // stack of EVALUATION_STACK_SIZE bytes, as with `eval`.
+    pub fn eval_expr(&mut self, program: Expr) -> Result<ResultValue, EvalError> {
+        let (globals, evaluation) = (&self.globals, &mut self.evaluation);
+        let located = resolver::locate_lambdas(&program);
+        let evaluated = eval::catch_silently(|| eval::evaluate_expr(&located, globals, evaluation));
+        evaluated.map_err(|payload| {
+            self.evaluation.recover();
+            caught(&located, &payload)
+        })
+    }
+
+    // Evaluate a program written in the JSON format. A document that isn't
+    // JSON or not a program fails with `EvalError::Other`.
+    pub fn eval_str(&mut self, text: &str) -> Result<ResultValue, EvalError> {
+        let json: serde_json::Value =
+            serde_json::from_str(text).map_err(|err| EvalError::Other(err.to_string()))?;
+        let program = Expr::from_json(&json).map_err(EvalError::Other)?;
+        self.eval_expr(program)
+    }
+}
@@ -0,0 +1,74 @@
+use crate::unify::{unify, Substitution, Term};
+
+// Queries stop after this many answers, so that goals with infinitely many
+// solutions still return
+const MAX_SOLUTIONS: usize = 100;
+
+// A clause `head :- body`; facts have an empty body
+#[derive(Debug, Clone)]
+struct Clause {
+    head: Term,
+    body: Vec<Term>,
+}
+
+// Facts and rules declared by the program, in declaration order
+#[derive(Debug, Default)]
+pub struct KnowledgeBase {
+    clauses: Vec<Clause>,
+    // Counter for renaming clause variables apart
+    renamings: usize,
+}
+
+impl KnowledgeBase {
+    pub fn add(&mut self, head: Term, body: Vec<Term>) {
+        self.clauses.push(Clause { head, body });
+    }
+
+    // All answers to a conjunction of goals, found depth-first and trying
+    // clauses in declaration order like Prolog. Each answer binds the
+    // variables of the goals.
+    pub fn query(&mut self, goals: &[Term]) -> Vec<Substitution> {
+        let mut variables = Vec::new();
+        for goal in goals {
+            goal.collect_variables(&mut variables);
+        }
+        let mut solutions = Vec::new();
+        self.solve(goals.to_vec(), Substitution::default(), &mut solutions);
+        solutions
+            .iter()
+            .map(|solution| solution.restrict(&variables))
+            .collect()
+    }
+
+    fn solve(
+        &mut self,
+        goals: Vec<Term>,
+        substitution: Substitution,
+        solutions: &mut Vec<Substitution>,
+    ) {
+        if solutions.len() >= MAX_SOLUTIONS {
+            return;
+        }
+        let Some((goal, rest)) = goals.split_first() else {
+            solutions.push(substitution);
+            return;
+        };
+        let goal = substitution.apply(goal);
+        for index in 0..self.clauses.len() {
+            self.renamings += 1;
+            let suffix = format!("#{}", self.renamings);
+            let clause = &self.clauses[index];
+            let head = clause.head.rename(&suffix);
+            let Ok(unifier) = unify(&goal, &head) else {
+                continue;
+            };
+            let mut next_goals: Vec<Term> = clause
+                .body
+                .iter()
+                .map(|term| term.rename(&suffix))
+                .collect();
+            next_goals.extend(rest.iter().cloned());
+            self.solve(next_goals, substitution.compose(&unifier), solutions);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::ast::*;
+use crate::env::*;
+use crate::eval::*;
+use std::collections::HashMap;
+
+// Maps each node of an optimized program back to the node of the original
+// program it was produced from, so reports in `--opt` mode can point at what
+// the user actually wrote
+#[derive(Debug, Default)]
+pub(crate) struct Provenance(HashMap<NodePath, NodePath>);
+
+impl Provenance {
+    fn record(&mut self, optimized: &NodePath, original: &NodePath) {
+        self.0.insert(optimized.clone(), original.clone());
+    }
+
+    // Drop the entries of nodes below `path`, which a rewrite has removed
+    fn forget_below(&mut self, path: &NodePath) {
+        self.0
+            .retain(|optimized, _| !optimized.0.starts_with(&path.0) || optimized == path);
+    }
+
+    pub(crate) fn original(&self, optimized: &NodePath) -> Option<&NodePath> {
+        self.0.get(optimized)
+    }
+}
+
+// Simplify a program before evaluating it: fold arithmetic on number
+// literals and drop cond clauses that can never be taken. `bound` holds
+// the variables in scope, which shadow builtins of the same name.
+pub(crate) fn optimize(
+    expr: &Expr,
+    original: &NodePath,
+    optimized: &NodePath,
+    bound: &[&str],
+    provenance: &mut Provenance,
+) -> Expr {
+    provenance.record(optimized, original);
+    match expr {
+        Expr::Application(items) => {
+            let items: Vec<Expr> = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    optimize(
+                        item,
+                        &original.child(i),
+                        &optimized.child(i),
+                        bound,
+                        provenance,
+                    )
+                })
+                .collect();
+            match fold_arithmetic(&items, bound) {
+                Some(number) => {
+                    provenance.forget_below(optimized);
+                    Expr::Number(number)
+                }
+                None => Expr::Application(items),
+            }
+        }
+        Expr::Lambda(parameters, body, _) => {
+            let mut bound = bound.to_vec();
+            bound.extend(parameters.iter().map(|name| name.as_str()));
+            let body = body
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    optimize(
+                        item,
+                        &original.child(i),
+                        &optimized.child(i),
+                        &bound,
+                        provenance,
+                    )
+                })
+                .collect();
+            Expr::Lambda(parameters.clone(), body, Site::default())
+        }
+        Expr::Letrec(bindings, _) => {
+            let mut bound = bound.to_vec();
+            bound.extend(bindings.iter().map(|(name, _)| name.as_str()));
+            expr.with_children(
+                expr.children()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, child)| {
+                        optimize(
+                            child,
+                            &original.child(i),
+                            &optimized.child(i),
+                            &bound,
+                            provenance,
+                        )
+                    })
+                    .collect(),
+            )
+        }
+        Expr::Cond(clauses) => {
+            let mut kept = Vec::new();
+            for (i, (test, result)) in clauses.iter().enumerate() {
+                let j = kept.len();
+                let test = optimize(
+                    test,
+                    &original.child(2 * i),
+                    &optimized.child(2 * j),
+                    bound,
+                    provenance,
+                );
+                if test == Expr::Identifier("false".into()) || test == Expr::Bool(false) {
+                    provenance.forget_below(&optimized.child(2 * j));
+                    provenance.0.remove(&optimized.child(2 * j));
+                    continue;
+                }
+                let result = optimize(
+                    result,
+                    &original.child(2 * i + 1),
+                    &optimized.child(2 * j + 1),
+                    bound,
+                    provenance,
+                );
+                let always = test == Expr::Identifier("true".into()) || test == Expr::Bool(true);
+                kept.push((test, result));
+                if always {
+                    // Later clauses are never reached
+                    break;
+                }
+            }
+            Expr::Cond(kept)
+        }
+        Expr::Array(_) | Expr::Dict(_) => expr.with_children(
+            expr.children()
+                .into_iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    optimize(
+                        child,
+                        &original.child(i),
+                        &optimized.child(i),
+                        bound,
+                        provenance,
+                    )
+                })
+                .collect(),
+        ),
+        _ => expr.clone(),
+    }
+}
+
+// Compute `add`, `sub` and `mul` applied to number literals ahead of time
+fn fold_arithmetic(items: &[Expr], bound: &[&str]) -> Option<i64> {
+    let operator = match items.first() {
+        Some(Expr::Identifier(name))
+            if name.starts_with(BUILTIN_PREFIX) || !bound.contains(&name.as_str()) =>
+        {
+            builtin_name(name)
+        }
+        _ => return None,
+    };
+    let mut numbers = Vec::new();
+    for item in &items[1..] {
+        numbers.push(as_number(item)?);
+    }
+    match operator {
+        "add" => numbers
+            .iter()
+            .try_fold(0i64, |sum, number| sum.checked_add(*number)),
+        "sub" => {
+            let (first, rest) = numbers.split_first()?;
+            rest.iter()
+                .try_fold(*first, |difference, number| difference.checked_sub(*number))
+        }
+        "mul" => numbers
+            .iter()
+            .try_fold(1i64, |product, number| product.checked_mul(*number)),
+        _ => None,
+    }
+}
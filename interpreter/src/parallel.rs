@@ -0,0 +1,154 @@
+use crate::ast::Expr;
+use crate::cli::{split_definitions, Definition};
+use crate::env::{Env, Vars};
+use crate::eval::*;
+use crate::turtle;
+use std::collections::HashMap;
+
+// Evaluate a program like `evaluate_expr`, but with its top-level
+// definitions (see `split_definitions`) evaluated on worker threads. A
+// definition waits for the definitions it refers to, and those that don't
+// refer to each other are evaluated at the same time. The values are then
+// bound level by level as usual, and what the definitions printed is
+// printed in their order, so the result and the output don't depend on
+// which worker finishes first. Definitions that declare facts or rules, or
+// draw, change the evaluation itself, so programs with any are evaluated on
+// this thread alone.
+pub(crate) fn evaluate_parallel(
+    program: &Expr,
+    globals: &Env,
+    eval: &mut Evaluation,
+) -> ResultValue {
+    let (definitions, block) = split_definitions(program);
+    let declares = definitions.iter().any(|definition| {
+        let free = definition.expr.free_variables();
+        free.iter().any(|name| {
+            name == "fact" || name == "rule" || turtle::BUILTINS.contains(&name.as_str())
+        })
+    });
+    if definitions.len() < 2 || declares {
+        return evaluate_expr(program, globals, eval);
+    }
+
+    // The definitions each definition refers to, and the wave it can be
+    // evaluated in: one after the last of theirs
+    let mut dependencies: Vec<Vec<usize>> = Vec::new();
+    let mut waves: Vec<usize> = Vec::new();
+    let mut visible: HashMap<&str, usize> = HashMap::new();
+    for (index, definition) in definitions.iter().enumerate() {
+        if index > 0 && definitions[index - 1].level != definition.level {
+            let level = definitions[index - 1].level;
+            for (earlier, defined) in definitions[..index].iter().enumerate() {
+                if defined.level == level {
+                    visible.insert(&defined.name, earlier);
+                }
+            }
+        }
+        let refers: Vec<usize> = definition
+            .expr
+            .free_variables()
+            .iter()
+            .filter_map(|name| visible.get(name.as_str()).copied())
+            .collect();
+        waves.push(
+            refers
+                .iter()
+                .map(|&earlier| waves[earlier] + 1)
+                .max()
+                .unwrap_or(0),
+        );
+        dependencies.push(refers);
+    }
+
+    let workers = std::thread::available_parallelism().map_or(1, |count| count.get());
+    let mut values: Vec<Option<ResultValue>> = vec![None; definitions.len()];
+    let mut outputs = vec![String::new(); definitions.len()];
+    for wave in 0..=waves.iter().copied().max().unwrap_or(0) {
+        let ready: Vec<usize> = (0..definitions.len())
+            .filter(|&index| waves[index] == wave)
+            .collect();
+        for batch in ready.chunks(workers) {
+            // Only the values a definition refers to are in its scope,
+            // which is all a closure needs to look up its free variables
+            let jobs: Vec<_> = batch
+                .iter()
+                .enumerate()
+                .map(|(worker, &index)| {
+                    let vars = dependencies[index]
+                        .iter()
+                        .map(|&earlier| {
+                            let value = values[earlier].clone().expect("Definition not evaluated");
+                            (definitions[earlier].name.clone(), value)
+                        })
+                        .collect();
+                    let env = Env::frame(eval.new_frame_id(), vars, Some(globals.clone()));
+                    (index, env, eval.worker(worker as u64, batch.len() as u64))
+                })
+                .collect();
+            let finished = std::thread::scope(|scope| {
+                let handles: Vec<_> = jobs
+                    .into_iter()
+                    .map(|(index, env, worker)| {
+                        let definition: &Definition = &definitions[index];
+                        std::thread::Builder::new()
+                            .stack_size(EVALUATION_STACK_SIZE)
+                            .spawn_scoped(scope, move || {
+                                let mut worker = worker();
+                                let value = catch_silently(|| {
+                                    evaluate_expr(definition.expr, &env, &mut worker)
+                                });
+                                (index, value, worker.steps, worker.take_output())
+                            })
+                            .expect("Failed to start a worker")
+                    })
+                    .collect();
+                let joined: Vec<_> = handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("A worker panicked"))
+                    .collect();
+                joined
+            });
+            // Count the steps of every worker, then fail like the first
+            // definition that failed, where running out of fuel is reported
+            // with the steps of the whole evaluation
+            let mut failure = None;
+            for (index, value, steps, output) in finished {
+                eval.steps += steps;
+                match value {
+                    Ok(value) => {
+                        outputs[index] = output;
+                        values[index] = Some(value);
+                    }
+                    Err(payload) => {
+                        failure.get_or_insert(payload);
+                    }
+                }
+            }
+            if let Some(payload) = failure {
+                match panic_error(&payload) {
+                    EvalError::FuelExhausted(_) => fail(EvalError::FuelExhausted(eval.steps - 1)),
+                    error => fail(error),
+                }
+            }
+        }
+    }
+
+    for output in &outputs {
+        for line in output.lines() {
+            eval.print(line);
+        }
+    }
+    let mut env = globals.clone();
+    let mut index = 0;
+    while index < definitions.len() {
+        let level = definitions[index].level;
+        let mut vars = Vars::default();
+        while index < definitions.len() && definitions[index].level == level {
+            let value = values[index].take().expect("Definition not evaluated");
+            vars.insert(definitions[index].name.clone(), value);
+            index += 1;
+        }
+        env = Env::frame(eval.new_frame_id(), vars, Some(env));
+    }
+    evaluate_block(block, &env, eval)
+}
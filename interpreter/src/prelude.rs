@@ -0,0 +1,10 @@
+// What a host embedding the interpreter needs, in one import:
+// `use interpreter::prelude::*`. The names here only change with the major
+// version; the modules they come from may change in any release.
+pub use crate::ast::{Expr, LangVersion};
+pub use crate::builtins::{Arity, BuiltinRegistry, Category};
+pub use crate::env::Env;
+pub use crate::eval::{
+    EvalError, EvalOptions, Evaluation, FromValue, IntoValue, Location, ResultValue,
+};
+pub use crate::{try_eval, Interpreter};
@@ -0,0 +1,109 @@
+// Documents for Wadler's "A prettier printer": text, line breaks, nesting
+// and groups that are printed on one line when they fit
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    // A line break that prints as the given text when its group is flat
+    Line(String),
+    Concat(Vec<Doc>),
+    Nest(usize, Box<Doc>),
+    Group(Box<Doc>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+impl Doc {
+    pub fn text(text: &str) -> Doc {
+        Doc::Text(text.to_string())
+    }
+
+    // A break that prints as a space when flat
+    pub fn line() -> Doc {
+        Doc::Line(" ".to_string())
+    }
+
+    // A break that prints as nothing when flat
+    pub fn softline() -> Doc {
+        Doc::Line(String::new())
+    }
+
+    pub fn nest(indent: usize, doc: Doc) -> Doc {
+        Doc::Nest(indent, Box::new(doc))
+    }
+
+    pub fn group(doc: Doc) -> Doc {
+        Doc::Group(Box::new(doc))
+    }
+
+    // Approximate number of bytes the document occupies
+    pub fn estimated_size(&self) -> usize {
+        let own = std::mem::size_of::<Doc>();
+        match self {
+            Doc::Text(text) | Doc::Line(text) => own + text.len(),
+            Doc::Concat(docs) => own + docs.iter().map(Doc::estimated_size).sum::<usize>(),
+            Doc::Nest(_, doc) | Doc::Group(doc) => own + doc.estimated_size(),
+        }
+    }
+
+    // The documents with `separator` between each pair
+    pub fn join(docs: Vec<Doc>, separator: Doc) -> Doc {
+        let mut joined = Vec::new();
+        for (i, doc) in docs.into_iter().enumerate() {
+            if i > 0 {
+                joined.push(separator.clone());
+            }
+            joined.push(doc);
+        }
+        Doc::Concat(joined)
+    }
+
+    // Lay out the document in `width` columns. A group is printed flat if
+    // it fits in what is left of the line, and its breaks become newlines
+    // otherwise.
+    pub fn render(&self, width: usize) -> String {
+        let mut output = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, Mode::Break, self)];
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    output.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line(flat) if mode == Mode::Flat => {
+                    output.push_str(flat);
+                    column += flat.chars().count();
+                }
+                Doc::Line(_) => {
+                    output.push('\n');
+                    output.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                Doc::Concat(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+                }
+                Doc::Nest(extra, doc) => stack.push((indent + extra, mode, doc)),
+                Doc::Group(doc) if mode == Mode::Flat => stack.push((indent, Mode::Flat, doc)),
+                Doc::Group(doc) => {
+                    let fits = column + doc.flat_width() <= width;
+                    let mode = if fits { Mode::Flat } else { Mode::Break };
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+        output
+    }
+
+    // The length of the document printed on one line
+    fn flat_width(&self) -> usize {
+        match self {
+            Doc::Text(text) | Doc::Line(text) => text.chars().count(),
+            Doc::Concat(docs) => docs.iter().map(Doc::flat_width).sum(),
+            Doc::Nest(_, doc) | Doc::Group(doc) => doc.flat_width(),
+        }
+    }
+}
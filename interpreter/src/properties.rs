@@ -0,0 +1,93 @@
+use crate::eval::{ResultValue, Rng};
+use serde_json::Value;
+
+// How the values of a parameter are generated
+#[derive(Debug)]
+enum Generator {
+    // A number in `low..=high`
+    Range(i64, i64),
+    // A string of `low..=high` chars from the alphabet
+    Text(Vec<char>, usize, usize),
+    // One of the listed values
+    OneOf(Vec<ResultValue>),
+}
+
+// A typed input parameter of a property, as declared in its test file:
+// `{"name": "a", "type": "number", "range": [-100, 100]}`,
+// `{"name": "s", "type": "string", "alphabet": "ab", "length": [0, 8]}`, or
+// either type with `"values": [...]` to choose from a list
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: String,
+    generator: Generator,
+}
+
+impl Parameter {
+    pub fn from_json(json: &Value) -> Result<Parameter, String> {
+        let name = json["name"]
+            .as_str()
+            .ok_or_else(|| format!("Parameter without a name: {}", json))?
+            .to_string();
+        let kind = json["type"].as_str().unwrap_or("number");
+        let bounds = |key: &str, default: [i64; 2]| -> Result<(i64, i64), String> {
+            match &json[key] {
+                Value::Null => Ok((default[0], default[1])),
+                Value::Array(pair) => match pair.as_slice() {
+                    [low, high] => match (low.as_i64(), high.as_i64()) {
+                        (Some(low), Some(high)) if low <= high => Ok((low, high)),
+                        _ => Err(format!("Bad {} of {}", key, name)),
+                    },
+                    _ => Err(format!("The {} of {} needs two numbers", key, name)),
+                },
+                _ => Err(format!("The {} of {} needs two numbers", key, name)),
+            }
+        };
+        let generator = if let Some(values) = json["values"].as_array() {
+            if values.is_empty() {
+                return Err(format!("No values for {}", name));
+            }
+            let values = values
+                .iter()
+                .map(|value| match (kind, value) {
+                    ("number", Value::Number(number)) => number
+                        .as_i64()
+                        .map(ResultValue::Number)
+                        .ok_or_else(|| format!("{} is not an integer", number)),
+                    ("string", Value::String(text)) => Ok(ResultValue::String(text.clone())),
+                    _ => Err(format!("{} is not a {} for {}", value, kind, name)),
+                })
+                .collect::<Result<_, _>>()?;
+            Generator::OneOf(values)
+        } else if kind == "number" {
+            let (low, high) = bounds("range", [-100, 100])?;
+            Generator::Range(low, high)
+        } else if kind == "string" {
+            let alphabet: Vec<char> = json["alphabet"].as_str().unwrap_or("abc").chars().collect();
+            let (low, high) = bounds("length", [0, 8])?;
+            if alphabet.is_empty() || low < 0 {
+                return Err(format!("Bad alphabet or length of {}", name));
+            }
+            Generator::Text(alphabet, low as usize, high as usize)
+        } else {
+            return Err(format!("Unknown type {} of {}", kind, name));
+        };
+        Ok(Parameter { name, generator })
+    }
+
+    pub fn generate(&self, rng: &mut Rng) -> ResultValue {
+        match &self.generator {
+            Generator::Range(low, high) => {
+                let span = high.abs_diff(*low).saturating_add(1);
+                ResultValue::Number(low.wrapping_add((rng.next() % span) as i64))
+            }
+            Generator::Text(alphabet, low, high) => {
+                let length = low + rng.below(high - low + 1);
+                let text = (0..length)
+                    .map(|_| alphabet[rng.below(alphabet.len())])
+                    .collect();
+                ResultValue::String(text)
+            }
+            Generator::OneOf(values) => values[rng.below(values.len())].clone(),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::ast::{Expr, LangVersion, NodePath, Site};
+use crate::env::BUILTIN_PREFIX;
+
+// Rewrite every identifier that a lambda or letrec of the program binds
+// into an `Expr::Local`, so evaluation takes its value from a slot of a
+// frame a known number of frames out instead of searching the frames by
+// name. Calling a lambda makes a frame of its parameters, and a letrec a
+// frame of its names, in order and each name once (see `Vars`), so the
+// frames at run time line up with the binders around the identifier. From
+// checkpoint 4 on, a block also opens an empty frame of its own, which is
+// counted as well. Anything else, globals, the prelude and builtins, is
+// still looked up by name. Lambdas get their sites like with
+// `locate_lambdas`.
+pub(crate) fn resolve_locals(program: &Expr, version: LangVersion) -> Expr {
+    let resolver = Resolver {
+        locals: true,
+        block_scoping: version.block_scoping(),
+    };
+    resolver.resolve(program, &NodePath::default(), &mut Vec::new())
+}
+
+// Attach its path to every lambda of the program, for the closures it
+// makes to show where they come from, and leave the identifiers as they are
+pub(crate) fn locate_lambdas(program: &Expr) -> Expr {
+    let resolver = Resolver {
+        locals: false,
+        block_scoping: false,
+    };
+    resolver.resolve(program, &NodePath::default(), &mut Vec::new())
+}
+
+struct Resolver {
+    // Whether identifiers are rewritten, or only lambdas located
+    locals: bool,
+    // Whether blocks open frames of their own
+    block_scoping: bool,
+}
+
+impl Resolver {
+    // `scope` holds the slots of the frames of the enclosing lambdas, letrecs
+    // and blocks, innermost last
+    fn resolve<'a>(&self, expr: &'a Expr, path: &NodePath, scope: &mut Vec<Vec<&'a str>>) -> Expr {
+        if let Expr::Identifier(name) = expr {
+            if !self.locals || name.starts_with(BUILTIN_PREFIX) {
+                return expr.clone();
+            }
+            for (depth, slots) in scope.iter().rev().enumerate() {
+                if let Some(slot) = slots.iter().position(|bound| bound == name) {
+                    return Expr::Local(*name, depth, slot);
+                }
+            }
+            return expr.clone();
+        }
+        let frame = matches!(expr, Expr::Lambda(_, _, _) | Expr::Letrec(_, _));
+        if frame {
+            let mut slots = Vec::new();
+            for name in expr.binds() {
+                if !slots.contains(&name) {
+                    slots.push(name);
+                }
+            }
+            scope.push(slots);
+        }
+        // The body of a lambda, or the expressions after the bindings of a
+        // letrec
+        let block = match expr {
+            Expr::Lambda(_, _, _) => 0,
+            Expr::Letrec(bindings, _) => bindings.len(),
+            _ => usize::MAX,
+        };
+        let children = expr
+            .children()
+            .into_iter()
+            .enumerate()
+            .map(|(index, child)| {
+                let in_block = self.block_scoping && index >= block;
+                if in_block {
+                    scope.push(Vec::new());
+                }
+                let resolved = self.resolve(child, &path.child(index), scope);
+                if in_block {
+                    scope.pop();
+                }
+                resolved
+            })
+            .collect();
+        if frame {
+            scope.pop();
+        }
+        match expr.with_children(children) {
+            Expr::Lambda(parameters, body, _) => {
+                Expr::Lambda(parameters, body, Site(Some(path.clone())))
+            }
+            resolved => resolved,
+        }
+    }
+}
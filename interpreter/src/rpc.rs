@@ -0,0 +1,174 @@
+use crate::ast::{Expr, NodeId, NodePath};
+use crate::env::Env;
+use crate::eval::{Evaluation, NodeIndex, OutputSink};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+// The `--rpc` protocol, for debuggers like the editor extensions. The
+// client sends one JSON request per line on stdin, such as
+// `{"id": 1, "method": "setBreakpoint", "params": {"nodeId": 3}}`, and the
+// interpreter answers each on a line of stdout with `{"id": 1, "result":
+// ...}` or `{"id": 1, "error": "..."}`. Between the answers it writes
+// events: "output" for every line the program prints, "stopped" when
+// evaluation reaches a breakpoint and "terminated" with the result or the
+// error at the end. Nodes are identified by the ids of `--list-nodes`.
+//
+// Evaluation starts paused before the program, and "continue" runs it to
+// the next breakpoint. "setBreakpoint", "listGlobals" and "getValue" work
+// whenever evaluation is paused, "getCallStack" once it has started.
+pub(crate) struct Debugger {
+    nodes: NodeIndex,
+    // The path and label of every node, by id
+    labels: Vec<(NodePath, String)>,
+    breakpoints: BTreeSet<usize>,
+    globals: Env,
+}
+
+impl Debugger {
+    pub(crate) fn new(program: &Expr, globals: &Env) -> Debugger {
+        let mut labels = Vec::new();
+        program.walk(&mut |_, path, node| labels.push((path.clone(), node.label())));
+        Debugger {
+            nodes: NodeIndex::new(program),
+            labels,
+            breakpoints: BTreeSet::new(),
+            globals: globals.clone(),
+        }
+    }
+
+    // Answer requests until the client says to continue. `stop` is the
+    // node evaluation is paused at and its environment, None before
+    // evaluation starts.
+    pub(crate) fn serve(&mut self, stop: Option<(NodeId, &Env)>, eval: &Evaluation) {
+        let stdin = io::stdin();
+        loop {
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // The client is gone, so nobody can continue after a
+                // breakpoint any more
+                self.breakpoints.clear();
+                return;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(err) => {
+                    send(json!({"id": null, "error": format!("Not JSON: {}", err)}));
+                    continue;
+                }
+            };
+            let id = request["id"].clone();
+            let method = request["method"].as_str().unwrap_or("");
+            if method == "continue" {
+                send(json!({"id": id, "result": null}));
+                return;
+            }
+            match self.answer(method, &request["params"], stop, eval) {
+                Ok(result) => send(json!({"id": id, "result": result})),
+                Err(message) => send(json!({"id": id, "error": message})),
+            }
+        }
+    }
+
+    fn answer(
+        &mut self,
+        method: &str,
+        params: &Value,
+        stop: Option<(NodeId, &Env)>,
+        eval: &Evaluation,
+    ) -> Result<Value, String> {
+        match method {
+            "setBreakpoint" => {
+                let id = params["nodeId"]
+                    .as_u64()
+                    .ok_or("setBreakpoint needs a nodeId")? as usize;
+                let (path, label) = self
+                    .labels
+                    .get(id)
+                    .ok_or_else(|| format!("No node with id {}", id))?;
+                self.breakpoints.insert(id);
+                Ok(json!({"nodeId": id, "path": path.to_string(), "label": label}))
+            }
+            "listGlobals" => Ok(variables(&self.globals)),
+            // Variables in scope where evaluation is paused, the globals
+            // before it starts
+            "getValue" => {
+                let name = params["name"].as_str().ok_or("getValue needs a name")?;
+                let env = stop.map_or(&self.globals, |(_, env)| env);
+                match env.get(name) {
+                    Some(value) => Ok(json!({"name": name, "value": value.to_string()})),
+                    None => Err(format!("Unbound variable: {}", name)),
+                }
+            }
+            // The frames of the lambda calls in progress, innermost first
+            "getCallStack" => {
+                let Some((id, _)) = stop else {
+                    return Err("getCallStack needs evaluation to have started".to_string());
+                };
+                let frames: Vec<Value> = eval
+                    .call_stack
+                    .iter()
+                    .flatten()
+                    .rev()
+                    .map(|frame| json!({"frame": frame.id, "variables": variables(frame)}))
+                    .collect();
+                let path = self.labels[id.0].0.to_string();
+                Ok(json!({"nodeId": id.0, "path": path, "frames": frames}))
+            }
+            _ => Err(format!("Unknown method: {}", method)),
+        }
+    }
+}
+
+// The variables of a frame, in the order they were bound
+fn variables(frame: &Env) -> Value {
+    frame
+        .vars()
+        .iter()
+        .map(|(name, value)| json!({"name": name.as_str(), "value": value.to_string()}))
+        .collect()
+}
+
+// Write an answer or an event on a line of its own
+pub(crate) fn send(message: Value) {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", message).expect("Failed to write to stdout");
+    stdout.flush().expect("Failed to write to stdout");
+}
+
+// Pause before evaluating a node with a breakpoint and tell the client,
+// then answer its requests until it says to continue
+pub(crate) fn pause_at_node(expr: &Expr, env: &Env, eval: &mut Evaluation) {
+    let Some(debugger) = &eval.debugger else {
+        return;
+    };
+    let Some((id, path)) = debugger.nodes.get(expr) else {
+        return;
+    };
+    if !debugger.breakpoints.contains(&id.0) {
+        return;
+    }
+    let id = *id;
+    send(json!({
+        "event": "stopped",
+        "reason": "breakpoint",
+        "nodeId": id.0,
+        "path": path.to_string(),
+    }));
+    let mut debugger = eval.debugger.take().expect("Paused without a debugger");
+    debugger.serve(Some((id, env)), eval);
+    eval.debugger = Some(debugger);
+}
+
+// Sends what the program prints to the client as output events, since
+// stdout carries the protocol
+pub(crate) struct RpcOutput;
+
+impl OutputSink for RpcOutput {
+    fn write_line(&mut self, line: &str) {
+        send(json!({"event": "output", "line": line}));
+    }
+}
@@ -0,0 +1,550 @@
+use crate::ast::{Expr, LangVersion};
+use crate::builtins::hex_encode;
+use crate::cli::split_definitions;
+use crate::env::{Env, Vars};
+use crate::eval::{
+    evaluate_block, evaluate_expr, panic_message, Evaluation, Output, OutputSink, ResultValue,
+    EVALUATION_STACK_SIZE,
+};
+use crate::resolver::locate_lambdas;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Settings of `serve`
+pub struct ServerOptions {
+    pub port: u16,
+    pub workers: usize,
+    // Sessions unused for this long are dropped
+    pub idle_timeout: Duration,
+    // How many definitions a session may keep
+    pub max_bindings: usize,
+    // The `--loop-limit` of every evaluation
+    pub loop_limit: usize,
+    pub version: LangVersion,
+    // Requests per minute from one address, and with one token
+    pub rate_limit: usize,
+    // Steps a session may use in total
+    pub fuel_quota: u64,
+    // Where to record every evaluation request, and the size at which the
+    // file is rotated
+    pub audit_log: Option<String>,
+    pub audit_max_bytes: u64,
+    // How long a client may take to send its request, and to take each
+    // part of the response
+    pub io_timeout: Duration,
+    // The largest request body accepted
+    pub max_body_bytes: usize,
+}
+
+// The longest request line and headers accepted
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+// How many rotated audit files are kept, as FILE.1 (the newest) to FILE.N
+const AUDIT_ROTATIONS: usize = 5;
+
+// Append-only JSON lines file of evaluation requests. Rotation checks the
+// size through this process's own handle and renames the file under it, so
+// only one server process may write to a file.
+struct AuditLog {
+    path: String,
+    max_bytes: u64,
+    file: File,
+}
+
+impl AuditLog {
+    fn open(path: &str, max_bytes: u64) -> AuditLog {
+        AuditLog {
+            path: path.to_string(),
+            max_bytes,
+            file: Self::append(path),
+        }
+    }
+
+    fn append(path: &str) -> File {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|err| panic!("Failed to open {}: {}", path, err))
+    }
+
+    // Append a record, first rotating the file if it is full
+    fn record(&mut self, record: Value) {
+        let size = self
+            .file
+            .metadata()
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if size >= self.max_bytes {
+            for index in (1..AUDIT_ROTATIONS).rev() {
+                let from = format!("{}.{}", self.path, index);
+                let _ = std::fs::rename(&from, format!("{}.{}", self.path, index + 1));
+            }
+            let _ = std::fs::rename(&self.path, format!("{}.1", self.path));
+            self.file = Self::append(&self.path);
+        }
+        writeln!(self.file, "{}", record).expect("Failed to write audit log");
+    }
+}
+
+// The definitions a client made so far, available to its later programs
+struct Session {
+    env: Env,
+    bindings: usize,
+    last_used: Instant,
+    // Steps used by all evaluations of the session
+    fuel_used: u64,
+}
+
+// When the requests of the last minute came, by client address and by token
+#[derive(Default)]
+struct RateLimiter {
+    requests: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    // Count a request by `key`, or return how many seconds to wait when it
+    // is over the limit
+    fn check(&mut self, key: String, limit: usize) -> Result<(), u64> {
+        let window = Duration::from_secs(60);
+        let requests = self.requests.entry(key).or_default();
+        while requests
+            .front()
+            .is_some_and(|time| time.elapsed() >= window)
+        {
+            requests.pop_front();
+        }
+        if requests.len() >= limit {
+            let wait = window.saturating_sub(requests[0].elapsed());
+            return Err(wait.as_secs() + 1);
+        }
+        requests.push_back(Instant::now());
+        Ok(())
+    }
+}
+
+// The response to a request over a limit
+fn too_many(message: String, retry_after: Option<u64>) -> (u16, Value) {
+    let mut body = serde_json::json!({ "error": message });
+    if let Some(seconds) = retry_after {
+        body["retry_after_seconds"] = seconds.into();
+    }
+    (429, body)
+}
+
+// Sessions by token. Each session has a lock of its own, so requests of
+// different sessions are evaluated in parallel and requests of the same
+// session one after another.
+type Sessions = Arc<Mutex<HashMap<String, Arc<Mutex<Session>>>>>;
+
+// Serve `POST /eval` requests with a body like
+// `{"token": "3f0c...", "program": <parser output>}` from a pool of worker
+// threads. The top-level definitions of a program (see `--watch`) stay in
+// the session of its token. A request without a token starts a new
+// session, and its response carries the token the server made for it.
+pub fn serve(options: ServerOptions, globals: Env) {
+    let listener = TcpListener::bind(("127.0.0.1", options.port))
+        .unwrap_or_else(|err| panic!("Failed to listen on port {}: {}", options.port, err));
+    eprintln!(
+        "Serving on http://127.0.0.1:{} with {} workers",
+        options.port, options.workers
+    );
+    let options = Arc::new(options);
+    let sessions: Sessions = Arc::default();
+    let limiter: Arc<Mutex<RateLimiter>> = Arc::default();
+    let audit = options
+        .audit_log
+        .as_ref()
+        .map(|path| Arc::new(Mutex::new(AuditLog::open(path, options.audit_max_bytes))));
+    let (sender, receiver) = mpsc::channel::<TcpStream>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..options.workers {
+        let (options, sessions, receiver) = (options.clone(), sessions.clone(), receiver.clone());
+        let (limiter, audit) = (limiter.clone(), audit.clone());
+        let globals = globals.clone();
+        std::thread::Builder::new()
+            .stack_size(EVALUATION_STACK_SIZE)
+            .spawn(move || loop {
+                let Ok(stream) = receiver.lock().unwrap().recv() else {
+                    return;
+                };
+                let audit = audit.as_deref();
+                handle_connection(stream, &options, &sessions, &limiter, audit, &globals);
+            })
+            .expect("Failed to start a worker");
+    }
+    for stream in listener.incoming().flatten() {
+        sender.send(stream).expect("All workers stopped");
+    }
+}
+
+// Sends every line a program prints to the client as soon as it is printed,
+// as a JSON line in a chunk of a chunked HTTP response
+struct StreamSink(TcpStream);
+
+impl OutputSink for StreamSink {
+    fn write_line(&mut self, line: &str) {
+        send_chunk(&mut self.0, &serde_json::json!({ "output": line }));
+    }
+}
+
+fn send_chunk(stream: &mut TcpStream, line: &Value) {
+    let line = format!("{}\n", line);
+    // The client may be gone already
+    let _ = write!(stream, "{:x}\r\n{}\r\n", line.len(), line);
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    options: &ServerOptions,
+    sessions: &Sessions,
+    limiter: &Mutex<RateLimiter>,
+    audit: Option<&Mutex<AuditLog>>,
+    globals: &Env,
+) {
+    // A client that sends nothing, or reads nothing, only holds a worker
+    // until the timeout
+    let _ = stream.set_write_timeout(Some(options.io_timeout));
+    let address = stream
+        .peer_addr()
+        .map(|address| address.ip().to_string())
+        .unwrap_or_default();
+    let (status, body) = match read_request(&stream, &address, options, limiter) {
+        Ok((method, path, body))
+            if method == "POST" && (path == "/eval" || path == "/eval/stream") =>
+        {
+            let start = Instant::now();
+            let streaming = path == "/eval/stream";
+            let output = if streaming {
+                // The status is only known at the end, so it is sent in the
+                // last line and the response itself always succeeds
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+                );
+                match stream.try_clone() {
+                    Ok(clone) => Output::Sink(Box::new(StreamSink(clone))),
+                    Err(_) => Output::Captured(String::new()),
+                }
+            } else {
+                Output::Captured(String::new())
+            };
+            let (status, mut response) =
+                handle_eval(&body, options, sessions, limiter, globals, output);
+            if let Some(audit) = audit {
+                // The program is recorded by its structural hash only
+                let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+                let program = Expr::from_json(&request["program"])
+                    .ok()
+                    .map(|program| format!("{:016x}", program.structural_hash()));
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|time| time.as_secs())
+                    .unwrap_or(0);
+                // The token lets anyone use the session, so it is recorded by
+                // a hash that still tells the requests of a session apart
+                let token = request["token"].as_str().or(response["token"].as_str());
+                audit.lock().unwrap().record(serde_json::json!({
+                    "time": time,
+                    "address": address,
+                    "session": token.map(token_hash),
+                    "program": program,
+                    "duration_ms": start.elapsed().as_secs_f64() * 1000.0,
+                    "steps": response["steps"],
+                    "status": status,
+                    "error": response["error"],
+                }));
+            }
+            if streaming {
+                // The output has been sent already
+                if response["output"] == "" {
+                    if let Some(fields) = response.as_object_mut() {
+                        fields.remove("output");
+                    }
+                }
+                response["status"] = status.into();
+                send_chunk(&mut stream, &response);
+                let _ = stream.write_all(b"0\r\n\r\n");
+                return;
+            }
+            (status, response)
+        }
+        Ok(_) => (
+            404,
+            serde_json::json!({ "error": "Only POST /eval and /eval/stream are served" }),
+        ),
+        Err(response) => response,
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        _ => "Unprocessable Entity",
+    };
+    let retry_after = match body["retry_after_seconds"].as_u64() {
+        Some(seconds) => format!("Retry-After: {}\r\n", seconds),
+        None => String::new(),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        retry_after,
+        body
+    );
+    // The client may be gone already
+    let _ = stream.write_all(response.as_bytes());
+}
+
+// Reads from a connection until a deadline, however slowly the client sends
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buffer)
+    }
+}
+
+// The method, path and body of an HTTP request. The client's rate limit is
+// checked and the size of the body is limited before the body is read.
+fn read_request(
+    stream: &TcpStream,
+    address: &str,
+    options: &ServerOptions,
+    limiter: &Mutex<RateLimiter>,
+) -> Result<(String, String, Vec<u8>), (u16, Value)> {
+    let bad = |err: String| (400, serde_json::json!({ "error": err }));
+    let mut reader = BufReader::new(DeadlineReader {
+        stream,
+        deadline: Instant::now() + options.io_timeout,
+    });
+    let (method, path, length) = read_head(&mut reader).map_err(bad)?;
+    let key = format!("address {}", address);
+    if let Err(wait) = limiter
+        .lock()
+        .unwrap()
+        .check(key.clone(), options.rate_limit)
+    {
+        let message = format!(
+            "Rate limit: at most {} requests per minute by {}",
+            options.rate_limit, key
+        );
+        return Err(too_many(message, Some(wait)));
+    }
+    if length > options.max_body_bytes {
+        let message = format!(
+            "Request body too large: at most {} bytes",
+            options.max_body_bytes
+        );
+        return Err((413, serde_json::json!({ "error": message })));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| bad(err.to_string()))?;
+    Ok((method, path, body))
+}
+
+// The method, path and Content-Length of an HTTP request
+fn read_head(reader: &mut impl BufRead) -> Result<(String, String, usize), String> {
+    let mut head = reader.take(MAX_HEAD_BYTES);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|err| err.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err("Malformed request line".to_string());
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        head.read_line(&mut header).map_err(|err| err.to_string())?;
+        if !header.ends_with('\n') {
+            return Err("Headers too long or cut off".to_string());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| "Bad Content-Length")?;
+            }
+        }
+    }
+    Ok((method, path, length))
+}
+
+fn handle_eval(
+    body: &[u8],
+    options: &ServerOptions,
+    sessions: &Sessions,
+    limiter: &Mutex<RateLimiter>,
+    globals: &Env,
+    output: Output,
+) -> (u16, Value) {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => return (400, serde_json::json!({ "error": err.to_string() })),
+    };
+    let program = match Expr::from_json(&request["program"]) {
+        Ok(program) => program,
+        Err(err) => return (400, serde_json::json!({ "error": err })),
+    };
+    // Only tokens the server issued name a session, so one client can't
+    // reach another's definitions by guessing or copying a name
+    let (token, session, issued) = {
+        let mut sessions = sessions.lock().unwrap();
+        sessions.retain(|_, session| match session.try_lock() {
+            Ok(session) => session.last_used.elapsed() < options.idle_timeout,
+            // In use, so not idle
+            Err(_) => true,
+        });
+        match request["token"].as_str() {
+            Some(token) => match sessions.get(token) {
+                Some(session) => (token.to_string(), session.clone(), false),
+                None => {
+                    let message = "Unknown session token: start a new session by leaving it out";
+                    return (401, serde_json::json!({ "error": message }));
+                }
+            },
+            None => {
+                let token = new_token();
+                let session = Arc::new(Mutex::new(Session {
+                    env: globals.clone(),
+                    bindings: 0,
+                    last_used: Instant::now(),
+                    fuel_used: 0,
+                }));
+                sessions.insert(token.clone(), session.clone());
+                (token, session, true)
+            }
+        }
+    };
+    // The client's address was counted before its request was read
+    if let Err(wait) = limiter
+        .lock()
+        .unwrap()
+        .check(format!("token {}", token), options.rate_limit)
+    {
+        let message = format!(
+            "Rate limit: at most {} requests per minute by one session",
+            options.rate_limit
+        );
+        return too_many(message, Some(wait));
+    }
+    let mut session = session.lock().unwrap();
+    session.last_used = Instant::now();
+    if session.fuel_used >= options.fuel_quota {
+        let message = format!(
+            "Fuel quota: the session used all of its {} steps",
+            options.fuel_quota
+        );
+        return too_many(message, None);
+    }
+    let (result, output, steps) = evaluate_in_session(&mut session, &program, options, output);
+    let (status, mut response) = match result {
+        Ok(ResultValue::Unit) => (
+            200,
+            serde_json::json!({ "result": null, "output": output, "steps": steps }),
+        ),
+        Ok(result) => (
+            200,
+            serde_json::json!({ "result": result.to_string(), "output": output, "steps": steps }),
+        ),
+        Err(err) => (
+            422,
+            serde_json::json!({ "error": err, "output": output, "steps": steps }),
+        ),
+    };
+    if issued {
+        response["token"] = token.into();
+    }
+    (status, response)
+}
+
+// How a session token is written in the audit log
+fn token_hash(token: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+// A new session token: 128 random bits from the operating system, as hex
+fn new_token() -> String {
+    let mut bytes = [0; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .unwrap_or_else(|err| panic!("Failed to read /dev/urandom: {}", err));
+    hex_encode(&bytes)
+}
+
+// Evaluate a program in a session, with the fuel the session has left, and
+// return the result, what it printed and the steps used. Its definitions are kept only when
+// the whole program evaluates and the session stays within its limit.
+fn evaluate_in_session(
+    session: &mut Session,
+    program: &Expr,
+    options: &ServerOptions,
+    output: Output,
+) -> (Result<ResultValue, String>, String, u64) {
+    let located = locate_lambdas(program);
+    let (definitions, block) = split_definitions(&located);
+    if session.bindings + definitions.len() > options.max_bindings {
+        let message = format!(
+            "Session limit: at most {} definitions",
+            options.max_bindings
+        );
+        return (Err(message), String::new(), 0);
+    }
+    let env = session.env.clone();
+    let mut eval = Evaluation::new(options.version);
+    eval.loop_limit = Some(options.loop_limit);
+    eval.fuel = Some(options.fuel_quota - session.fuel_used);
+    eval.output = output;
+    let evaluated = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut env = env;
+        let mut index = 0;
+        while index < definitions.len() {
+            let level = definitions[index].level;
+            let mut vars = Vars::default();
+            for definition in definitions[index..].iter().take_while(|d| d.level == level) {
+                let value = evaluate_expr(definition.expr, &env, &mut eval);
+                vars.insert(definition.name.clone(), value);
+                index += 1;
+            }
+            env = Env::frame(eval.new_frame_id(), vars, Some(env));
+        }
+        let result = evaluate_block(block, &env, &mut eval);
+        (env, result)
+    }));
+    // Failed evaluations use fuel too
+    session.fuel_used += eval.steps.min(options.fuel_quota - session.fuel_used);
+    let output = eval.take_output();
+    let (env, result) = match evaluated {
+        Ok(evaluated) => evaluated,
+        Err(payload) => return (Err(panic_message(&payload)), output, eval.steps),
+    };
+    session.env = env;
+    session.bindings += definitions.len();
+    (Ok(result), output, eval.steps)
+}
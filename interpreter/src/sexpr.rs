@@ -0,0 +1,307 @@
+use crate::ast::{Expr, NodePath, Site, SourceMap, Span};
+
+// Read a program written as a Scheme-style s-expression:
+//
+//   (cond ((= n 0) 1) (true (mul n (fact (sub n 1)))))
+//
+// Numbers, strings and `#t`/`#f` are literals, other atoms are
+// identifiers, and a list is a call, except for the special forms
+// `(lambda (a b) body ...)` (or `λ`), `(cond (test result) ...)` and
+// `(letrec ((name value) ...) body ...)`.
+// `[a b]` is an array literal and `{"k" v}` a dict literal. `;` starts a
+// comment that runs to the end of the line. Also returns where every node
+// of the program is written.
+pub fn parse(text: &str) -> Result<(Expr, SourceMap), String> {
+    let mut parser = SexprParser {
+        chars: text.chars().collect(),
+        position: 0,
+    };
+    let (expr, spans) = parser.expr()?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        return Err(format!(
+            "Unexpected text after the program at position {}",
+            parser.position
+        ));
+    }
+    let mut map = SourceMap::default();
+    parser.record(
+        &spans,
+        &NodePath::default(),
+        &parser.line_starts(),
+        &mut map,
+    );
+    Ok((expr, map))
+}
+
+struct SexprParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+// The positions a node starts and ends at, with those of its children in
+// the order of `Expr::children`
+struct Spans {
+    start: usize,
+    end: usize,
+    children: Vec<Spans>,
+}
+
+impl SexprParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    // Skip whitespace and comments
+    fn skip_whitespace(&mut self) {
+        while let Some(char) = self.peek() {
+            if char == ';' {
+                while self.peek().is_some_and(|char| char != '\n') {
+                    self.position += 1;
+                }
+            } else if char.is_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expr(&mut self) -> Result<(Expr, Spans), String> {
+        self.skip_whitespace();
+        let start = self.position;
+        let (expr, children) = match self.peek() {
+            None => return Err("Expected an expression at the end of the input".to_string()),
+            Some('(') => {
+                self.position += 1;
+                self.list()?
+            }
+            Some('[') => {
+                self.position += 1;
+                let (elements, children) = self.until(']')?.into_iter().unzip();
+                (Expr::Array(elements), children)
+            }
+            Some('{') => {
+                self.position += 1;
+                self.dict()?
+            }
+            Some('"') => (Expr::String(self.string()?), Vec::new()),
+            Some(char @ (')' | ']' | '}')) => {
+                return Err(format!("Unexpected {} at position {}", char, self.position))
+            }
+            Some(_) => (self.atom(), Vec::new()),
+        };
+        let spans = Spans {
+            start,
+            end: self.position,
+            children,
+        };
+        Ok((expr, spans))
+    }
+
+    // The expressions up to a closing bracket, which is consumed
+    fn until(&mut self, close: char) -> Result<Vec<(Expr, Spans)>, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(char) if char == close => {
+                    self.position += 1;
+                    return Ok(items);
+                }
+                None => return Err(format!("Missing {} at the end of the input", close)),
+                Some(_) => items.push(self.expr()?),
+            }
+        }
+    }
+
+    // A list after its `(`: a special form or a call
+    fn list(&mut self) -> Result<(Expr, Vec<Spans>), String> {
+        let start = self.position - 1;
+        let mut items = self.until(')')?;
+        match items.first() {
+            Some((Expr::Identifier(head), _)) if head == "lambda" || head == "λ" => {
+                let Some((Expr::Application(parameters), _)) = items.get(1) else {
+                    return Err(format!(
+                        "A lambda needs a list of parameters at position {}",
+                        start
+                    ));
+                };
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| match parameter {
+                        Expr::Identifier(name) => Ok(name.to_string()),
+                        _ => Err(format!(
+                            "Invalid parameter {} at position {}",
+                            parameter, start
+                        )),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if items.len() < 3 {
+                    return Err(format!("A lambda needs a body at position {}", start));
+                }
+                let (body, children): (Vec<Expr>, _) = items.split_off(2).into_iter().unzip();
+                Ok((
+                    Expr::Lambda(parameters, body.into(), Site::default()),
+                    children,
+                ))
+            }
+            Some((Expr::Identifier(head), _)) if head == "cond" => {
+                let mut children = Vec::new();
+                let clauses = items
+                    .drain(1..)
+                    .map(|clause| match clause {
+                        (Expr::Application(parts), spans) if parts.len() == 2 => {
+                            children.extend(spans.children);
+                            let mut parts = parts.into_iter();
+                            Ok((parts.next().unwrap(), parts.next().unwrap()))
+                        }
+                        _ => Err(format!(
+                            "A cond clause needs a test and a result at position {}",
+                            start
+                        )),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((Expr::Cond(clauses), children))
+            }
+            Some((Expr::Identifier(head), _)) if head == "letrec" => {
+                if !matches!(items.get(1), Some((Expr::Application(_), _))) {
+                    return Err(format!(
+                        "A letrec needs a list of bindings at position {}",
+                        start
+                    ));
+                }
+                if items.len() < 3 {
+                    return Err(format!("A letrec needs a body at position {}", start));
+                }
+                let (body, body_spans): (Vec<_>, Vec<_>) = items.split_off(2).into_iter().unzip();
+                let Some((Expr::Application(pairs), spans)) = items.pop() else {
+                    unreachable!()
+                };
+                let mut children = Vec::new();
+                let mut bindings = Vec::new();
+                for (pair, spans) in pairs.iter().zip(spans.children) {
+                    let (name, value) = match pair {
+                        Expr::Application(parts) => match parts.as_slice() {
+                            [Expr::Identifier(name), value] => (name.to_string(), value.clone()),
+                            _ => return Err(invalid_binding(start)),
+                        },
+                        _ => return Err(invalid_binding(start)),
+                    };
+                    bindings.push((name, value));
+                    children.extend(spans.children.into_iter().nth(1));
+                }
+                children.extend(body_spans);
+                Ok((Expr::Letrec(bindings, body), children))
+            }
+            _ => {
+                let (items, children) = items.into_iter().unzip();
+                Ok((Expr::Application(items), children))
+            }
+        }
+    }
+
+    // A dict after its `{`: string keys alternating with values
+    fn dict(&mut self) -> Result<(Expr, Vec<Spans>), String> {
+        let start = self.position - 1;
+        let mut entries = Vec::new();
+        let mut children = Vec::new();
+        let mut items = self.until('}')?.into_iter();
+        while let Some((key, _)) = items.next() {
+            let (Expr::String(key), Some((value, spans))) = (key, items.next()) else {
+                return Err(format!(
+                    "A dict needs string keys, each followed by a value, at position {}",
+                    start
+                ));
+            };
+            entries.push((key, value));
+            children.push(spans);
+        }
+        Ok((Expr::Dict(entries), children))
+    }
+
+    // A string literal, with the escapes \" \\ \n and \t
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.position;
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let char = self
+                .peek()
+                .ok_or_else(|| format!("Unterminated string at position {}", start))?;
+            self.position += 1;
+            match char {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        _ => return Err(format!("Unknown escape at position {}", self.position)),
+                    };
+                    self.position += 1;
+                    string.push(escaped);
+                }
+                _ => string.push(char),
+            }
+        }
+    }
+
+    // A number or an identifier: everything up to whitespace or a bracket
+    fn atom(&mut self) -> Expr {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|char| !char.is_whitespace() && !"()[]{}\";".contains(char))
+        {
+            self.position += 1;
+        }
+        let atom: String = self.chars[start..self.position].iter().collect();
+        match atom.as_str() {
+            "#t" => return Expr::Bool(true),
+            "#f" => return Expr::Bool(false),
+            _ => {}
+        }
+        match atom.parse() {
+            Ok(number) => Expr::Number(number),
+            Err(_) => Expr::Identifier(atom.into()),
+        }
+    }
+
+    // The position of the first char of every line
+    fn line_starts(&self) -> Vec<usize> {
+        let breaks = self.chars.iter().enumerate();
+        let starts = breaks
+            .filter(|(_, &char)| char == '\n')
+            .map(|(at, _)| at + 1);
+        std::iter::once(0).chain(starts).collect()
+    }
+
+    // Add the spans of a node and its descendants to the map
+    fn record(&self, spans: &Spans, path: &NodePath, line_starts: &[usize], map: &mut SourceMap) {
+        let line_column = |position: usize| {
+            let line = line_starts.partition_point(|&start| start <= position);
+            (line, position - line_starts[line - 1] + 1)
+        };
+        let (line, column) = line_column(spans.start);
+        let (end_line, end_column) = line_column(spans.end);
+        let span = Span {
+            line,
+            column,
+            end_line,
+            end_column,
+        };
+        map.0.insert(path.clone(), span);
+        for (index, child) in spans.children.iter().enumerate() {
+            self.record(child, &path.child(index), line_starts, map);
+        }
+    }
+}
+
+fn invalid_binding(position: usize) -> String {
+    format!(
+        "A letrec binding needs a name and a value at position {}",
+        position
+    )
+}
@@ -0,0 +1,715 @@
+use crate::ast::*;
+use crate::env::*;
+use crate::eval::*;
+use serde_json::Value;
+use std::fmt;
+
+// Substitution semantics for the pure subset of the language: instead of
+// environments, applying a lambda substitutes the argument values into its
+// body. Each call of `step` performs one reduction, leftmost innermost, so
+// the sequence of steps is the call-by-value reduction of the program.
+pub(crate) struct Stepper {
+    pub(crate) version: LangVersion,
+}
+
+impl Stepper {
+    // Reduce once; returns the new expression and the rule that was used,
+    // or `None` when the expression is a value or stuck
+    pub(crate) fn step(&self, expr: &Expr) -> Option<(Expr, String)> {
+        match expr {
+            Expr::Application(items) => {
+                // Reduce the function and the arguments first, left to right.
+                // A builtin in function position is only reduced by calling it.
+                for (i, item) in items.iter().enumerate() {
+                    let builtin = i == 0 && matches!(item, Expr::Identifier(_));
+                    if !builtin && !is_value(item) {
+                        let (reduced, rule) = self.step(item)?;
+                        let mut items = items.clone();
+                        items[i] = reduced;
+                        return Some((Expr::Application(items), rule));
+                    }
+                }
+                match &items[0] {
+                    Expr::Lambda(parameters, body, _) => {
+                        let result = beta_reduce(parameters, body, &items[1..], self.version)?;
+                        Some((result, "beta".to_string()))
+                    }
+                    Expr::Identifier(name) => {
+                        let result = reduce_primitive(builtin_name(name), &items[1..])?;
+                        Some((result, format!("delta {}", name)))
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Cond(clauses) => {
+                let (test, result) = clauses.first()?;
+                if !is_value(test) {
+                    let (reduced, rule) = self.step(test)?;
+                    let mut clauses = clauses.clone();
+                    clauses[0].0 = reduced;
+                    return Some((Expr::Cond(clauses), rule));
+                }
+                let taken = match test {
+                    Expr::Identifier(name) if name == "true" => true,
+                    Expr::Identifier(name) if name == "false" => false,
+                    Expr::Bool(value) => *value,
+                    _ => return None,
+                };
+                if taken {
+                    Some((result.clone(), "cond".to_string()))
+                } else {
+                    Some((Expr::Cond(clauses[1..].to_vec()), "cond".to_string()))
+                }
+            }
+            // Unfold: each name becomes the letrec again, with the lambda
+            // bound to it as its body
+            Expr::Letrec(bindings, body) if self.version.letrec() => {
+                if !bindings
+                    .iter()
+                    .all(|(_, value)| matches!(value, Expr::Lambda(_, _, _)))
+                {
+                    return None;
+                }
+                let mut result = body.last()?.clone();
+                for (name, value) in bindings {
+                    let unfolded = Expr::Letrec(bindings.clone(), vec![value.clone()]);
+                    result = substitute(&result, name, &unfolded);
+                }
+                Some((result, "letrec".to_string()))
+            }
+            // Reduce the elements left to right
+            Expr::Array(_) | Expr::Dict(_) => {
+                let mut children: Vec<Expr> = expr.children().into_iter().cloned().collect();
+                let i = children.iter().position(|child| !is_value(child))?;
+                let (reduced, rule) = self.step(&children[i])?;
+                children[i] = reduced;
+                Some((expr.with_children(children), rule))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Substitute the arguments for the parameters in the body of a lambda
+fn beta_reduce(
+    parameters: &[String],
+    body: &[Expr],
+    arguments: &[Expr],
+    version: LangVersion,
+) -> Option<Expr> {
+    if arguments.len() < parameters.len() {
+        return None;
+    }
+    let mut result = if version.block_scoping() {
+        body.last()?.clone()
+    } else {
+        body.first()?.clone()
+    };
+    // Substitute all parameters at once by first renaming them apart from
+    // the arguments
+    let mut avoid: Vec<String> = parameters.to_vec();
+    for argument in arguments {
+        avoid.extend(argument.free_variables());
+    }
+    avoid.extend(result.free_variables());
+    let mut renamed = Vec::new();
+    for parameter in parameters {
+        let fresh = fresh_name(parameter, &avoid);
+        avoid.push(fresh.clone());
+        result = substitute(&result, parameter, &Expr::Identifier(fresh.as_str().into()));
+        renamed.push(fresh);
+    }
+    for (name, argument) in renamed.iter().zip(arguments) {
+        result = substitute(&result, name, argument);
+    }
+    Some(result)
+}
+
+// Values can't be reduced any further. `builtin:NAME` is not a value, so
+// a program that doesn't call it is stuck.
+pub(crate) fn is_value(expr: &Expr) -> bool {
+    match expr {
+        Expr::Identifier(name) => !name.starts_with(BUILTIN_PREFIX),
+        Expr::Array(_) | Expr::Dict(_) => expr.children().into_iter().all(is_value),
+        expr => matches!(
+            expr,
+            Expr::Number(_) | Expr::String(_) | Expr::Bool(_) | Expr::Lambda(_, _, _)
+        ),
+    }
+}
+
+// Compute a builtin applied to values, as a single reduction
+fn reduce_primitive(name: &str, arguments: &[Expr]) -> Option<Expr> {
+    let numbers: Vec<i64> = arguments.iter().map(as_number).collect::<Option<_>>()?;
+    let boolean = |value: bool| Some(Expr::Identifier(value.to_string().into()));
+    match (name, numbers.as_slice()) {
+        ("add", _) => numbers
+            .iter()
+            .try_fold(0i64, |a, b| a.checked_add(*b))
+            .map(Expr::Number),
+        ("sub", [first, rest @ ..]) => rest
+            .iter()
+            .try_fold(*first, |a, b| a.checked_sub(*b))
+            .map(Expr::Number),
+        ("mul", _) => numbers
+            .iter()
+            .try_fold(1i64, |a, b| a.checked_mul(*b))
+            .map(Expr::Number),
+        ("div", _) => numbers
+            .iter()
+            .try_fold(1i64, |a, b| a.checked_div(*b))
+            .map(Expr::Number),
+        ("zero?", [n]) => boolean(*n == 0),
+        ("=", [a, b]) => boolean(a == b),
+        ("<", [a, b]) => boolean(a < b),
+        ("<=", [a, b]) => boolean(a <= b),
+        (">", [a, b]) => boolean(a > b),
+        (">=", [a, b]) => boolean(a >= b),
+        _ => None,
+    }
+}
+
+// Capture-avoiding substitution of `value` for the free occurrences of `name`
+pub(crate) fn substitute(expr: &Expr, name: &str, value: &Expr) -> Expr {
+    match expr {
+        Expr::Identifier(identifier) if identifier == name => value.clone(),
+        Expr::Array(_) | Expr::Dict(_) => expr.with_children(
+            expr.children()
+                .into_iter()
+                .map(|child| substitute(child, name, value))
+                .collect(),
+        ),
+        Expr::Lambda(parameters, body, _) => {
+            if parameters.iter().any(|parameter| parameter == name) {
+                // `name` is shadowed inside
+                return expr.clone();
+            }
+            // Rename parameters that would capture free variables of `value`
+            let free = value.free_variables();
+            let mut parameters = parameters.clone();
+            let mut body = body.clone();
+            for parameter in parameters.iter_mut() {
+                if free.contains(parameter) {
+                    let mut avoid = free.clone();
+                    for item in body.iter() {
+                        avoid.extend(item.free_variables());
+                    }
+                    let fresh = fresh_name(parameter, &avoid);
+                    let renamed = Expr::Identifier(fresh.as_str().into());
+                    body = body
+                        .iter()
+                        .map(|item| substitute(item, parameter, &renamed))
+                        .collect();
+                    *parameter = fresh;
+                }
+            }
+            let body = body
+                .iter()
+                .map(|item| substitute(item, name, value))
+                .collect();
+            Expr::Lambda(parameters, body, Site::default())
+        }
+        Expr::Letrec(bindings, body) => {
+            if bindings.iter().any(|(bound, _)| bound == name) {
+                // `name` is shadowed inside
+                return expr.clone();
+            }
+            // Rename bound names that would capture free variables of
+            // `value`, in the bindings and the body alike
+            let free = value.free_variables();
+            let mut bindings = bindings.clone();
+            let mut body = body.clone();
+            for i in 0..bindings.len() {
+                let bound = bindings[i].0.clone();
+                if free.contains(&bound) {
+                    let mut avoid = free.clone();
+                    for item in bindings.iter().map(|(_, item)| item).chain(&body) {
+                        avoid.extend(item.free_variables());
+                    }
+                    let fresh = fresh_name(&bound, &avoid);
+                    let renamed = Expr::Identifier(fresh.as_str().into());
+                    for (_, item) in bindings.iter_mut() {
+                        *item = substitute(item, &bound, &renamed);
+                    }
+                    body = body
+                        .iter()
+                        .map(|item| substitute(item, &bound, &renamed))
+                        .collect();
+                    bindings[i].0 = fresh;
+                }
+            }
+            Expr::Letrec(
+                bindings
+                    .into_iter()
+                    .map(|(bound, item)| (bound, substitute(&item, name, value)))
+                    .collect(),
+                body.iter()
+                    .map(|item| substitute(item, name, value))
+                    .collect(),
+            )
+        }
+        Expr::Application(items) => Expr::Application(
+            items
+                .iter()
+                .map(|item| substitute(item, name, value))
+                .collect(),
+        ),
+        Expr::Cond(clauses) => Expr::Cond(
+            clauses
+                .iter()
+                .map(|(test, result)| {
+                    (
+                        substitute(test, name, value),
+                        substitute(result, name, value),
+                    )
+                })
+                .collect(),
+        ),
+        _ => expr.clone(),
+    }
+}
+
+// `name` with primes added until it is not in `avoid`
+fn fresh_name(name: &str, avoid: &[String]) -> String {
+    let mut fresh = format!("{}'", name);
+    while avoid.contains(&fresh) {
+        fresh.push('\'');
+    }
+    fresh
+}
+
+// Church encodings of booleans, numerals and pairs, loaded with
+// `--prelude church`. Definitions are evaluated in order, so later ones
+// may use earlier ones.
+const CHURCH_PRELUDE: &str = include_str!("../prelude/church.json");
+
+// Array functions written with accumulators, loaded with `--prelude lists`,
+// as reference material for accumulator-passing style. Each one is a
+// closure over a `letrec` loop that carries an index, so the loop makes
+// tail calls and runs in constant stack.
+const LISTS_PRELUDE: &str = include_str!("../prelude/lists.json");
+
+// Evaluate the definitions of a prelude into the global environment
+pub(crate) fn load_prelude(name: &str, globals: &mut Env, eval: &mut Evaluation) {
+    let source = match name {
+        "church" => CHURCH_PRELUDE,
+        "lists" => LISTS_PRELUDE,
+        _ => panic!("Unknown prelude: {} (expected church or lists)", name),
+    };
+    let definitions: Vec<(String, Value)> =
+        serde_json::from_str(source).expect("Prelude is not well-formatted");
+    // Preludes are written in the newest version of the language. Their
+    // `letrec`s are evaluated here, so the closures they make work in
+    // programs of every version.
+    let version = std::mem::replace(&mut eval.version, LangVersion::Cp4);
+    for (name, json) in definitions {
+        let expr = Expr::from_json(&json).unwrap_or_else(|err| panic!("{}", err));
+        // The definitions so far, without the ones to come, so that no
+        // closure holds the frame it is bound in
+        let value = evaluate_expr(&expr, &globals.copy(), eval);
+        globals.define(&name, value);
+    }
+    eval.version = version;
+}
+
+// In `--pure-lambda` mode programs may only use lambdas, applications and
+// variables bound by lambdas or the prelude
+pub(crate) fn check_pure_lambda(program: &Expr, globals: &Env) -> Result<(), String> {
+    let mut problem = None;
+    program.walk(&mut |_, path, node| {
+        if problem.is_none() {
+            match node {
+                Expr::Number(_)
+                | Expr::String(_)
+                | Expr::Bool(_)
+                | Expr::Cond(_)
+                | Expr::Array(_)
+                | Expr::Dict(_) => {
+                    problem = Some(format!(
+                        "{} at {} is not allowed in pure lambda calculus",
+                        node.kind(),
+                        path
+                    ));
+                }
+                _ => {}
+            }
+        }
+    });
+    if let Some(problem) = problem {
+        return Err(problem);
+    }
+    for name in program.free_variables() {
+        if globals.get(&name).is_none() {
+            return Err(format!("{} is not bound; builtins are disabled", name));
+        }
+    }
+    Ok(())
+}
+
+// Turn a closure back into a closed term by substituting the values of its
+// free variables
+pub(crate) fn read_back(value: &ResultValue) -> Expr {
+    match value {
+        ResultValue::Number(number) => Expr::Number(*number),
+        ResultValue::String(string) => Expr::String(string.clone()),
+        ResultValue::Bool(value) => Expr::Bool(*value),
+        ResultValue::Lambda(parameters, body, env, _) => {
+            let mut term = Expr::Lambda(parameters.clone(), body.clone(), Site::default());
+            for name in term.free_variables() {
+                if let Some(value) = env.get(&name) {
+                    term = substitute(&term, &name, &read_back(&value));
+                }
+            }
+            term
+        }
+        ResultValue::Contract(contract) => read_back(&contract.2),
+        ResultValue::Parser(parser) => {
+            fail(EvalError::TypeError(format!("Can't read back {}", parser)))
+        }
+        ResultValue::Doc(_) => fail(EvalError::TypeError(
+            "Can't read back a document".to_string(),
+        )),
+        value @ (ResultValue::Unit
+        | ResultValue::Float(_)
+        | ResultValue::Iterator(_)
+        | ResultValue::Array(_)
+        | ResultValue::Set(_)
+        | ResultValue::Dict(_)
+        | ResultValue::Bytes(_)
+        | ResultValue::DateTime(_)) => {
+            fail(EvalError::TypeError(format!("Can't read back {}", value)))
+        }
+    }
+}
+
+// Reduce the leftmost outermost redex, also under lambdas, so repeated
+// steps find the normal form of a term if it has one
+pub(crate) fn normal_order_step(expr: &Expr, version: LangVersion) -> Option<Expr> {
+    match expr {
+        Expr::Application(items) => {
+            if let Expr::Lambda(parameters, body, _) = &items[0] {
+                if let Some(result) = beta_reduce(parameters, body, &items[1..], version) {
+                    return Some(result);
+                }
+            }
+            for (i, item) in items.iter().enumerate() {
+                if let Some(reduced) = normal_order_step(item, version) {
+                    let mut items = items.clone();
+                    items[i] = reduced;
+                    return Some(Expr::Application(items));
+                }
+            }
+            None
+        }
+        Expr::Lambda(parameters, body, _) => {
+            for (i, item) in body.iter().enumerate() {
+                if let Some(reduced) = normal_order_step(item, version) {
+                    let mut body = body.to_vec();
+                    body[i] = reduced;
+                    return Some(Expr::Lambda(
+                        parameters.clone(),
+                        body.into(),
+                        Site::default(),
+                    ));
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+// Name a normal form that is a Church boolean or numeral
+pub(crate) fn church_meaning(term: &Expr) -> Option<String> {
+    let Expr::Lambda(outer, body, _) = term else {
+        return None;
+    };
+    let [Expr::Lambda(inner, body, _)] = &body[..] else {
+        return None;
+    };
+    let ([f], [x], [body]) = (outer.as_slice(), inner.as_slice(), &body[..]) else {
+        return None;
+    };
+    match body {
+        Expr::Identifier(name) if name == f => return Some("true".to_string()),
+        Expr::Identifier(name) if name == x && f != x => {
+            return Some("false / 0".to_string());
+        }
+        _ => {}
+    }
+    // f(f(...f(x)))
+    let mut count = 0;
+    let mut node = body;
+    loop {
+        match node {
+            Expr::Identifier(name) if name == x => return Some(count.to_string()),
+            Expr::Application(items)
+                if items.len() == 2 && items[0] == Expr::Identifier(f.as_str().into()) =>
+            {
+                count += 1;
+                node = &items[1];
+            }
+            _ => return None,
+        }
+    }
+}
+
+// A program with de Bruijn indices instead of variable names. Lambdas take
+// several parameters, so a bound variable is a pair: how many lambdas out
+// its binder is (0 for the innermost) and its position in that lambda's
+// parameter list. Printed as `depth.slot`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DeBruijn {
+    Number(i64),
+    String(String),
+    Bool(bool),
+    Bound(usize, usize),
+    // Variables no lambda binds (globals and builtins) keep their name
+    Free(String),
+    Application(Vec<DeBruijn>),
+    // Number of parameters and the body
+    Lambda(usize, Vec<DeBruijn>),
+    // The bound values and the body, all in the scope of the bindings,
+    // which are numbered like the parameters of a lambda
+    Letrec(Vec<DeBruijn>, Vec<DeBruijn>),
+    Cond(Vec<(DeBruijn, DeBruijn)>),
+    Array(Vec<DeBruijn>),
+    Dict(Vec<(String, DeBruijn)>),
+}
+
+impl DeBruijn {
+    pub(crate) fn from_expr(expr: &Expr) -> DeBruijn {
+        DeBruijn::convert(expr, &mut Vec::new())
+    }
+
+    // `scope` holds the parameter lists of the enclosing lambdas (and the
+    // names of the enclosing letrecs), innermost last
+    fn convert<'a>(expr: &'a Expr, scope: &mut Vec<Vec<&'a str>>) -> DeBruijn {
+        match expr {
+            Expr::Number(number) => DeBruijn::Number(*number),
+            Expr::String(string) => DeBruijn::String(string.clone()),
+            Expr::Bool(value) => DeBruijn::Bool(*value),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => {
+                for (depth, parameters) in scope.iter().rev().enumerate() {
+                    if let Some(slot) = parameters.iter().position(|parameter| parameter == name) {
+                        return DeBruijn::Bound(depth, slot);
+                    }
+                }
+                DeBruijn::Free(name.to_string())
+            }
+            Expr::Application(items) => DeBruijn::Application(
+                items
+                    .iter()
+                    .map(|item| DeBruijn::convert(item, scope))
+                    .collect(),
+            ),
+            Expr::Lambda(parameters, body, _) => {
+                scope.push(parameters.iter().map(String::as_str).collect());
+                let body = body
+                    .iter()
+                    .map(|item| DeBruijn::convert(item, scope))
+                    .collect();
+                scope.pop();
+                DeBruijn::Lambda(parameters.len(), body)
+            }
+            Expr::Letrec(bindings, body) => {
+                scope.push(bindings.iter().map(|(name, _)| name.as_str()).collect());
+                let values = bindings
+                    .iter()
+                    .map(|(_, value)| DeBruijn::convert(value, scope))
+                    .collect();
+                let body = body
+                    .iter()
+                    .map(|item| DeBruijn::convert(item, scope))
+                    .collect();
+                scope.pop();
+                DeBruijn::Letrec(values, body)
+            }
+            Expr::Cond(clauses) => DeBruijn::Cond(
+                clauses
+                    .iter()
+                    .map(|(test, result)| {
+                        (
+                            DeBruijn::convert(test, scope),
+                            DeBruijn::convert(result, scope),
+                        )
+                    })
+                    .collect(),
+            ),
+            Expr::Array(elements) => DeBruijn::Array(
+                elements
+                    .iter()
+                    .map(|element| DeBruijn::convert(element, scope))
+                    .collect(),
+            ),
+            Expr::Dict(entries) => DeBruijn::Dict(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), DeBruijn::convert(value, scope)))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Back to named variables: parameters are called `x<level>` (with
+    // `_<slot>` when a lambda has several), primed where that would clash
+    // with a free variable
+    pub(crate) fn to_expr(&self) -> Expr {
+        let mut free = Vec::new();
+        self.collect_free(&mut free);
+        self.rename(&mut Vec::new(), &free)
+    }
+
+    fn collect_free(&self, free: &mut Vec<String>) {
+        match self {
+            DeBruijn::Free(name) => free.push(name.clone()),
+            DeBruijn::Application(items) | DeBruijn::Lambda(_, items) | DeBruijn::Array(items) => {
+                for item in items {
+                    item.collect_free(free);
+                }
+            }
+            DeBruijn::Letrec(values, body) => {
+                for item in values.iter().chain(body) {
+                    item.collect_free(free);
+                }
+            }
+            DeBruijn::Dict(entries) => {
+                for (_, value) in entries {
+                    value.collect_free(free);
+                }
+            }
+            DeBruijn::Cond(clauses) => {
+                for (test, result) in clauses {
+                    test.collect_free(free);
+                    result.collect_free(free);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The names of the `count` variables bound at `level`
+    fn names(count: usize, level: usize, free: &[String]) -> Vec<String> {
+        (0..count)
+            .map(|slot| {
+                let name = if count == 1 {
+                    format!("x{}", level)
+                } else {
+                    format!("x{}_{}", level, slot)
+                };
+                if free.contains(&name) {
+                    fresh_name(&name, free)
+                } else {
+                    name
+                }
+            })
+            .collect()
+    }
+
+    fn rename(&self, scope: &mut Vec<Vec<String>>, free: &[String]) -> Expr {
+        match self {
+            DeBruijn::Number(number) => Expr::Number(*number),
+            DeBruijn::String(string) => Expr::String(string.clone()),
+            DeBruijn::Bool(value) => Expr::Bool(*value),
+            DeBruijn::Bound(depth, slot) => {
+                Expr::Identifier(scope[scope.len() - 1 - depth][*slot].as_str().into())
+            }
+            DeBruijn::Free(name) => Expr::Identifier(name.as_str().into()),
+            DeBruijn::Application(items) => {
+                Expr::Application(items.iter().map(|item| item.rename(scope, free)).collect())
+            }
+            DeBruijn::Lambda(count, body) => {
+                let parameters = DeBruijn::names(*count, scope.len(), free);
+                scope.push(parameters.clone());
+                let body = body.iter().map(|item| item.rename(scope, free)).collect();
+                scope.pop();
+                Expr::Lambda(parameters, body, Site::default())
+            }
+            DeBruijn::Letrec(values, body) => {
+                let names = DeBruijn::names(values.len(), scope.len(), free);
+                scope.push(names.clone());
+                let bindings = names
+                    .into_iter()
+                    .zip(values)
+                    .map(|(name, value)| (name, value.rename(scope, free)))
+                    .collect();
+                let body = body.iter().map(|item| item.rename(scope, free)).collect();
+                scope.pop();
+                Expr::Letrec(bindings, body)
+            }
+            DeBruijn::Cond(clauses) => Expr::Cond(
+                clauses
+                    .iter()
+                    .map(|(test, result)| (test.rename(scope, free), result.rename(scope, free)))
+                    .collect(),
+            ),
+            DeBruijn::Array(elements) => Expr::Array(
+                elements
+                    .iter()
+                    .map(|element| element.rename(scope, free))
+                    .collect(),
+            ),
+            DeBruijn::Dict(entries) => Expr::Dict(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.rename(scope, free)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DeBruijn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeBruijn::Number(number) => write!(f, "{}", number),
+            DeBruijn::String(string) => write!(f, "{:?}", string),
+            DeBruijn::Bool(value) => write!(f, "{}", value),
+            DeBruijn::Bound(depth, slot) => write!(f, "{}.{}", depth, slot),
+            DeBruijn::Free(name) => write!(f, "{}", name),
+            DeBruijn::Application(items) => {
+                write!(f, "{}(", items[0])?;
+                for (i, item) in items.iter().skip(1).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            DeBruijn::Lambda(count, body) => {
+                write!(f, "λ{} {{ ", count)?;
+                for (i, item) in body.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, " }}")
+            }
+            DeBruijn::Letrec(values, body) => {
+                let values: Vec<String> = values.iter().map(DeBruijn::to_string).collect();
+                let body: Vec<String> = body.iter().map(DeBruijn::to_string).collect();
+                write!(f, "letrec {} {{ {} }}", values.join(", "), body.join("; "))
+            }
+            DeBruijn::Cond(clauses) => {
+                write!(f, "cond")?;
+                for (test, result) in clauses {
+                    write!(f, " ({} => {})", test, result)?;
+                }
+                Ok(())
+            }
+            DeBruijn::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(DeBruijn::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            DeBruijn::Dict(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{:?}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
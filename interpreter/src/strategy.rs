@@ -0,0 +1,380 @@
+use crate::ast::*;
+use crate::builtins::*;
+use crate::env::*;
+use crate::eval::*;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Steps each strategy may take before it counts as not terminating
+const STRATEGY_FUEL: usize = 1_000_000;
+
+// How arguments are passed to lambdas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Strategy {
+    // Evaluated before the call
+    Value,
+    // Evaluated every time the parameter is used
+    Name,
+    // Evaluated the first time the parameter is used, then remembered
+    Need,
+}
+
+impl Strategy {
+    const ALL: [Strategy; 3] = [Strategy::Value, Strategy::Name, Strategy::Need];
+
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::Value => "call-by-value",
+            Strategy::Name => "call-by-name",
+            Strategy::Need => "call-by-need",
+        }
+    }
+}
+
+// A value of the comparison's evaluator: data, which builtins work on, or a
+// closure, whose frames hold the arguments unevaluated
+#[derive(Clone)]
+enum Value {
+    Data(ResultValue),
+    Closure(Rc<Closure>),
+}
+
+struct Closure {
+    parameters: Vec<String>,
+    body: Arc<[Expr]>,
+    scope: Rc<Frame>,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Data(value) => write!(f, "{}", value.quoted()),
+            Value::Closure(closure) => write!(f, "<closure ({})>", closure.parameters.join(", ")),
+        }
+    }
+}
+
+// An argument: its value, or the expression and scope to evaluate it in
+enum Thunk {
+    Done(Value),
+    Delayed(Expr, Rc<Frame>),
+}
+
+struct Frame {
+    vars: HashMap<String, Rc<RefCell<Thunk>>>,
+    parent: Option<Rc<Frame>>,
+}
+
+impl Frame {
+    fn get(&self, name: &str) -> Option<Rc<RefCell<Thunk>>> {
+        match self.vars.get(name) {
+            Some(thunk) => Some(thunk.clone()),
+            None => self.parent.as_ref()?.get(name),
+        }
+    }
+}
+
+// Evaluates a program under one strategy, counting the expressions it
+// evaluates and the thunks it forces
+struct Machine {
+    strategy: Strategy,
+    steps: usize,
+    forces: usize,
+    depth: usize,
+    version: LangVersion,
+    // For the builtins and the depth limit
+    eval: Evaluation,
+}
+
+impl Machine {
+    fn evaluate(&mut self, expr: &Expr, scope: &Rc<Frame>) -> Value {
+        self.steps += 1;
+        if self.steps > STRATEGY_FUEL {
+            fail(EvalError::FuelExhausted(STRATEGY_FUEL as u64));
+        }
+        if self.depth >= self.eval.max_depth {
+            fail(EvalError::LimitExceeded(format!(
+                "Too deeply nested: over {} expressions in progress",
+                self.eval.max_depth
+            )));
+        }
+        self.depth += 1;
+        let value = self.evaluate_node(expr, scope);
+        self.depth -= 1;
+        value
+    }
+
+    fn evaluate_node(&mut self, expr: &Expr, scope: &Rc<Frame>) -> Value {
+        match expr {
+            Expr::Number(number) => Value::Data(ResultValue::Number(*number)),
+            Expr::String(string) => Value::Data(ResultValue::String(string.clone())),
+            Expr::Bool(value) => Value::Data(ResultValue::Bool(*value)),
+            Expr::Identifier(name) | Expr::Local(name, _, _) => match scope.get(name) {
+                Some(thunk) => self.force(&thunk),
+                None if BUILTINS.contains(&builtin_name(name)) => fail(EvalError::TypeError(
+                    format!(
+                        "{} is a builtin and can only be called, wrap it in a lambda to pass it as a value",
+                        builtin_name(name)
+                    ),
+                )),
+                None => fail(EvalError::UnboundVariable(name.to_string())),
+            },
+            Expr::Lambda(parameters, body, _) => Value::Closure(Rc::new(Closure {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                scope: scope.clone(),
+            })),
+            Expr::Cond(clauses) => {
+                for (test, result) in clauses {
+                    if self.holds(test, scope) {
+                        return self.evaluate(result, scope);
+                    }
+                }
+                fail(EvalError::Other("No clause of the cond holds".to_string()))
+            }
+            Expr::Array(elements) => Value::Data(ResultValue::Array(
+                elements
+                    .iter()
+                    .map(|element| self.data(element, scope, "An array"))
+                    .collect(),
+            )),
+            Expr::Dict(entries) => Value::Data(ResultValue::Dict(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.data(value, scope, "A dict")))
+                    .collect(),
+            )),
+            Expr::Letrec(bindings, body) => {
+                // The bound lambdas are evaluated in the frame they are
+                // bound in, so the thunks are filled in once it exists
+                let thunks: Vec<_> = bindings
+                    .iter()
+                    .map(|_| Rc::new(RefCell::new(Thunk::Done(Value::Data(ResultValue::Unit)))))
+                    .collect();
+                let frame = Rc::new(Frame {
+                    vars: bindings
+                        .iter()
+                        .zip(&thunks)
+                        .map(|((name, _), thunk)| (name.clone(), thunk.clone()))
+                        .collect(),
+                    parent: Some(scope.clone()),
+                });
+                for ((_, value), thunk) in bindings.iter().zip(&thunks) {
+                    *thunk.borrow_mut() = Thunk::Delayed(value.clone(), frame.clone());
+                }
+                let mut result = Value::Data(ResultValue::Unit);
+                for expr in body {
+                    result = self.evaluate(expr, &frame);
+                }
+                result
+            }
+            Expr::Application(application) => {
+                if let Some(Expr::Identifier(name)) = application.first() {
+                    if name.starts_with(BUILTIN_PREFIX) || scope.get(name).is_none() {
+                        // Builtins are strict in every strategy
+                        let builtin = builtin_name(name);
+                        let arguments = application[1..]
+                            .iter()
+                            .map(|argument| self.data(argument, scope, builtin))
+                            .collect();
+                        return Value::Data(apply_builtin(builtin, arguments, &mut self.eval));
+                    }
+                }
+                let closure = match self.evaluate(&application[0], scope) {
+                    Value::Closure(closure) => closure,
+                    Value::Data(value) => fail(EvalError::TypeError(format!(
+                        "Can't call {}: only lambdas of the program can be compared",
+                        value.described()
+                    ))),
+                };
+                if application.len() - 1 < closure.parameters.len() {
+                    fail(EvalError::ArityMismatch {
+                        expected: closure.parameters.len(),
+                        got: application.len() - 1,
+                    });
+                }
+                let mut vars = HashMap::new();
+                for (parameter, argument) in closure.parameters.iter().zip(&application[1..]) {
+                    let thunk = match self.strategy {
+                        Strategy::Value => Thunk::Done(self.evaluate(argument, scope)),
+                        Strategy::Name | Strategy::Need => {
+                            Thunk::Delayed(argument.clone(), scope.clone())
+                        }
+                    };
+                    vars.insert(parameter.clone(), Rc::new(RefCell::new(thunk)));
+                }
+                let frame = Rc::new(Frame {
+                    vars,
+                    parent: Some(closure.scope.clone()),
+                });
+                let body = if self.version.block_scoping() {
+                    &closure.body[..]
+                } else {
+                    &closure.body[..1]
+                };
+                let mut result = Value::Data(ResultValue::Number(0));
+                for expr in body {
+                    result = self.evaluate(expr, &frame);
+                }
+                result
+            }
+        }
+    }
+
+    // The value of a parameter, evaluating its argument if it hasn't been
+    fn force(&mut self, thunk: &Rc<RefCell<Thunk>>) -> Value {
+        let (expr, scope) = match &*thunk.borrow() {
+            Thunk::Done(value) => return value.clone(),
+            Thunk::Delayed(expr, scope) => (expr.clone(), scope.clone()),
+        };
+        self.forces += 1;
+        let value = self.evaluate(&expr, &scope);
+        if self.strategy == Strategy::Need {
+            *thunk.borrow_mut() = Thunk::Done(value.clone());
+        }
+        value
+    }
+
+    // The value of an expression that must not be a closure, for builtins
+    // and literals
+    fn data(&mut self, expr: &Expr, scope: &Rc<Frame>, user: &str) -> ResultValue {
+        match self.evaluate(expr, scope) {
+            Value::Data(value) => value,
+            Value::Closure(_) => fail(EvalError::TypeError(format!(
+                "{} can't hold a lambda in the strategy comparison",
+                user
+            ))),
+        }
+    }
+
+    // Whether a cond test holds, read like the evaluator does
+    fn holds(&mut self, test: &Expr, scope: &Rc<Frame>) -> bool {
+        let number = |value: ResultValue| match value {
+            ResultValue::Number(number) => number,
+            value => fail(EvalError::TypeError(format!(
+                "Expected a number but got {}",
+                value
+            ))),
+        };
+        match test {
+            Expr::Identifier(name) if name == "true" => true,
+            Expr::Identifier(name) if name == "false" => false,
+            Expr::Bool(value) => *value,
+            Expr::Application(items) => match items.first() {
+                Some(Expr::Identifier(operator)) if operator == "zero?" => {
+                    number(self.data(&items[1], scope, "zero?")) == 0
+                }
+                Some(Expr::Identifier(operator))
+                    if ["=", "<", "<=", ">", ">="].contains(&operator.as_str()) =>
+                {
+                    let left = self.data(&items[1], scope, operator);
+                    let right = self.data(&items[2], scope, operator);
+                    let ordering = left.compare(&right).unwrap_or_else(|err| fail(err));
+                    match operator.as_str() {
+                        "=" => ordering == Ordering::Equal,
+                        "<" => ordering == Ordering::Less,
+                        "<=" => ordering != Ordering::Greater,
+                        ">" => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    }
+                }
+                Some(Expr::Identifier(operator)) if !self.version.boolean_calls() => {
+                    fail(EvalError::UnknownOperator(operator.to_string()))
+                }
+                _ => match self.data(test, scope, "A cond test") {
+                    ResultValue::Bool(value) => value,
+                    value => fail(EvalError::TypeError(format!(
+                        "Expected a boolean but got {}",
+                        value
+                    ))),
+                },
+            },
+            _ => fail(EvalError::InvalidCondition(test.to_string())),
+        }
+    }
+}
+
+// How a program fared under one strategy
+struct Outcome {
+    strategy: Strategy,
+    steps: usize,
+    forces: usize,
+    result: String,
+}
+
+// Evaluate a program under call-by-value, call-by-name and call-by-need,
+// and print the steps each took, how many arguments they evaluated on use,
+// and their results. Builtins evaluate their arguments first under every
+// strategy, so only lambdas of the program are lazy.
+pub(crate) fn compare_strategies(program: &Expr, globals: &Env, version: LangVersion) {
+    let mut vars = HashMap::new();
+    let mut env = Some(globals);
+    while let Some(frame) = env {
+        for (name, value) in frame.vars().iter() {
+            let thunk = Thunk::Done(Value::Data(value.clone()));
+            vars.entry(name.to_string())
+                .or_insert_with(|| Rc::new(RefCell::new(thunk)));
+        }
+        env = frame.parent.as_deref();
+    }
+    let scope = Rc::new(Frame { vars, parent: None });
+
+    // Failures are part of the comparison, so don't print them as they happen
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let outcomes: Vec<Outcome> = Strategy::ALL
+        .into_iter()
+        .map(|strategy| {
+            let mut machine = Machine {
+                strategy,
+                steps: 0,
+                forces: 0,
+                depth: 0,
+                version,
+                eval: Evaluation::new(version),
+            };
+            let evaluated = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                machine.evaluate(program, &scope)
+            }));
+            let result = match evaluated {
+                Ok(value) => value.to_string(),
+                Err(payload) => match panic_error(&payload) {
+                    error @ (EvalError::FuelExhausted(_) | EvalError::LimitExceeded(_)) => {
+                        format!("no result: {}", error)
+                    }
+                    error => format!("error: {}", error),
+                },
+            };
+            Outcome {
+                strategy,
+                steps: machine.steps.min(STRATEGY_FUEL),
+                forces: machine.forces,
+                result,
+            }
+        })
+        .collect();
+    std::panic::set_hook(hook);
+
+    println!("{:<14} {:>8} {:>8}  result", "strategy", "steps", "forces");
+    for outcome in &outcomes {
+        println!(
+            "{:<14} {:>8} {:>8}  {}",
+            outcome.strategy.name(),
+            outcome.steps,
+            outcome.forces,
+            outcome.result
+        );
+    }
+    if outcomes
+        .iter()
+        .all(|outcome| outcome.result == outcomes[0].result)
+    {
+        println!("All strategies agree");
+    } else {
+        println!("The strategies disagree");
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+// An interned name. Identifiers get theirs when the program is parsed and
+// frames are keyed by them, so looking a variable up compares numbers
+// instead of strings. The symbol carries its name too, so reading the
+// name doesn't need the table.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+    id: u32,
+    name: &'static str,
+}
+
+// Every name interned so far. Values move between threads in `--parallel`
+// and the server, so there is one table for all of them, and names are
+// never removed from it.
+#[derive(Default)]
+struct Table {
+    ids: HashMap<&'static str, u32>,
+    names: Vec<&'static str>,
+}
+
+fn table() -> &'static RwLock<Table> {
+    static TABLE: OnceLock<RwLock<Table>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+impl Symbol {
+    // The symbol of a name, adding the name to the table the first time
+    pub fn intern(name: &str) -> Symbol {
+        if let Some(symbol) = Symbol::lookup(name) {
+            return symbol;
+        }
+        let mut table = table().write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have added it in the meantime
+        if let Some(&id) = table.ids.get(name) {
+            return Symbol {
+                id,
+                name: table.names[id as usize],
+            };
+        }
+        let name: &'static str = Box::leak(name.into());
+        let id = u32::try_from(table.names.len()).expect("Too many names");
+        table.names.push(name);
+        table.ids.insert(name, id);
+        Symbol { id, name }
+    }
+
+    // The symbol of a name, if it has been interned. A name that hasn't is
+    // bound nowhere.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        let table = table().read().unwrap_or_else(PoisonError::into_inner);
+        let (&name, &id) = table.ids.get_key_value(name)?;
+        Some(Symbol { id, name })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.name == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.name == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.name
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.name
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.name
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.name
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+// Shown like the string it stands for
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name)
+    }
+}
+
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// The canonical composition (NFC) of a string, so that "é" written as one
+// char and as "e" with a combining accent are the same string
+pub fn normalize(string: &str) -> String {
+    string.nfc().collect()
+}
+
+// Order strings by the chars of their NFC forms. Unlike `<`, canonically
+// equivalent strings are equal.
+pub fn compare(left: &str, right: &str) -> Ordering {
+    left.nfc().cmp(right.nfc())
+}
+
+// Whether two strings are equal when case is ignored. Uppercasing first
+// folds the chars that only have an uppercase of several chars, like "ß"
+// and "SS".
+pub fn eq_ignore_case(left: &str, right: &str) -> bool {
+    fold_case(left) == fold_case(right)
+}
+
+fn fold_case(string: &str) -> String {
+    let decomposed: String = string.nfd().collect();
+    normalize(&decomposed.to_uppercase().to_lowercase())
+}
+
+// The order of strings for sorting words, the same on every platform and
+// in every locale: first by their letters, ignoring accents and case, then
+// by accents, then lowercase before uppercase, and finally by chars, so
+// that only equal strings compare equal. Letters compare by code point, so
+// this is alphabetical for each script but not a language's dictionary
+// order.
+pub fn collate(left: &str, right: &str) -> Ordering {
+    CollationKey::new(left).cmp(&CollationKey::new(right))
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct CollationKey {
+    letters: String,
+    accents: String,
+    uppercase: Vec<bool>,
+    chars: String,
+}
+
+impl CollationKey {
+    fn new(string: &str) -> CollationKey {
+        let decomposed: String = string.nfd().collect();
+        let without_marks: String = decomposed
+            .chars()
+            .filter(|&char| !is_combining_mark(char))
+            .collect();
+        CollationKey {
+            letters: fold_case(&without_marks),
+            accents: fold_case(&decomposed),
+            uppercase: decomposed.chars().map(char::is_uppercase).collect(),
+            chars: normalize(string),
+        }
+    }
+}
+
+// The Levenshtein distance between two strings: the fewest chars to insert,
+// delete or replace to turn one into the other. Keeps only two rows of the
+// table, so it takes time proportional to the product of the lengths but
+// memory proportional to one of them.
+pub fn edit_distance(left: &str, right: &str) -> usize {
+    let left: Vec<char> = left.chars().collect();
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    let mut current = vec![0; right.len() + 1];
+    for (i, left_char) in left.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, right_char) in right.iter().enumerate() {
+            let replace = previous[j] + usize::from(left_char != right_char);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
+
+// A line of a diff: in both texts, only in the old one or only in the new
+#[derive(Debug, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// The shortest edit turning the old lines into the new ones, with Myers'
+// algorithm: for each number of edits d, the furthest point reached on
+// every diagonal, until one reaches the end. That takes time proportional
+// to the length of the texts times d, so similar texts diff quickly.
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    // The furthest x on diagonal k = x - y, at index k + offset
+    let mut furthest = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    'search: for d in 0..=n + m {
+        trace.push(furthest.clone());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| (k + offset) as usize;
+            let mut x = if k == -d || (k != d && furthest[at(k - 1)] < furthest[at(k + 1)]) {
+                furthest[at(k + 1)]
+            } else {
+                furthest[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from the end through the points each d came from
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let (d, k) = (d as isize, x - y);
+        let at = |k: isize| (k + offset) as usize;
+        let previous_k = if k == -d || (k != d && furthest[at(k - 1)] < furthest[at(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest[at(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            lines.push(DiffLine::Same(old[x as usize]));
+        }
+        if d > 0 {
+            if x == previous_x {
+                lines.push(DiffLine::Added(new[previous_y as usize]));
+            } else {
+                lines.push(DiffLine::Removed(old[previous_x as usize]));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    lines.reverse();
+    lines
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+// The drawing builtins, which move the turtle
+pub const BUILTINS: [&str; 5] = ["penDown", "penUp", "forward", "turn", "circle"];
+
+// Space around the drawing in the SVG file
+const MARGIN: f64 = 10.0;
+
+// Turtle graphics: a pen that starts at the origin facing up, with the pen
+// down, and leaves lines behind as it moves. Angles are in degrees,
+// counterclockwise, and y grows upwards as in mathematics.
+#[derive(Debug)]
+pub struct Turtle {
+    x: f64,
+    y: f64,
+    heading: f64,
+    pen_down: bool,
+    shapes: Vec<Shape>,
+}
+
+#[derive(Debug)]
+enum Shape {
+    Line((f64, f64), (f64, f64)),
+    Circle((f64, f64), f64),
+}
+
+impl Default for Turtle {
+    fn default() -> Turtle {
+        Turtle {
+            x: 0.0,
+            y: 0.0,
+            heading: 90.0,
+            pen_down: true,
+            shapes: Vec::new(),
+        }
+    }
+}
+
+impl Turtle {
+    pub fn pen(&mut self, down: bool) {
+        self.pen_down = down;
+    }
+
+    // Move `distance` ahead, backwards when negative
+    pub fn forward(&mut self, distance: f64) {
+        let from = (self.x, self.y);
+        let radians = self.heading.to_radians();
+        self.x += distance * radians.cos();
+        self.y += distance * radians.sin();
+        if self.pen_down {
+            self.shapes.push(Shape::Line(from, (self.x, self.y)));
+        }
+    }
+
+    // Turn left by `degrees`, right when negative
+    pub fn turn(&mut self, degrees: f64) {
+        self.heading = (self.heading + degrees).rem_euclid(360.0);
+    }
+
+    // Draw a circle around the turtle, which stays where it is
+    pub fn circle(&mut self, radius: f64) {
+        if self.pen_down {
+            self.shapes
+                .push(Shape::Circle((self.x, self.y), radius.abs()));
+        }
+    }
+
+    // The drawing as an SVG document just large enough to hold it
+    pub fn to_svg(&self) -> String {
+        let (mut left, mut bottom, mut right, mut top) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        let mut extend = |(x, y): (f64, f64), radius: f64| {
+            left = left.min(x - radius);
+            right = right.max(x + radius);
+            bottom = bottom.min(y - radius);
+            top = top.max(y + radius);
+        };
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line(from, to) => {
+                    extend(*from, 0.0);
+                    extend(*to, 0.0);
+                }
+                Shape::Circle(center, radius) => extend(*center, *radius),
+            }
+        }
+        let (width, height) = (right - left + 2.0 * MARGIN, top - bottom + 2.0 * MARGIN);
+        // SVG's y grows downwards
+        let point = |(x, y): (f64, f64)| (number(x), number(-y));
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">\n",
+            number(width),
+            number(height),
+            number(left - MARGIN),
+            number(-top - MARGIN),
+            number(width),
+            number(height)
+        );
+        svg.push_str("<g fill=\"none\" stroke=\"black\" stroke-width=\"1\">\n");
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line(from, to) => {
+                    let ((x1, y1), (x2, y2)) = (point(*from), point(*to));
+                    writeln!(
+                        svg,
+                        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                        x1, y1, x2, y2
+                    )
+                }
+                Shape::Circle(center, radius) => {
+                    let (cx, cy) = point(*center);
+                    writeln!(
+                        svg,
+                        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>",
+                        cx,
+                        cy,
+                        number(*radius)
+                    )
+                }
+            }
+            .unwrap();
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+}
+
+// A coordinate with at most two decimals, so that rounding errors of the
+// trigonometry don't show
+fn number(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    // No "-0"
+    let rounded = if rounded == 0.0 { 0.0 } else { rounded };
+    format!("{}", rounded)
+}
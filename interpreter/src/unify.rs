@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// A first-order term. Variables start with an uppercase letter or `_`,
+// everything else is a functor applied to zero or more arguments, as in
+// `f(X, g(a))`. Numbers are functors without arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Var(String),
+    Compound(String, Vec<Term>),
+}
+
+// A mapping from variables to terms. Substitutions produced by `unify` are
+// idempotent: no variable that is bound appears in the bound terms.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Substitution(BTreeMap<String, Term>);
+
+impl Term {
+    // Parse a term written as `f(X, g(a, Y))`
+    pub fn parse(text: &str) -> Result<Term, String> {
+        let mut parser = TermParser {
+            chars: text.chars().collect(),
+            position: 0,
+        };
+        let term = parser.term()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(format!("Unexpected text after term: {}", text));
+        }
+        Ok(term)
+    }
+
+    // Add the variables of the term to `variables` in order of appearance
+    pub fn collect_variables(&self, variables: &mut Vec<String>) {
+        match self {
+            Term::Var(name) if !variables.contains(name) => variables.push(name.clone()),
+            Term::Var(_) => {}
+            Term::Compound(_, arguments) => {
+                for argument in arguments {
+                    argument.collect_variables(variables);
+                }
+            }
+        }
+    }
+
+    // Append `suffix` to every variable name. The parser never produces
+    // names containing `#`, so such a suffix keeps clause variables apart
+    // from the query's.
+    pub fn rename(&self, suffix: &str) -> Term {
+        match self {
+            Term::Var(name) => Term::Var(format!("{}{}", name, suffix)),
+            Term::Compound(functor, arguments) => Term::Compound(
+                functor.clone(),
+                arguments
+                    .iter()
+                    .map(|argument| argument.rename(suffix))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Whether `var` appears anywhere in the term
+    pub fn occurs(&self, var: &str) -> bool {
+        match self {
+            Term::Var(name) => name == var,
+            Term::Compound(_, arguments) => arguments.iter().any(|argument| argument.occurs(var)),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Var(name) => write!(f, "{}", name),
+            Term::Compound(functor, arguments) if arguments.is_empty() => write!(f, "{}", functor),
+            Term::Compound(functor, arguments) => {
+                write!(f, "{}(", functor)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// Recursive descent parser for terms
+struct TermParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl TermParser {
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.position < self.chars.len()
+            && (self.chars[self.position].is_alphanumeric() || self.chars[self.position] == '_')
+        {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(format!("Expected a term at position {}", start));
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+        let first = name.chars().next().unwrap();
+        self.skip_whitespace();
+        if self.chars.get(self.position) != Some(&'(') {
+            if first.is_uppercase() || first == '_' {
+                return Ok(Term::Var(name));
+            }
+            return Ok(Term::Compound(name, Vec::new()));
+        }
+        if first.is_uppercase() || first == '_' {
+            return Err(format!("Variable {} can't have arguments", name));
+        }
+        self.position += 1;
+        let mut arguments = vec![self.term()?];
+        loop {
+            self.skip_whitespace();
+            match self.chars.get(self.position) {
+                Some(',') => {
+                    self.position += 1;
+                    arguments.push(self.term()?);
+                }
+                Some(')') => {
+                    self.position += 1;
+                    return Ok(Term::Compound(name, arguments));
+                }
+                _ => return Err(format!("Expected , or ) at position {}", self.position)),
+            }
+        }
+    }
+}
+
+impl Substitution {
+    // Replace the bound variables of a term
+    pub fn apply(&self, term: &Term) -> Term {
+        match term {
+            Term::Var(name) => match self.0.get(name) {
+                Some(bound) => bound.clone(),
+                None => term.clone(),
+            },
+            Term::Compound(functor, arguments) => Term::Compound(
+                functor.clone(),
+                arguments
+                    .iter()
+                    .map(|argument| self.apply(argument))
+                    .collect(),
+            ),
+        }
+    }
+
+    // The substitution that applies `self` first and then `other`
+    pub fn compose(&self, other: &Substitution) -> Substitution {
+        let mut composed: BTreeMap<String, Term> = self
+            .0
+            .iter()
+            .map(|(var, term)| (var.clone(), other.apply(term)))
+            .collect();
+        for (var, term) in &other.0 {
+            composed.entry(var.clone()).or_insert_with(|| term.clone());
+        }
+        Substitution(composed)
+    }
+
+    // The bindings of `variables` only
+    pub fn restrict(&self, variables: &[String]) -> Substitution {
+        Substitution(
+            variables
+                .iter()
+                .filter_map(|var| Some((var.clone(), self.0.get(var)?.clone())))
+                .collect(),
+        )
+    }
+
+    fn single(var: &str, term: Term) -> Substitution {
+        let mut map = BTreeMap::new();
+        map.insert(var.to_string(), term);
+        Substitution(map)
+    }
+}
+
+impl fmt::Display for Substitution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (var, term)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} = {}", var, term)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+// Find the most general unifier of two terms, with occurs check
+pub fn unify(left: &Term, right: &Term) -> Result<Substitution, String> {
+    match (left, right) {
+        (Term::Var(a), Term::Var(b)) if a == b => Ok(Substitution::default()),
+        (Term::Var(var), term) | (term, Term::Var(var)) => {
+            if term.occurs(var) {
+                return Err(format!("{} occurs in {}", var, term));
+            }
+            Ok(Substitution::single(var, term.clone()))
+        }
+        (Term::Compound(f, f_arguments), Term::Compound(g, g_arguments)) => {
+            if f != g || f_arguments.len() != g_arguments.len() {
+                return Err(format!("{} and {} don't match", left, right));
+            }
+            let mut substitution = Substitution::default();
+            for (a, b) in f_arguments.iter().zip(g_arguments) {
+                let unifier = unify(&substitution.apply(a), &substitution.apply(b))?;
+                substitution = substitution.compose(&unifier);
+            }
+            Ok(substitution)
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use crate::ast::{Expr, LangVersion, NodePath};
+use crate::builtins::apply_builtin;
+use crate::env::*;
+use crate::eval::*;
+use crate::symbol::Symbol;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// The bytecode backend of the REPL. Programs are compiled to instructions
+// for a stack machine, which keeps the evaluator's model of environments:
+// calling a closure makes an `Env` frame of its parameters inside the
+// closure's environment, variables the resolver turned into
+// `Expr::Local` are read from their slots, and closures are the same
+// `ResultValue::Lambda` values, so builtins like `map` call them with the
+// evaluator and the globals can be shared with it. Calls in tail position
+// reuse the frame, and calls don't nest on the Rust stack.
+//
+// A session compiles every input as a unit of its own, linked against the
+// globals and constants of the units before it: a global that is already
+// defined is read from its slot of the global frame, which a name keeps
+// when it is defined again, and one that isn't yet is looked up by name
+// when the code runs. Literals go into one constants table for the whole
+// session. The code of a lambda's body is compiled the first time one of
+// its closures is called, and kept by the address of the body, which the
+// closures share.
+pub(crate) struct Vm {
+    version: LangVersion,
+    globals: Env,
+    constants: Vec<ResultValue>,
+    // Where each literal is in `constants`
+    literals: HashMap<Literal, usize>,
+    // Compiled bodies, with the body they were compiled from to keep its
+    // address from being reused
+    bodies: HashMap<*const Expr, (Arc<[Expr]>, Rc<Code>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Literal {
+    Number(i64),
+    String(String),
+    Bool(bool),
+    Unit,
+}
+
+// One instruction of the stack machine
+#[derive(Debug, Clone)]
+enum Op {
+    // Push a value of the constants table
+    Constant(usize),
+    // Push the global in a slot of the global frame
+    Global(usize),
+    // Push the value of a name no scope bound when the code was compiled:
+    // a global defined since, or a builtin, which is printed like the
+    // evaluator does
+    Name(Symbol),
+    // Push the value in a slot of the frame a number of frames out
+    Local(usize, usize),
+    // Push a closure of the lambda in the current environment
+    Lambda(Rc<Lambda>),
+    // Leave the function on top as the result and jump if it is a number or
+    // a string, as the evaluator does before evaluating the arguments
+    Callee(usize),
+    // Call the function below the arguments, or make the call take the
+    // place of the current one
+    Call(usize),
+    TailCall(usize),
+    // Call the builtin of a name no scope bound when the code was
+    // compiled, or the global defined since
+    CallName(Symbol, usize),
+    Jump(usize),
+    JumpIfFalse(usize),
+    // Cond tests
+    IsZero,
+    Compare(Symbol),
+    ExpectBool,
+    // Collect values into an array, or into a dict with these keys
+    Array(usize),
+    Dict(Arc<[String]>),
+    // Open a letrec frame for the bindings, and bind the closures made in
+    // it under these names in a frame of their own, as the evaluator does
+    Letrec,
+    Bind(Arc<[Symbol]>),
+    // Open the frame of a block, and close the innermost frame
+    EnterBlock,
+    Leave,
+    Pop,
+    Fail(EvalError),
+    Return,
+}
+
+// What the closures of a lambda of the code are made of
+#[derive(Debug)]
+struct Lambda {
+    parameters: Vec<String>,
+    body: Arc<[Expr]>,
+    path: Option<NodePath>,
+}
+
+// The code of a unit or of the body of a lambda
+#[derive(Debug, Default)]
+struct Code {
+    ops: Vec<Op>,
+}
+
+// A call in progress: its code, where it is, its frames, innermost last,
+// and where its values start on the stack
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    envs: Vec<Env>,
+    base: usize,
+}
+
+impl Vm {
+    pub(crate) fn new(globals: &Env, version: LangVersion) -> Vm {
+        Vm {
+            version,
+            globals: globals.clone(),
+            constants: Vec::new(),
+            literals: HashMap::new(),
+            bodies: HashMap::new(),
+        }
+    }
+
+    // Compile a program, resolved with `resolve_locals`, and run it
+    pub(crate) fn run(&mut self, program: &Expr, eval: &mut Evaluation) -> ResultValue {
+        let mut code = Code::default();
+        self.compile(program, false, &mut code);
+        code.ops.push(Op::Return);
+        self.execute(Rc::new(code), eval)
+    }
+
+    fn constant(&mut self, literal: Literal) -> Op {
+        if let Some(&index) = self.literals.get(&literal) {
+            return Op::Constant(index);
+        }
+        self.constants.push(match &literal {
+            Literal::Number(number) => ResultValue::Number(*number),
+            Literal::String(string) => ResultValue::String(string.clone()),
+            Literal::Bool(value) => ResultValue::Bool(*value),
+            Literal::Unit => ResultValue::Unit,
+        });
+        self.literals.insert(literal, self.constants.len() - 1);
+        Op::Constant(self.constants.len() - 1)
+    }
+
+    // The slot of a global that is defined already
+    fn global(&self, name: Symbol) -> Option<usize> {
+        if name.starts_with(BUILTIN_PREFIX) {
+            return None;
+        }
+        self.globals.vars().position(name)
+    }
+
+    fn compile(&mut self, expr: &Expr, tail: bool, code: &mut Code) {
+        match expr {
+            Expr::Number(number) => code.ops.push(self.constant(Literal::Number(*number))),
+            Expr::String(string) => code
+                .ops
+                .push(self.constant(Literal::String(string.clone()))),
+            Expr::Bool(value) => code.ops.push(self.constant(Literal::Bool(*value))),
+            Expr::Local(_, depth, slot) => code.ops.push(Op::Local(*depth, *slot)),
+            Expr::Identifier(name) => match self.global(*name) {
+                Some(slot) => code.ops.push(Op::Global(slot)),
+                None => code.ops.push(Op::Name(*name)),
+            },
+            Expr::Lambda(parameters, body, site) => code.ops.push(Op::Lambda(Rc::new(Lambda {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                path: site.0.clone(),
+            }))),
+            Expr::Application(items) => {
+                let arguments = &items[1..];
+                if let Expr::Identifier(name) = &items[0] {
+                    if self.global(*name).is_none() {
+                        for argument in arguments {
+                            self.compile(argument, false, code);
+                        }
+                        code.ops.push(Op::CallName(*name, arguments.len()));
+                        return;
+                    }
+                }
+                self.compile(&items[0], false, code);
+                let callee = code.ops.len();
+                code.ops.push(Op::Callee(0));
+                for argument in arguments {
+                    self.compile(argument, false, code);
+                }
+                code.ops.push(if tail {
+                    Op::TailCall(arguments.len())
+                } else {
+                    Op::Call(arguments.len())
+                });
+                code.ops[callee] = Op::Callee(code.ops.len());
+            }
+            Expr::Cond(clauses) => {
+                let mut ends = Vec::new();
+                for (test, result) in clauses {
+                    self.compile_test(test, code);
+                    let next = code.ops.len();
+                    code.ops.push(Op::JumpIfFalse(0));
+                    self.compile(result, tail, code);
+                    ends.push(code.ops.len());
+                    code.ops.push(Op::Jump(0));
+                    code.ops[next] = Op::JumpIfFalse(code.ops.len());
+                }
+                code.ops
+                    .push(Op::Fail(EvalError::NoMatchingClause(expr.to_string())));
+                for end in ends {
+                    code.ops[end] = Op::Jump(code.ops.len());
+                }
+            }
+            Expr::Array(elements) => {
+                for element in elements {
+                    self.compile(element, false, code);
+                }
+                code.ops.push(Op::Array(elements.len()));
+            }
+            Expr::Dict(entries) => {
+                for (_, value) in entries {
+                    self.compile(value, false, code);
+                }
+                let keys = entries.iter().map(|(key, _)| key.clone()).collect();
+                code.ops.push(Op::Dict(keys));
+            }
+            Expr::Letrec(bindings, body) => {
+                if !self.version.letrec() {
+                    code.ops.push(Op::Fail(EvalError::Other(
+                        "letrec needs --lang-version cp4".to_string(),
+                    )));
+                    return;
+                }
+                code.ops.push(Op::Letrec);
+                for (name, value) in bindings {
+                    if !matches!(value, Expr::Lambda(_, _, _)) {
+                        code.ops.push(Op::Fail(EvalError::TypeError(format!(
+                            "letrec can only bind lambdas, but {} is bound to {}",
+                            name, value
+                        ))));
+                        return;
+                    }
+                    self.compile(value, false, code);
+                }
+                let names = bindings.iter().map(|(name, _)| Symbol::from(name.as_str()));
+                code.ops.push(Op::Bind(names.collect()));
+                self.compile_body(body, tail, code);
+                // Unless the body ended in a tail call, close the block and
+                // the letrec frame
+                code.ops.push(Op::Leave);
+                code.ops.push(Op::Leave);
+            }
+        }
+    }
+
+    // A body in the current frame: the first expression in checkpoint 3,
+    // and from checkpoint 4 on every expression in a block frame of its own
+    fn compile_body(&mut self, body: &[Expr], tail: bool, code: &mut Code) {
+        if !self.version.block_scoping() {
+            match body.first() {
+                Some(expr) => self.compile(expr, tail, code),
+                None => code.ops.push(self.constant(Literal::Unit)),
+            }
+            return;
+        }
+        code.ops.push(Op::EnterBlock);
+        let Some((last, rest)) = body.split_last() else {
+            code.ops.push(self.constant(Literal::Unit));
+            return;
+        };
+        for expr in rest {
+            self.compile(expr, false, code);
+            code.ops.push(Op::Pop);
+        }
+        self.compile(last, tail, code);
+    }
+
+    // A cond test, which leaves a boolean, as the evaluator decides them
+    fn compile_test(&mut self, test: &Expr, code: &mut Code) {
+        let invalid = || Op::Fail(EvalError::InvalidCondition(test.to_string()));
+        match test {
+            Expr::Identifier(name) | Expr::Local(name, _, _) => match name.as_str() {
+                "true" => code.ops.push(self.constant(Literal::Bool(true))),
+                "false" => code.ops.push(self.constant(Literal::Bool(false))),
+                _ => code.ops.push(invalid()),
+            },
+            Expr::Bool(value) => code.ops.push(self.constant(Literal::Bool(*value))),
+            Expr::Application(items) => {
+                let (Expr::Identifier(operator) | Expr::Local(operator, _, _)) = &items[0] else {
+                    code.ops.push(invalid());
+                    return;
+                };
+                let comparison = ["=", "<", "<=", ">", ">="].contains(&operator.as_str());
+                if operator.as_str() == "zero?" && items.len() >= 2 {
+                    self.compile(&items[1], false, code);
+                    code.ops.push(Op::IsZero);
+                } else if !comparison && self.version.boolean_calls() {
+                    self.compile(test, false, code);
+                    code.ops.push(Op::ExpectBool);
+                } else if !comparison {
+                    code.ops
+                        .push(Op::Fail(EvalError::UnknownOperator(operator.to_string())));
+                } else if items.len() >= 3 {
+                    self.compile(&items[1], false, code);
+                    self.compile(&items[2], false, code);
+                    code.ops.push(Op::Compare(*operator));
+                } else {
+                    code.ops.push(invalid());
+                }
+            }
+            _ => code.ops.push(invalid()),
+        }
+    }
+
+    // The code of a lambda's body, compiled on its first call
+    fn body(&mut self, body: &Arc<[Expr]>) -> Rc<Code> {
+        let key = body.as_ptr();
+        if let Some((_, code)) = self.bodies.get(&key) {
+            return code.clone();
+        }
+        let mut code = Code::default();
+        self.compile_body(body, true, &mut code);
+        code.ops.push(Op::Return);
+        let code = Rc::new(code);
+        self.bodies.insert(key, (body.clone(), code.clone()));
+        code
+    }
+
+    fn execute(&mut self, code: Rc<Code>, eval: &mut Evaluation) -> ResultValue {
+        let mut stack: Vec<ResultValue> = Vec::new();
+        let mut frames = vec![Frame {
+            code,
+            pc: 0,
+            envs: vec![self.globals.clone()],
+            base: 0,
+        }];
+        loop {
+            let frame = frames.last_mut().expect("A call in progress");
+            let op = frame.code.ops[frame.pc].clone();
+            frame.pc += 1;
+            eval.steps += 1;
+            if eval.fuel.is_some_and(|fuel| eval.steps > fuel) {
+                fail(EvalError::FuelExhausted(eval.steps - 1));
+            }
+            let env = frame.envs.last().expect("A frame to evaluate in");
+            match op {
+                Op::Constant(index) => stack.push(self.constants[index].clone()),
+                Op::Global(slot) => {
+                    let vars = self.globals.vars();
+                    stack.push(vars.slot(slot).expect("Globals keep their slots").clone());
+                }
+                Op::Name(name) => stack.push(match resolve(name, env) {
+                    Resolution::Variable(value) => value,
+                    Resolution::Builtin(_) => unbound_value(name, eval),
+                }),
+                Op::Local(depth, slot) => stack.push(env.slot(depth, slot)),
+                Op::Lambda(lambda) => {
+                    stack.push(ResultValue::Lambda(
+                        lambda.parameters.clone(),
+                        lambda.body.clone(),
+                        env.clone(),
+                        lambda.path.clone(),
+                    ));
+                }
+                Op::Callee(target) => {
+                    let function = stack.last().expect("A function to call");
+                    if let ResultValue::Number(_) | ResultValue::String(_) = function {
+                        frame.pc = target;
+                    }
+                }
+                Op::Call(count) | Op::TailCall(count) => {
+                    let arguments = stack.split_off(stack.len() - count);
+                    let function = stack.pop().expect("A function to call");
+                    let tail = matches!(op, Op::TailCall(_));
+                    self.call(function, arguments, tail, &mut stack, &mut frames, eval);
+                }
+                Op::CallName(name, count) => {
+                    let arguments = stack.split_off(stack.len() - count);
+                    match resolve(name, env) {
+                        Resolution::Variable(function) => {
+                            self.call(function, arguments, false, &mut stack, &mut frames, eval)
+                        }
+                        Resolution::Builtin(builtin) => {
+                            stack.push(apply_builtin(builtin, arguments, eval))
+                        }
+                    }
+                }
+                Op::Jump(target) => frame.pc = target,
+                Op::JumpIfFalse(target) => {
+                    if let Some(ResultValue::Bool(false)) = stack.pop() {
+                        frame.pc = target;
+                    }
+                }
+                Op::IsZero => {
+                    let zero = match stack.pop().expect("A value to test") {
+                        ResultValue::Number(number) => number == 0,
+                        ResultValue::Float(float) => float == 0.0,
+                        value => fail(EvalError::TypeError(format!(
+                            "Expected a number but got {}",
+                            value
+                        ))),
+                    };
+                    stack.push(ResultValue::Bool(zero));
+                }
+                Op::Compare(operator) => {
+                    let right = stack.pop().expect("A value to compare");
+                    let left = stack.pop().expect("A value to compare");
+                    let ordering = left.compare(&right).unwrap_or_else(|err| fail(err));
+                    stack.push(ResultValue::Bool(match operator.as_str() {
+                        "=" => ordering == Ordering::Equal,
+                        "<" => ordering == Ordering::Less,
+                        "<=" => ordering != Ordering::Greater,
+                        ">" => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    }));
+                }
+                Op::ExpectBool => match stack.last().expect("A value to test") {
+                    ResultValue::Bool(_) => {}
+                    value => fail(EvalError::TypeError(format!(
+                        "Expected a boolean but got {}",
+                        value
+                    ))),
+                },
+                Op::Array(count) => {
+                    let elements = stack.split_off(stack.len() - count);
+                    stack.push(ResultValue::Array(elements.into()));
+                }
+                Op::Dict(keys) => {
+                    let values = stack.split_off(stack.len() - keys.len());
+                    let mut entries: Vec<(String, ResultValue)> = Vec::new();
+                    for (key, value) in keys.iter().zip(values) {
+                        match entries.iter_mut().find(|(existing, _)| existing == key) {
+                            Some(entry) => entry.1 = value,
+                            None => entries.push((key.clone(), value)),
+                        }
+                    }
+                    stack.push(ResultValue::Dict(entries));
+                }
+                Op::Letrec => {
+                    let mut letrec =
+                        Env::frame(eval.new_frame_id(), Vars::default(), Some(env.clone()));
+                    letrec.recursive = true;
+                    frame.envs.push(letrec);
+                }
+                Op::Bind(names) => {
+                    let closures = stack.split_off(stack.len() - names.len());
+                    let letrec = frame.envs.pop().expect("A letrec frame");
+                    let outer = frame.envs.last().expect("A frame around the letrec");
+                    let vars = names.iter().copied().zip(closures).collect();
+                    let mut bound = Env::frame(letrec.id, vars, Some(outer.clone()));
+                    bound.recursive = true;
+                    frame.envs.push(bound.tie());
+                }
+                Op::EnterBlock => {
+                    let block = block_scope(env, eval);
+                    frame.envs.push(block);
+                }
+                Op::Leave => {
+                    frame.envs.pop();
+                }
+                Op::Pop => {
+                    stack.pop();
+                }
+                Op::Fail(error) => fail(error),
+                Op::Return => {
+                    let value = stack.pop().unwrap_or(ResultValue::Unit);
+                    let frame = frames.pop().expect("A call to return from");
+                    stack.truncate(frame.base);
+                    if frames.is_empty() {
+                        return value;
+                    }
+                    stack.push(value);
+                }
+            }
+        }
+    }
+
+    // Call a function with its arguments taken off the stack: a closure
+    // starts a call of its own, in place of the current one for a call in
+    // tail position, and anything else is applied by the evaluator
+    fn call(
+        &mut self,
+        function: ResultValue,
+        arguments: Vec<ResultValue>,
+        tail: bool,
+        stack: &mut Vec<ResultValue>,
+        frames: &mut Vec<Frame>,
+        eval: &mut Evaluation,
+    ) {
+        let ResultValue::Lambda(parameters, body, closure_env, _) = function else {
+            stack.push(apply_function(function, arguments, eval));
+            return;
+        };
+        if arguments.len() < parameters.len() {
+            fail(EvalError::ArityMismatch {
+                expected: parameters.len(),
+                got: arguments.len(),
+            });
+        }
+        let env = Env::frame(
+            eval.new_frame_id(),
+            parameters.iter().cloned().zip(arguments).collect(),
+            Some(closure_env.tie()),
+        );
+        let code = self.body(&body);
+        if tail {
+            let frame = frames.pop().expect("A call to replace");
+            stack.truncate(frame.base);
+        } else if frames.len() >= eval.max_depth {
+            fail(EvalError::LimitExceeded(format!(
+                "Too deeply nested: over {} calls in progress",
+                eval.max_depth
+            )));
+        }
+        frames.push(Frame {
+            code,
+            pc: 0,
+            envs: vec![env],
+            base: stack.len(),
+        });
+    }
+}
@@ -0,0 +1,168 @@
+use crate::date::{DateTime, ISO_FORMAT};
+use crate::eval::ResultValue;
+use serde_json::Value;
+
+// The state of the outside world for world-passing programs, which do I/O
+// by handing a world to a builtin and getting the next world back. Worlds
+// are plain values, so programs stay pure: evaluating the same calls on
+// the same world always gives the same results.
+pub struct World {
+    // Lines not read yet
+    input: Vec<String>,
+    // Lines printed so far
+    output: Vec<String>,
+    files: Vec<(String, String)>,
+    now: DateTime,
+}
+
+impl World {
+    // Read the initial world from a fixture: the lines of input, the files
+    // and the time, as in
+    //
+    //   {"input": ["a line"], "files": {"notes.txt": "..."},
+    //    "now": "2024-01-01T00:00:00"}
+    //
+    // Every field is optional.
+    pub fn load(path: &str) -> Result<World, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let fixture: Value = serde_json::from_str(&text)
+            .map_err(|err| format!("{} is not valid JSON: {}", path, err))?;
+        let bad = |field: &str| format!("The {} of the world in {} is invalid", field, path);
+        let input = match &fixture["input"] {
+            Value::Null => Vec::new(),
+            Value::Array(lines) => lines
+                .iter()
+                .map(|line| {
+                    line.as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| bad("input"))
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(bad("input")),
+        };
+        let files = match &fixture["files"] {
+            Value::Null => Vec::new(),
+            Value::Object(files) => files
+                .iter()
+                .map(|(name, contents)| match contents.as_str() {
+                    Some(contents) => Ok((name.clone(), contents.to_string())),
+                    None => Err(bad("files")),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(bad("files")),
+        };
+        let now = match &fixture["now"] {
+            Value::Null => DateTime(0),
+            Value::String(now) => DateTime::parse(now, ISO_FORMAT)?,
+            _ => return Err(bad("now")),
+        };
+        Ok(World {
+            input,
+            output: Vec::new(),
+            files,
+            now,
+        })
+    }
+
+    // Read a world back from the value a program passed
+    pub fn from_value(value: &ResultValue) -> Option<World> {
+        let ResultValue::Dict(entries) = value else {
+            return None;
+        };
+        let field = |name: &str| {
+            entries
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+        };
+        let strings = |value: &ResultValue| match value {
+            ResultValue::Array(lines) => lines
+                .iter()
+                .map(|line| match line {
+                    ResultValue::String(line) => Some(line.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        };
+        let ResultValue::Dict(files) = field("files")? else {
+            return None;
+        };
+        let ResultValue::DateTime(now) = field("now")? else {
+            return None;
+        };
+        Some(World {
+            input: strings(field("input")?)?,
+            output: strings(field("output")?)?,
+            files: files
+                .iter()
+                .map(|(name, contents)| match contents {
+                    ResultValue::String(contents) => Some((name.clone(), contents.clone())),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            now: *now,
+        })
+    }
+
+    pub fn to_value(&self) -> ResultValue {
+        let strings = |lines: &[String]| {
+            ResultValue::Array(
+                lines
+                    .iter()
+                    .map(|line| ResultValue::String(line.clone()))
+                    .collect(),
+            )
+        };
+        let files = self
+            .files
+            .iter()
+            .map(|(name, contents)| (name.clone(), ResultValue::String(contents.clone())))
+            .collect();
+        ResultValue::Dict(vec![
+            ("input".to_string(), strings(&self.input)),
+            ("output".to_string(), strings(&self.output)),
+            ("files".to_string(), ResultValue::Dict(files)),
+            ("now".to_string(), ResultValue::DateTime(self.now)),
+        ])
+    }
+
+    // The next line of input, or None at the end
+    pub fn read_line(&mut self) -> Option<String> {
+        if self.input.is_empty() {
+            return None;
+        }
+        Some(self.input.remove(0))
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub fn print(&mut self, line: String) {
+        self.output.push(line);
+    }
+
+    pub fn read_file(&self, name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(file, _)| file == name)
+            .map(|(_, contents)| contents.as_str())
+    }
+
+    pub fn write_file(&mut self, name: &str, contents: String) {
+        match self.files.iter_mut().find(|(file, _)| file == name) {
+            Some(file) => file.1 = contents,
+            None => self.files.push((name.to_string(), contents)),
+        }
+    }
+
+    // The time, which advances by a second every time it is read so that
+    // readings are distinct
+    pub fn now(&mut self) -> DateTime {
+        let now = self.now;
+        self.now = DateTime(now.0 + 1);
+        now
+    }
+}
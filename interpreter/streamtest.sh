@@ -0,0 +1,44 @@
+#!/bin/bash
+
+# Runs every program in tests/streams (s-expressions) with the flags in its
+# .args file and compares stdout and stderr separately with its .stdout and
+# .stderr files: what the program prints and its result go to stdout, and
+# everything the interpreter reports (errors, traces, reports) to stderr.
+# Set UPDATE_SNAPSHOTS=1 to record the outputs of new or changed programs.
+#
+# Set INTERPRETER to test another build.
+interpreter="${INTERPRETER:-cargo run --quiet --}"
+
+# ------------------------------------------------------------------
+
+failed=0
+stderr=$(mktemp)
+trap 'rm -f "$stderr"' EXIT
+for program in tests/streams/*.scm; do
+    name="${program%.*}"
+    args=$(cat "$name.args" 2>/dev/null)
+    # The location of a panic changes with every edit, so only its message
+    # is kept
+    out=$(RUST_BACKTRACE=0 $interpreter $args < "$program" 2> "$stderr")
+    err=$(sed -e '/^thread .* panicked at /d' -e '/^note: run with /d' "$stderr")
+    if [[ -n "$UPDATE_SNAPSHOTS" ]]; then
+	echo "$out" > "$name.stdout"
+	echo "$err" > "$name.stderr"
+	continue
+    fi
+    if [[ "$out" != "$(cat "$name.stdout" 2>/dev/null)" ]]; then
+	echo Stdout of "$program" changed:
+	diff <(echo "$out") "$name.stdout"
+	failed=1
+    fi
+    if [[ "$err" != "$(cat "$name.stderr" 2>/dev/null)" ]]; then
+	echo Stderr of "$program" changed:
+	diff <(echo "$err") "$name.stderr"
+	failed=1
+    fi
+done
+
+if [[ $failed != 0 ]]; then
+    exit 1
+fi
+echo "All stream tests passed!"
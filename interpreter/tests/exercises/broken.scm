@@ -0,0 +1,2 @@
+; Unbalanced parentheses don't parse, so this file is skipped
+(add 1 (mul 2 3)
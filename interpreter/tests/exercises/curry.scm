@@ -0,0 +1,4 @@
+; Curried addition and function composition
+(((lambda (compose) (compose (lambda (x) (add x 1)) (lambda (x) (mul x 2))))
+  (lambda (f g) (lambda (x) (f (g x)))))
+ 20)
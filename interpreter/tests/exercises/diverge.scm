@@ -0,0 +1,3 @@
+; A loop that never ends runs out of fuel
+(letrec ((loop (lambda (n) (loop (add n 1)))))
+  (loop 0))
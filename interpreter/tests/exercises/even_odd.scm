@@ -0,0 +1,4 @@
+; Mutual recursion
+(letrec ((even? (lambda (n) (cond ((= n 0) #t) (#t (odd? (sub n 1))))))
+         (odd? (lambda (n) (cond ((= n 0) #f) (#t (even? (sub n 1)))))))
+  (even? 17))
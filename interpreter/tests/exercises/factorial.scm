@@ -0,0 +1,3 @@
+; EOPL 1.x: factorial by structural recursion on numbers
+(letrec ((fact (lambda (n) (cond ((= n 0) 1) (#t (mul n (fact (sub n 1))))))))
+  (fact 10))
@@ -0,0 +1,3 @@
+; Tree recursion
+(letrec ((fib (lambda (n) (cond ((< n 2) n) (#t (add (fib (sub n 1)) (fib (sub n 2))))))))
+  (fib 10))
@@ -0,0 +1,2 @@
+; Calling a name nothing binds is an error
+(frobnicate 1 2)
@@ -0,0 +1,4 @@
+; The condition of a breakpoint can call the builtins the program can, here
+; `hit`, which the host adds
+((lambda (f) (add (f #f 1) (f #t 2)))
+ (lambda (b n) (mul n 10)))
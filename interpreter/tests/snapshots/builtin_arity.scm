@@ -0,0 +1,5 @@
+; Comparisons are procedures that return a boolean outside cond tests too,
+; and a builtin called with the wrong number of arguments fails before it
+; runs
+(array (print (array (< 1 2) (>= "a" "b") (zero? 0)))
+       (iterRange 1 2 3))
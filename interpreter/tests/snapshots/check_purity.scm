@@ -0,0 +1,6 @@
+; Under --check-purity the functions map and filter call when their
+; iterator is forced can't have effects, and the program doesn't run
+(letrec ((shout (lambda (x) (print x))))
+  (print "never printed")
+  (collect (map (lambda (x) (shout x) (mul x x)) (iterRange 1 4)) "array")
+  (collect (filter (lambda (x) (= x 2)) (iterRange 1 4)) "array"))
@@ -0,0 +1,4 @@
+; the first argument is used twice and the second never
+((lambda (twice ignore) (add twice twice))
+ ((lambda (n) (mul n n)) 7)
+ ((lambda (loop) (loop loop)) (lambda (loop) (loop loop))))
@@ -0,0 +1,6 @@
+; Edit distances for a spell checker, and a diff of expected and actual
+; output as a test runner would show it
+(array (editDistance "kitten" "sitting")
+       (editDistance "naïve" "naive")
+       (editDistance "" "abc")
+       (diffLines "1\n2\n3\n5" "1\n3\n4\n5"))
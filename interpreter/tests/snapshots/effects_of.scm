@@ -0,0 +1,13 @@
+; effectsOf infers the effects of a function from its code, through the
+; functions it calls, recursion included
+(letrec ((shout (lambda (x) (print x)))
+         (count (lambda (n) (cond ((= n 0) 0) (#t (add (shout n) (count (sub n 1)))))))
+         (log (lambda (name) (fact (array "seen" name)) (count 2)))
+         (twice (lambda (f x) (f (f x))))
+         (square (lambda (x) (mul x x))))
+  [(effectsOf square)
+   (effectsOf count)
+   (effectsOf log)
+   (effectsOf twice)
+   (effectsOf (lambda () (twice shout 1)))
+   (effectsOf (lambda () (nowUtc) (readBytes "x")))])
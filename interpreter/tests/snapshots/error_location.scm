@@ -0,0 +1,6 @@
+; the second call of f divides by zero
+((lambda (f)
+   (add (f 2)
+        (f 0)))
+ (lambda (n)
+   (div 10 n)))
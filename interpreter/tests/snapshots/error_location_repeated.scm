@@ -0,0 +1,4 @@
+; the two divisions are the same subtree, and only the second one divides by
+; zero
+[(letrec ((k (lambda () 1))) (div 10 (k)))
+ (letrec ((k (lambda () 0))) (div 10 (k)))]
@@ -0,0 +1,7 @@
+; scale reads factor and mul from where it was defined, not from the frame
+; of its caller, which binds both
+((lambda (factor)
+   ((lambda (scale)
+      ((lambda (factor mul) (add (scale 3) factor)) 100 0))
+    (lambda (n) (mul n factor))))
+ 2)
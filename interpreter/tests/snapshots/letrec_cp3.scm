@@ -0,0 +1 @@
+(letrec ((f (lambda (n) n))) (f 1))
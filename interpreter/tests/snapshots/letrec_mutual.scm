@@ -0,0 +1,5 @@
+; Mutually recursive definitions that refer to each other by name
+(letrec ((fact (lambda (n) (cond ((= n 0) 1) (#t (mul n (fact (sub n 1)))))))
+         (even? (lambda (n) (cond ((= n 0) #t) (#t (odd? (sub n 1))))))
+         (odd? (lambda (n) (cond ((= n 0) #f) (#t (even? (sub n 1)))))))
+  [(fact 5) (even? 10) (odd? 7)])
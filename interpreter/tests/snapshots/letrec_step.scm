@@ -0,0 +1,2 @@
+(letrec ((fact (lambda (n) (cond ((= n 0) 1) (#t (mul n (fact (sub n 1))))))))
+  (fact 1))
@@ -0,0 +1,3 @@
+; A loop that never ends is stopped after the steps --max-steps allows
+(letrec ((loop (lambda (n) (loop (add n 1)))))
+  (loop 0))
@@ -0,0 +1,2 @@
+; Programs as values: the AST of a program, and evaluating it with chosen variables
+[(parse "(add x 1)") (evalAst (parse "(add x 1)") {"x" 41}) (evalAst (parse "{\"Identifier\": \"y\"}") {"y" #t})]
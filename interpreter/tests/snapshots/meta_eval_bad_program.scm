@@ -0,0 +1 @@
+(evalAst (parse "(lambda") {})
@@ -0,0 +1,2 @@
+; By default a result that doesn't fit in a number is an error
+(add 9223372036854775807 1)
@@ -0,0 +1,8 @@
+; Results that don't fit in a number become floats, and arithmetic with
+; a float goes on with floats; numbers and floats compare by value
+(array (add 9223372036854775807 1)
+       (sub -9223372036854775807 10)
+       (mul 4611686018427387904 4 3)
+       (add 1 2)
+       (formatFloat (mul 9223372036854775807 2) 1)
+       (sort (array (add 9223372036854775807 1) 2 1)))
@@ -0,0 +1,5 @@
+; Results that don't fit in a number, and one that does
+(array (add 9223372036854775807 1)
+       (sub -9223372036854775807 10)
+       (mul 4611686018427387904 4 3)
+       (add 1 2))
@@ -0,0 +1,6 @@
+; a, b and c don't refer to each other and are evaluated at the same time,
+; then d and e. What they print still comes out in order.
+((lambda (a b c)
+   ((lambda (d e) (add a b c d e))
+    (mul a 2) (print (add c 100))))
+ (print 1) (sub 10 3) (print 3))
@@ -0,0 +1,5 @@
+; a and b don't refer to each other and are evaluated at the same time, and
+; each gets half of the steps left, so they run out like without --parallel
+((lambda (a b) (add a b))
+ (letrec ((count (lambda (n) (cond ((= n 0) 0) (#t (count (sub n 1))))))) (count 50))
+ (letrec ((count (lambda (n) (cond ((= n 0) 0) (#t (count (sub n 1))))))) (count 50)))
@@ -0,0 +1,5 @@
+; the accumulator-style array functions of the lists prelude
+[(length [4 5 6])
+ (length [])
+ (reverse [1 2 3])
+ (mapAcc (lambda (sum x) [(add sum x) (mul x x)]) 0 [1 2 3])]
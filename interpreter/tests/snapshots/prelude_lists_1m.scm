@@ -0,0 +1,8 @@
+; the lists prelude on a million elements: the loops make tail calls, read
+; the array by index and append to flat arrays, so this runs in constant
+; stack and linear time
+((lambda (xs)
+   [(length xs)
+    (get (reverse xs) 0)
+    (get (get (mapAcc (lambda (sum x) [(add sum x) (mul x 2)]) 0 xs) 1) -1)])
+ (collect (iterRange 0 1000000) "array"))
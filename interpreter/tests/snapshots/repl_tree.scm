@@ -0,0 +1,7 @@
+; The REPL evaluates each line with the definitions of the lines before it,
+; and a line that fails only reports its error
+(define double (lambda (n) (add n n)))
+(double 21)
+(div 1 0)
+(define n (double 5))
+(sub n 1)
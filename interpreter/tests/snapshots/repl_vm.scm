@@ -0,0 +1,19 @@
+; The REPL compiles each line for the VM, linked against the definitions
+; and constants of the lines before it
+(define square (lambda (n) (mul n n)))
+(square 7)
+(define count (lambda (n acc) (cond ((= n 0) acc) (#t (count (sub n 1) (add acc 1))))))
+(count 100000 0)
+(collect (map square (iterRange 1 5)) "array")
+(letrec ((even? (lambda (n) (cond ((zero? n) #t) (#t (odd? (sub n 1)))))) (odd? (lambda (n) (cond ((zero? n) #f) (#t (even? (sub n 1))))))) [(even? 10) (odd? 7)])
+(define later (lambda (n) (helper n)))
+(define helper (lambda (n) (add n 1)))
+(later 1)
+(define square (lambda (n) (add n n)))
+(square 7)
+(cond ((= x 1) 1))
+(nope 1 2)
+unbound
+(define twice (lambda (f) (lambda (v) (f (f v)))))
+((twice square) 5)
+{"a" (square 3) "b" "text"}
@@ -0,0 +1,5 @@
+; Each variable is found by how many frames out its binder is and its
+; slot there; globals and builtins are still looked up by name
+((lambda (x y)
+   ((lambda (z x) (add x (mul y z))) 3 4))
+ 1 2)
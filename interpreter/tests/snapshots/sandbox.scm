@@ -0,0 +1,5 @@
+; In the sandbox print returns its argument without printing it, and the
+; builtins that reach outside the evaluation are unknown
+(array (print "not printed")
+       (add 2 3)
+       (readBytes "/etc/passwd"))
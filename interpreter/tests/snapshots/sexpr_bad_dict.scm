@@ -0,0 +1,2 @@
+; a dict needs string keys
+{"a" 1 b 2}
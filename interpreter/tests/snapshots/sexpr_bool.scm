@@ -0,0 +1,2 @@
+; #t and #f are the boolean literals
+(cond ((= (add 1 1) 3) [#f]) (#t [#t #f]))
@@ -0,0 +1,5 @@
+; factorial with a self-applied lambda
+((lambda (fact) (fact fact 5))
+ (lambda (self n)
+   (cond ((= n 0) 1)
+         (true (mul n (self self (sub n 1)))))))
@@ -0,0 +1,12 @@
+; Strings compared as Unicode text: "é" as one char and as "e" with a
+; combining accent are the same, case can be ignored, and collation sorts
+; by letters before accents and case
+(array (strCmp "café" "café")
+       (bytesLen (bytesFromString (normalize "café")))
+       (strCmp "apple" "Banana")
+       (strEqIgnoreCase "STRASSE" "straße")
+       (strEqIgnoreCase "ÉTÉ" "été")
+       (collate "éclair" "eclair")
+       (sort (array "zebra" "Éclair" "apple" "Apple" "eclair" "éclair"))
+       (sortCollated (array "zebra" "Éclair" "apple" "Apple" "eclair" "éclair")))
+
@@ -0,0 +1,11 @@
+; A square with a circle at each corner, written to stdout as SVG
+((lambda (side)
+   ((lambda (corner) (corner corner 4))
+    (lambda (self n)
+      (cond ((= n 0) (penUp))
+            (#t ((lambda ()
+                   (forward side)
+                   (circle 5)
+                   (turn 90)
+                   (self self (sub n 1)))))))))
+ 40)
@@ -0,0 +1,12 @@
+; read two lines and a file, threading the world through every call
+((lambda (first)
+   ((lambda (last)
+      ((lambda (greeting)
+         ((lambda (stamped)
+            (writeFileW
+             (printW (printW (get stamped 1) (get greeting 0)) (get first 0))
+             "signed.txt" (get last 0)))
+          (nowW (get greeting 1))))
+       (readFileW (get last 1) "greeting.txt")))
+    (readLineW (get first 1))))
+ (readLineW world))
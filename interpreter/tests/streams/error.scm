@@ -0,0 +1,2 @@
+; What was printed before the error stays on stdout
+((lambda (n) (print n) (div n 0)) 7)
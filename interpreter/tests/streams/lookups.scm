@@ -0,0 +1,2 @@
+; The output and the result on stdout, the report on stderr
+((lambda (n) (print n) (mul n 2)) 21)
@@ -0,0 +1,2 @@
+; Only what the program prints, without the result
+((lambda (x) (print x) (print (add x 1))) 1)